@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use nvml_wrapper::{struct_wrappers::device::AccountingStats as NvmlAccountingStats, Device};
+#[cfg(feature = "render")]
+use tabled::Tabled;
+
+use crate::json::Json;
+use crate::schema;
+use crate::units::Bytes;
+
+#[cfg_attr(feature = "render", derive(Tabled))]
+#[cfg_attr(feature = "render", tabled(rename_all = "PascalCase"))]
+pub struct AccountingStats {
+    pub gpu: u32,
+    pub pid: u32,
+    #[cfg_attr(
+        feature = "render",
+        tabled(display_with("Self::display_utilizations", self))
+    )]
+    pub utilizations: (Option<u32>, Option<u32>), // (gpu, memory), lifetime averages
+    #[cfg_attr(
+        feature = "render",
+        tabled(display_with("Self::display_max_memory", self))
+    )]
+    pub max_memory: Option<Bytes>,
+    #[cfg_attr(feature = "render", tabled(display_with("Self::display_time", self)))]
+    pub time: Duration, // that the process's compute context was active
+}
+
+impl AccountingStats {
+    /// Returns stats for processes that have already exited on this device.
+    /// Empty if accounting mode is disabled or unsupported.
+    pub fn from_nvml_device(device: &Device) -> Vec<Self> {
+        if !device.is_accounting_enabled().unwrap_or(false) {
+            return vec![];
+        }
+        let gpu = device.index().unwrap_or_default();
+        let pids = device.accounting_pids().unwrap_or_default();
+
+        pids.iter()
+            .filter_map(|pid| {
+                // the process may have been evicted from the accounting buffer
+                // since we listed the pids, so just skip it
+                let stats = device.accounting_stats_for(*pid).ok()?;
+                if stats.is_running {
+                    return None;
+                }
+                Some(Self {
+                    gpu,
+                    pid: *pid,
+                    utilizations: (stats.gpu_utilization, stats.memory_utilization),
+                    max_memory: stats.max_memory_usage.map(Bytes),
+                    // NVML counts it in ms
+                    time: Duration::from_millis(stats.time),
+                })
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "render")]
+    fn display_utilizations(&self) -> String {
+        let (gpu_utilization, memory_utilization) = self.utilizations;
+        format!(
+            "GPU {:>3} VRAM {:>3}",
+            display_pct(gpu_utilization),
+            display_pct(memory_utilization)
+        )
+    }
+
+    #[cfg(feature = "render")]
+    fn display_max_memory(&self) -> String {
+        match self.max_memory {
+            Some(bytes) => bytes.gib(),
+            None => "N/A".to_string(),
+        }
+    }
+
+    #[cfg(feature = "render")]
+    fn display_time(&self) -> String {
+        let secs = self.time.as_secs();
+        format!(
+            "{:02}:{:02}:{:02}",
+            secs / 3600,
+            (secs % 3600) / 60,
+            secs % 60
+        )
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("gpu", self.gpu.into()),
+            ("pid", self.pid.into()),
+            ("gpu_utilization", self.utilizations.0.into()),
+            ("memory_utilization", self.utilizations.1.into()),
+            ("max_memory_bytes", self.max_memory.into()),
+            ("time_ms", (self.time.as_millis() as u64).into()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        schema::object(vec![
+            ("gpu", schema::integer()),
+            ("pid", schema::integer()),
+            ("gpu_utilization", schema::nullable(schema::integer())),
+            ("memory_utilization", schema::nullable(schema::integer())),
+            ("max_memory_bytes", schema::nullable(schema::integer())),
+            ("time_ms", schema::integer()),
+        ])
+    }
+}
+
+/// Returns lifetime stats for the given running processes on this device.
+/// Empty if accounting mode is disabled or unsupported.
+pub fn running_process_accounting(
+    device: &Device,
+    pids: &[u32],
+) -> Vec<(u32, NvmlAccountingStats)> {
+    if !device.is_accounting_enabled().unwrap_or(false) {
+        return vec![];
+    }
+    pids.iter()
+        .filter_map(|pid| Some((*pid, device.accounting_stats_for(*pid).ok()?)))
+        .collect()
+}
+
+pub fn display_pct(pct: Option<u32>) -> String {
+    match pct {
+        Some(pct) => format!("{}%", pct),
+        None => "N/A".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_times_in_ms_in_json() {
+        let stats = AccountingStats {
+            gpu: 0,
+            pid: 999,
+            utilizations: (Some(80), None),
+            max_memory: None,
+            time: Duration::from_millis(3_723_500),
+        };
+        assert!(stats
+            .to_json()
+            .to_string()
+            .ends_with(r#""time_ms":3723500}"#));
+        #[cfg(feature = "render")]
+        assert_eq!(stats.display_time(), "01:02:03");
+    }
+}
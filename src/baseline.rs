@@ -0,0 +1,263 @@
+use crate::json::Json;
+use crate::schema;
+use crate::{GPUStats, Machine};
+
+// current clocks vary with load, so only drops of more than this (%) are reported
+const CLOCK_TOLERANCE: u32 = 10;
+// and temperatures with the room, so only rises of more than this (°C)
+const TEMP_TOLERANCE: u32 = 10;
+
+/// The performance-relevant stats of one GPU, to compare against later.
+#[derive(Debug, PartialEq)]
+pub struct GpuBaseline {
+    pub idx: u32,
+    pub name: String,
+    pub clocks: ((u32, u32), (u32, u32)), // ((SM, memory), (max SM, max memory)) in MHz
+    pub temp: u32,
+    pub capability: String, // e.g. "8.0"
+}
+
+/// A snapshot of every GPU for `--export-baseline`, e.g. before a driver update,
+/// that `--compare-baseline` checks the machine against afterwards.
+#[derive(Debug, PartialEq)]
+pub struct Baseline {
+    pub driver_version: String,
+    pub gpus: Vec<GpuBaseline>,
+}
+
+impl GpuBaseline {
+    pub fn from_gpu(gpu: &GPUStats) -> Self {
+        Self {
+            idx: gpu.idx,
+            name: gpu.name.clone(),
+            clocks: gpu.clocks,
+            temp: gpu.temp,
+            capability: gpu.display_capability(),
+        }
+    }
+
+    fn to_json(&self) -> Json {
+        let ((sm_clock, memory_clock), (max_sm_clock, max_memory_clock)) = self.clocks;
+        Json::object(vec![
+            ("idx", self.idx.into()),
+            ("name", (&self.name).into()),
+            ("sm_clock_mhz", sm_clock.into()),
+            ("memory_clock_mhz", memory_clock.into()),
+            ("max_sm_clock_mhz", max_sm_clock.into()),
+            ("max_memory_clock_mhz", max_memory_clock.into()),
+            ("temp", self.temp.into()),
+            ("capability", (&self.capability).into()),
+        ])
+    }
+
+    fn json_schema() -> Json {
+        schema::object(vec![
+            ("idx", schema::integer()),
+            ("name", schema::string()),
+            ("sm_clock_mhz", schema::integer()),
+            ("memory_clock_mhz", schema::integer()),
+            ("max_sm_clock_mhz", schema::integer()),
+            ("max_memory_clock_mhz", schema::integer()),
+            ("temp", schema::integer()),
+            ("capability", schema::string()),
+        ])
+    }
+
+    fn from_json(json: &Json) -> Result<Self, String> {
+        let mhz = |key: &str| u32_field(json, key);
+        Ok(Self {
+            idx: u32_field(json, "idx")?,
+            name: string_field(json, "name")?,
+            clocks: (
+                (mhz("sm_clock_mhz")?, mhz("memory_clock_mhz")?),
+                (mhz("max_sm_clock_mhz")?, mhz("max_memory_clock_mhz")?),
+            ),
+            temp: u32_field(json, "temp")?,
+            capability: string_field(json, "capability")?,
+        })
+    }
+
+    /// How `current` differs from this, e.g. "max clock reduced from 1695MHz to 1410MHz".
+    fn deviations(&self, current: &GpuBaseline) -> Vec<String> {
+        if current.name != self.name {
+            // a different GPU, so its clocks aren't comparable
+            return vec![format!("changed from {} to {}", self.name, current.name)];
+        }
+        let ((sm_clock, memory_clock), (max_sm_clock, max_memory_clock)) = self.clocks;
+        let ((current_sm_clock, current_memory_clock), (current_max_sm, current_max_memory)) =
+            current.clocks;
+        let mut deviations = vec![];
+        let mut changed = |what: &str, before: u32, after: u32, unit: &str| {
+            let direction = if after < before {
+                "reduced"
+            } else {
+                "increased"
+            };
+            deviations.push(format!(
+                "{} {} from {}{} to {}{}",
+                what, direction, before, unit, after, unit
+            ));
+        };
+        if current_max_sm != max_sm_clock {
+            changed("max clock", max_sm_clock, current_max_sm, "MHz");
+        }
+        if current_max_memory != max_memory_clock {
+            changed(
+                "max memory clock",
+                max_memory_clock,
+                current_max_memory,
+                "MHz",
+            );
+        }
+        if dropped(sm_clock, current_sm_clock) {
+            changed("clock", sm_clock, current_sm_clock, "MHz");
+        }
+        if dropped(memory_clock, current_memory_clock) {
+            changed("memory clock", memory_clock, current_memory_clock, "MHz");
+        }
+        if current.temp > self.temp + TEMP_TOLERANCE {
+            changed("temperature", self.temp, current.temp, "°C");
+        }
+        if current.capability != self.capability {
+            deviations.push(format!(
+                "compute capability changed from {} to {}",
+                self.capability, current.capability
+            ));
+        }
+        deviations
+    }
+}
+
+impl Baseline {
+    pub fn from_machine(machine: &Machine) -> Self {
+        Self {
+            driver_version: machine.driver.driver_version.clone(),
+            gpus: machine.gpus.iter().map(GpuBaseline::from_gpu).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("driver_version", (&self.driver_version).into()),
+            (
+                "gpus",
+                Json::Array(self.gpus.iter().map(GpuBaseline::to_json).collect()),
+            ),
+        ])
+    }
+
+    /// The JSON Schema (draft 7) of `to_json`, i.e. of `--export-baseline` files.
+    pub fn json_schema() -> Json {
+        schema::document(
+            "bmon --export-baseline",
+            schema::object(vec![
+                ("driver_version", schema::string()),
+                ("gpus", schema::array(GpuBaseline::json_schema())),
+            ]),
+        )
+    }
+
+    /// Reads back a baseline written by `to_json`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let json = Json::parse(text)?;
+        let gpus = json["gpus"]
+            .as_array()
+            .ok_or("missing gpus")?
+            .iter()
+            .map(GpuBaseline::from_json)
+            .collect::<Result<Vec<GpuBaseline>, String>>()?;
+        Ok(Self {
+            driver_version: string_field(&json, "driver_version")?,
+            gpus,
+        })
+    }
+
+    /// One line per deviation of `current` from this, prefixed with the GPU,
+    /// e.g. "GPU 0: max clock reduced from 1695MHz to 1410MHz". Empty if nothing changed.
+    pub fn compare(&self, current: &Baseline) -> Vec<String> {
+        let mut deviations = vec![];
+        for gpu in &self.gpus {
+            match current.gpus.iter().find(|current| current.idx == gpu.idx) {
+                Some(current) => deviations.extend(
+                    gpu.deviations(current)
+                        .into_iter()
+                        .map(|deviation| format!("GPU {}: {}", gpu.idx, deviation)),
+                ),
+                None => deviations.push(format!("GPU {}: missing (was {})", gpu.idx, gpu.name)),
+            }
+        }
+        for gpu in &current.gpus {
+            if !self.gpus.iter().any(|baseline| baseline.idx == gpu.idx) {
+                deviations.push(format!(
+                    "GPU {}: not in the baseline ({})",
+                    gpu.idx, gpu.name
+                ));
+            }
+        }
+        deviations
+    }
+}
+
+// whether a clock fell by more than `CLOCK_TOLERANCE` percent
+fn dropped(before: u32, after: u32) -> bool {
+    after as u64 * 100 < before as u64 * (100 - CLOCK_TOLERANCE) as u64
+}
+
+fn u32_field(json: &Json, key: &str) -> Result<u32, String> {
+    json[key]
+        .as_i64()
+        .and_then(|value| u32::try_from(value).ok())
+        .ok_or_else(|| format!("missing or invalid {}", key))
+}
+
+fn string_field(json: &Json, key: &str) -> Result<String, String> {
+    json[key]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| format!("missing or invalid {}", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::mock::MockDevice;
+
+    #[test]
+    fn reports_deviations_from_a_saved_baseline() {
+        let mut before = MockDevice::new(0);
+        before.max_clocks = Some((1695, 1593));
+        before.clocks = Some((1695, 1593));
+        let machine = Machine::with_gpus(vec![
+            GPUStats::from_device(&before),
+            GPUStats::from_device(&MockDevice::new(1)),
+        ]);
+        let baseline = Baseline::from_machine(&machine);
+        let saved = Baseline::parse(&baseline.to_json().to_string()).unwrap();
+        assert_eq!(saved, baseline);
+        assert!(saved.compare(&baseline).is_empty());
+        assert_eq!(
+            schema::violations(&Baseline::json_schema(), &baseline.to_json(), "baseline"),
+            Vec::<String>::new()
+        );
+
+        let mut after = MockDevice::new(0);
+        after.max_clocks = Some((1410, 1593));
+        // within the tolerance for current clocks
+        after.clocks = Some((1600, 1593));
+        after.temperature = Some(60);
+        let machine = Machine::with_gpus(vec![GPUStats::from_device(&after)]);
+        assert_eq!(
+            saved.compare(&Baseline::from_machine(&machine)),
+            vec![
+                "GPU 0: max clock reduced from 1695MHz to 1410MHz",
+                "GPU 0: temperature increased from 35°C to 60°C",
+                "GPU 1: missing (was NVIDIA A100-SXM4-80GB)",
+            ]
+        );
+
+        assert_eq!(
+            Baseline::parse(r#"{"driver_version":"535.104.05"}"#),
+            Err("missing gpus".to_string())
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use std::fs;
+use std::path::Path;
+
+use crate::json::Json;
+use crate::schema;
+
+// cgroup v1 reports "no limit" as a huge page-aligned number rather than -1
+const V1_UNLIMITED_BYTES: u64 = 1 << 62;
+
+/// CPU and memory limits of the cgroup bmon runs in, e.g. inside a container.
+/// Each limit is None if it isn't set, in which case the host values apply.
+#[derive(Default)]
+pub struct CgroupLimits {
+    pub cpus: Option<f32>,
+    pub memory: Option<u64>,      // in bytes
+    pub memory_used: Option<u64>, // in bytes, only read if memory is limited
+}
+
+impl CgroupLimits {
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("cpu_limit", self.cpus.into()),
+            ("memory_limit_bytes", self.memory.into()),
+            ("memory_used_bytes", self.memory_used.into()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        schema::object(vec![
+            ("cpu_limit", schema::nullable(schema::number())),
+            ("memory_limit_bytes", schema::nullable(schema::integer())),
+            ("memory_used_bytes", schema::nullable(schema::integer())),
+        ])
+    }
+}
+
+pub fn get_cgroup_limits() -> CgroupLimits {
+    cgroup_limits_from(Path::new("/sys/fs/cgroup"))
+}
+
+/// Reads the limits under a cgroup mount, trying the unified (v2)
+/// hierarchy first and falling back to the v1 controllers.
+fn cgroup_limits_from(root: &Path) -> CgroupLimits {
+    if root.join("cgroup.controllers").exists() {
+        // v2, e.g. cpu.max = "1600000 100000" or "max 100000"
+        let cpus = read(&root.join("cpu.max")).and_then(|cpu_max| {
+            let mut words = cpu_max.split_whitespace();
+            let quota = words.next()?.parse::<f32>().ok()?;
+            let period = words.next()?.parse::<f32>().ok()?;
+            Some(quota / period)
+        });
+        let memory = read(&root.join("memory.max")).and_then(|max| max.parse::<u64>().ok());
+        let memory_used = memory
+            .and_then(|_| read(&root.join("memory.current")))
+            .and_then(|current| current.parse::<u64>().ok());
+        CgroupLimits {
+            cpus,
+            memory,
+            memory_used,
+        }
+    } else {
+        // v1, the quota is -1 if unlimited
+        let quota = read(&root.join("cpu/cpu.cfs_quota_us")).and_then(|q| q.parse::<f32>().ok());
+        let period = read(&root.join("cpu/cpu.cfs_period_us")).and_then(|p| p.parse::<f32>().ok());
+        let cpus = match (quota, period) {
+            (Some(quota), Some(period)) if quota > 0.0 && period > 0.0 => Some(quota / period),
+            _ => None,
+        };
+        let memory = read(&root.join("memory/memory.limit_in_bytes"))
+            .and_then(|limit| limit.parse::<u64>().ok())
+            .filter(|limit| *limit < V1_UNLIMITED_BYTES);
+        let memory_used = memory
+            .and_then(|_| read(&root.join("memory/memory.usage_in_bytes")))
+            .and_then(|usage| usage.parse::<u64>().ok());
+        CgroupLimits {
+            cpus,
+            memory,
+            memory_used,
+        }
+    }
+}
+
+fn read(path: &Path) -> Option<String> {
+    Some(fs::read_to_string(path).ok()?.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fake /sys/fs/cgroup tree, removed on drop.
+    struct MockCgroup {
+        root: PathBuf,
+    }
+
+    impl MockCgroup {
+        fn new(test_name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "bmon-cgroup-{}-{}",
+                std::process::id(),
+                test_name
+            ));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            Self { root }
+        }
+
+        fn file(&self, path: &str, contents: &str) {
+            let path = self.root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, format!("{}\n", contents)).unwrap();
+        }
+    }
+
+    impl Drop for MockCgroup {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn reads_v2_limits() {
+        let cgroup = MockCgroup::new("v2");
+        cgroup.file("cgroup.controllers", "cpuset cpu io memory");
+        cgroup.file("cpu.max", "1600000 100000");
+        cgroup.file("memory.max", "68719476736");
+        cgroup.file("memory.current", "44023414784");
+
+        let limits = cgroup_limits_from(&cgroup.root);
+        assert_eq!(limits.cpus, Some(16.0));
+        assert_eq!(limits.memory, Some(68719476736));
+        assert_eq!(limits.memory_used, Some(44023414784));
+    }
+
+    #[test]
+    fn v2_max_is_unlimited() {
+        let cgroup = MockCgroup::new("v2-max");
+        cgroup.file("cgroup.controllers", "cpu memory");
+        cgroup.file("cpu.max", "max 100000");
+        cgroup.file("memory.max", "max");
+        cgroup.file("memory.current", "44023414784");
+
+        let limits = cgroup_limits_from(&cgroup.root);
+        assert_eq!(limits.cpus, None);
+        assert_eq!(limits.memory, None);
+        assert_eq!(limits.memory_used, None);
+    }
+
+    #[test]
+    fn reads_v1_limits() {
+        let cgroup = MockCgroup::new("v1");
+        cgroup.file("cpu/cpu.cfs_quota_us", "150000");
+        cgroup.file("cpu/cpu.cfs_period_us", "100000");
+        cgroup.file("memory/memory.limit_in_bytes", "8589934592");
+        cgroup.file("memory/memory.usage_in_bytes", "1073741824");
+
+        let limits = cgroup_limits_from(&cgroup.root);
+        assert_eq!(limits.cpus, Some(1.5));
+        assert_eq!(limits.memory, Some(8589934592));
+        assert_eq!(limits.memory_used, Some(1073741824));
+    }
+
+    #[test]
+    fn v1_defaults_are_unlimited() {
+        let cgroup = MockCgroup::new("v1-unlimited");
+        cgroup.file("cpu/cpu.cfs_quota_us", "-1");
+        cgroup.file("cpu/cpu.cfs_period_us", "100000");
+        cgroup.file("memory/memory.limit_in_bytes", "9223372036854771712");
+
+        let limits = cgroup_limits_from(&cgroup.root);
+        assert_eq!(limits.cpus, None);
+        assert_eq!(limits.memory, None);
+    }
+}
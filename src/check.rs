@@ -0,0 +1,135 @@
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::Nvml;
+use std::fs;
+
+use crate::color::{self, Color};
+use crate::device::GpuDevice;
+
+/// The outcome of one step of `bmon check-nvml`.
+pub struct Check {
+    pub name: String,
+    pub result: Result<(), String>, // the reason on failure
+}
+
+impl Check {
+    fn new<T>(name: impl Into<String>, result: Result<T, impl ToString>) -> Self {
+        Self {
+            name: name.into(),
+            result: result.map(|_| ()).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn pass(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            result: Ok(()),
+        }
+    }
+
+    fn fail(name: impl Into<String>, reason: impl ToString) -> Self {
+        Self {
+            name: name.into(),
+            result: Err(reason.to_string()),
+        }
+    }
+
+    pub fn passed(&self) -> bool {
+        self.result.is_ok()
+    }
+
+    /// e.g. "GPU 0 temperature        ✓ PASS" or "GPU 1 power              ✗ FAIL: Not Supported"
+    pub fn format(&self) -> String {
+        let status = match &self.result {
+            Ok(()) => color::paint("✓ PASS", Color::Green),
+            Err(reason) => color::paint(&format!("✗ FAIL: {}", reason), Color::Red),
+        };
+        format!("{:<24} {}", self.name, status)
+    }
+}
+
+/// Runs every check in order, skipping those that depend on an earlier
+/// failure, e.g. the GPU checks if NVML can't be initialised.
+pub fn check_nvml() -> Vec<Check> {
+    let mut checks = vec![Check::new(
+        "NVIDIA driver installed",
+        fs::read_to_string("/proc/driver/nvidia/version")
+            .map_err(|e| format!("can't read /proc/driver/nvidia/version ({})", e)),
+    )];
+
+    let nvml = match Nvml::init() {
+        Ok(nvml) => nvml,
+        Err(e) => {
+            checks.push(Check::fail("NVML init", init_reason(e)));
+            return checks;
+        }
+    };
+    checks.push(Check::pass("NVML init"));
+
+    let num_gpus = match nvml.device_count() {
+        Ok(num_gpus) => num_gpus,
+        Err(e) => {
+            checks.push(Check::fail("GPU count", e));
+            return checks;
+        }
+    };
+    checks.push(Check::pass(format!("GPU count ({})", num_gpus)));
+
+    for i in 0..num_gpus {
+        match nvml.device_by_index(i) {
+            Ok(device) => {
+                checks.push(Check::pass(format!("GPU {} accessible", i)));
+                checks.extend(device_checks(i, &device));
+            }
+            Err(e) => checks.push(Check::fail(format!("GPU {} accessible", i), e)),
+        }
+    }
+    checks
+}
+
+/// Whether each of the fields bmon relies on can be read from `device`.
+fn device_checks(idx: u32, device: &impl GpuDevice) -> Vec<Check> {
+    let check = |field: &str, result: Result<(), NvmlError>| {
+        Check::new(format!("GPU {} {}", idx, field), result)
+    };
+    vec![
+        check("temperature", device.temperature().map(|_| ())),
+        check("power", device.power_usage().map(|_| ())),
+        check("utilization", device.utilization_rates().map(|_| ())),
+        check("memory", device.memory_info().map(|_| ())),
+    ]
+}
+
+/// Adds a hint to the errors seen on misconfigured systems.
+fn init_reason(e: NvmlError) -> String {
+    match e {
+        NvmlError::LibloadingError(_) => {
+            format!("{} (is libnvidia-ml.so on the library path?)", e)
+        }
+        NvmlError::DriverNotLoaded => format!("{} (is the nvidia kernel module loaded?)", e),
+        NvmlError::NoPermission => format!("{} (try running as root)", e),
+        e => e.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::mock::MockDevice;
+
+    #[test]
+    fn reports_unreadable_fields() {
+        let checks = device_checks(0, &MockDevice::new(0));
+        assert!(checks.iter().all(Check::passed));
+        assert_eq!(checks[0].format(), "GPU 0 temperature        ✓ PASS");
+
+        let mut device = MockDevice::new(1);
+        device.power_usage = None;
+        device.errors = vec!["memory_info"];
+        let failed = device_checks(1, &device)
+            .into_iter()
+            .filter(|check| !check.passed())
+            .map(|check| check.name)
+            .collect::<Vec<String>>();
+        assert_eq!(failed, vec!["GPU 1 power", "GPU 1 memory"]);
+    }
+}
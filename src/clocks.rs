@@ -0,0 +1,170 @@
+use nvml_wrapper::Nvml;
+
+use crate::device::GpuDevice;
+use crate::error::BmonError;
+use crate::json::Json;
+use crate::log::debug;
+use crate::schema;
+
+/// Valid clock combinations of one GPU, for `nvidia-smi --lock-gpu-clocks`.
+pub struct SupportedClocks {
+    pub idx: u32,
+    pub name: String,
+    pub clocks: Vec<(u32, Vec<u32>)>, // (memory clock, SM clocks) in MHz
+}
+
+impl SupportedClocks {
+    pub fn from_device(device: &impl GpuDevice) -> Self {
+        let idx = device.index().unwrap_or(0);
+        let name = device.name().unwrap_or_else(|_| "N/A".to_string());
+        Self {
+            idx,
+            name,
+            clocks: supported_clocks(device),
+        }
+    }
+
+    /// e.g.
+    /// ```text
+    /// GPU 0 (NVIDIA A100-SXM4-80GB):
+    ///   Memory 1593MHz: SM 1410, 1395, 1380 MHz
+    /// ```
+    pub fn format(&self) -> String {
+        let mut lines = vec![format!("GPU {} ({}):", self.idx, self.name)];
+        if self.clocks.is_empty() {
+            lines.push("  Not supported".to_string());
+        }
+        for (memory_clock, sm_clocks) in &self.clocks {
+            let sm_clocks = sm_clocks
+                .iter()
+                .map(|clock| clock.to_string())
+                .collect::<Vec<String>>()
+                .join(", ");
+            lines.push(format!(
+                "  Memory {}MHz: SM {} MHz",
+                memory_clock, sm_clocks
+            ));
+        }
+        lines.join("\n")
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("idx", self.idx.into()),
+            ("name", (&self.name).into()),
+            (
+                "memory_clocks",
+                self.clocks
+                    .iter()
+                    .map(|(memory_clock, sm_clocks)| {
+                        Json::object(vec![
+                            ("memory_clock_mhz", (*memory_clock).into()),
+                            ("sm_clocks_mhz", sm_clocks.clone().into()),
+                        ])
+                    })
+                    .collect::<Vec<Json>>()
+                    .into(),
+            ),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        schema::object(vec![
+            ("idx", schema::integer()),
+            ("name", schema::string()),
+            (
+                "memory_clocks",
+                schema::array(schema::object(vec![
+                    ("memory_clock_mhz", schema::integer()),
+                    ("sm_clocks_mhz", schema::array(schema::integer())),
+                ])),
+            ),
+        ])
+    }
+}
+
+/// What `bmon supported-clocks --json` prints.
+pub fn supported_clocks_json(gpus: &[SupportedClocks]) -> Json {
+    let gpus = gpus
+        .iter()
+        .map(SupportedClocks::to_json)
+        .collect::<Vec<Json>>();
+    Json::object(vec![("gpus", gpus.into())])
+}
+
+/// The JSON Schema (draft 7) of `supported_clocks_json`.
+pub fn supported_clocks_schema() -> Json {
+    schema::document(
+        "bmon supported-clocks --json",
+        schema::object(vec![(
+            "gpus",
+            schema::array(SupportedClocks::json_schema()),
+        )]),
+    )
+}
+
+/// Every supported (memory clock, SM clocks) pair of `device`, in MHz.
+pub fn supported_clocks(device: &impl GpuDevice) -> Vec<(u32, Vec<u32>)> {
+    // consumer GPUs usually report NotSupported, which leaves the list empty
+    let memory_clocks = device.supported_memory_clocks().unwrap_or_else(|e| {
+        debug!("failed to query supported memory clocks: {}", e);
+        vec![]
+    });
+    memory_clocks
+        .iter()
+        .map(|memory_clock| {
+            let sm_clocks = device
+                .supported_graphics_clocks(*memory_clock)
+                .unwrap_or_else(|e| {
+                    debug!(
+                        "failed to query supported SM clocks at {}MHz: {}",
+                        memory_clock, e
+                    );
+                    vec![]
+                });
+            (*memory_clock, sm_clocks)
+        })
+        .collect()
+}
+
+/// The supported clocks of every GPU.
+pub fn query_supported_clocks() -> Result<Vec<SupportedClocks>, BmonError> {
+    let nvml = Nvml::init()?;
+    let num_gpus = nvml.device_count()?;
+    (0..num_gpus)
+        .map(|i| Ok(SupportedClocks::from_device(&nvml.device_by_index(i)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::mock::MockDevice;
+
+    #[test]
+    fn lists_supported_clocks() {
+        let mut device = MockDevice::new(0);
+        device.supported_clocks = Some(vec![(1593, vec![1410, 1395]), (1512, vec![1275])]);
+        let clocks = SupportedClocks::from_device(&device);
+        assert_eq!(
+            clocks.format(),
+            "GPU 0 (NVIDIA A100-SXM4-80GB):\n  Memory 1593MHz: SM 1410, 1395 MHz\n  Memory 1512MHz: SM 1275 MHz"
+        );
+
+        // a memory clock whose SM clocks can't be read is still listed
+        device.errors = vec!["supported_graphics_clocks"];
+        assert_eq!(
+            supported_clocks(&device),
+            vec![(1593, vec![]), (1512, vec![])]
+        );
+
+        let unsupported = SupportedClocks::from_device(&MockDevice::unsupported(1));
+        assert_eq!(unsupported.format(), "GPU 1 (N/A):\n  Not supported");
+
+        let json = supported_clocks_json(&[clocks, unsupported]);
+        assert_eq!(
+            schema::violations(&supported_clocks_schema(), &json, "clocks"),
+            Vec::<String>::new()
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use nvml_wrapper::error::NvmlError;
+use nvml_wrapper::Nvml;
+use std::cell::{Cell, RefCell};
+
+use crate::gpu::{get_driver_stats, DriverStats};
+use crate::log::debug;
+use crate::{BmonError, CollectOptions, Machine};
+
+/// Collects repeated snapshots with the same NVML instance, for `bmon watch`,
+/// the TUI and the daemon, rather than paying for `Nvml::init` every time.
+///
+/// NVML is re-initialised, and the GPUs re-enumerated, when a GPU is lost or
+/// the device list changes (e.g. after a GPU reset or MIG reconfiguration).
+/// Device handles borrow the `Nvml` they came from, so they're looked up again
+/// for each snapshot; that lookup is cheap next to the initialisation.
+pub struct Collector {
+    nvml: RefCell<Nvml>,
+    driver: RefCell<DriverStats>, // only changes with the driver, so queried once per init
+    stale: Cell<bool>,            // whether the last snapshot lost a GPU
+    options: CollectOptions,
+}
+
+impl Collector {
+    /// Initialises NVML, failing if it's unusable. Each snapshot collects `options`,
+    /// as with `Machine::new`.
+    pub fn new(options: CollectOptions) -> Result<Self, BmonError> {
+        debug!("initialising NVML");
+        let nvml = Nvml::init()?;
+        let driver = get_driver_stats(&nvml);
+        Ok(Self {
+            nvml: RefCell::new(nvml),
+            driver: RefCell::new(driver),
+            stale: Cell::new(false),
+            options,
+        })
+    }
+
+    /// A new snapshot. A GPU lost during it is only a warning, with NVML
+    /// re-initialised before the next one; if the GPUs can't even be
+    /// counted, NVML is re-initialised and the snapshot retried once.
+    pub fn collect(&self) -> Result<Machine, BmonError> {
+        if self.stale.get() {
+            self.reinit()?;
+        }
+        match self.try_collect() {
+            Err(BmonError::Nvml(e)) if needs_reinit(&e) => {
+                debug!("re-initialising NVML after: {}", e);
+                self.reinit()?;
+                self.try_collect()
+            }
+            result => result,
+        }
+    }
+
+    fn try_collect(&self) -> Result<Machine, BmonError> {
+        let (machine, stale) =
+            Machine::collect(&self.nvml.borrow(), &self.driver.borrow(), &self.options)?;
+        self.stale.set(stale);
+        Ok(machine)
+    }
+
+    fn reinit(&self) -> Result<(), BmonError> {
+        let nvml = Nvml::init()?;
+        *self.driver.borrow_mut() = get_driver_stats(&nvml);
+        // dropping the old instance shuts it down, after the new one is up
+        *self.nvml.borrow_mut() = nvml;
+        self.stale.set(false);
+        Ok(())
+    }
+}
+
+/// Whether `e` means the device handles are out of date, rather than a field
+/// being unavailable. An index past the end of a shrunk device list is `InvalidArg`.
+pub(crate) fn needs_reinit(e: &NvmlError) -> bool {
+    matches!(
+        e,
+        NvmlError::GpuLost
+            | NvmlError::ResetRequired
+            | NvmlError::Uninitialized
+            | NvmlError::NotFound
+            | NvmlError::InvalidArg
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_reinitialises_for_stale_devices() {
+        assert!(needs_reinit(&NvmlError::GpuLost));
+        assert!(needs_reinit(&NvmlError::InvalidArg));
+        assert!(!needs_reinit(&NvmlError::NotSupported));
+        assert!(!needs_reinit(&NvmlError::NoPermission));
+    }
+
+    #[test]
+    fn options_select_gpus() {
+        assert_eq!(CollectOptions::new(), CollectOptions::ALL);
+        assert!(CollectOptions::ALL.includes_gpu(7));
+
+        let options = CollectOptions::GPUS_ONLY.gpus([0, 1]).exclude_gpus([1]);
+        assert!(options.includes_gpu(0));
+        assert!(!options.includes_gpu(1));
+        assert!(!options.includes_gpu(2));
+
+        // the union collects more stats, from the same GPUs
+        let union = options.clone().union(CollectOptions::ALL);
+        assert!(union.processes && union.io);
+        assert_eq!(union.gpus, options.gpus);
+    }
+}
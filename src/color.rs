@@ -0,0 +1,26 @@
+use std::io::IsTerminal;
+
+#[derive(Clone, Copy)]
+pub enum Color {
+    Green,
+    Yellow,
+    Red,
+}
+
+/// Color is used when printing to a terminal, unless `NO_COLOR` is set.
+pub fn enabled() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+/// Wraps `text` in ANSI color codes, or returns it unchanged if color is disabled.
+pub fn paint(text: &str, color: Color) -> String {
+    if !enabled() {
+        return text.to_string();
+    }
+    let code = match color {
+        Color::Green => 32,
+        Color::Yellow => 33,
+        Color::Red => 31,
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
@@ -0,0 +1,305 @@
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use std::path::PathBuf;
+
+use bmon::log::warning;
+
+// arguments that only make sense on the command line
+const CLI_ONLY: [&str; 4] = ["help", "version", "config", "dump_config"];
+
+/// A value from the config file, which is a flat subset of TOML.
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    // a number or string, as it would be typed on the command line
+    Scalar(String),
+    Array(Vec<String>),
+}
+
+/// `$XDG_CONFIG_HOME/bmon/config.toml`, falling back to `~/.config/bmon/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("bmon").join("config.toml"))
+}
+
+/// Parses `key = value` lines, where the key is the name of a command line flag
+/// (e.g. `min_runtime` or `min-runtime`) and the value a boolean, number, string,
+/// or single-line array. Returns the line number and reason for the first invalid line.
+pub fn parse(contents: &str) -> Result<Vec<(String, Value)>, (usize, String)> {
+    let mut entries = vec![];
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('[') {
+            return Err((
+                i + 1,
+                "tables aren't supported, keys must be top level".into(),
+            ));
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err((i + 1, "expected `key = value`".into()));
+        };
+        let value = parse_value(value.trim()).map_err(|reason| (i + 1, reason))?;
+        entries.push((key.trim().replace('-', "_"), value));
+    }
+    Ok(entries)
+}
+
+fn parse_value(value: &str) -> Result<Value, String> {
+    if let Some(items) = value.strip_prefix('[') {
+        let (items, rest) = items
+            .split_once(']')
+            .ok_or("unterminated array, arrays must fit on one line")?;
+        check_trailing(rest)?;
+        let items = items
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| {
+                let (item, rest) = parse_scalar(item)?;
+                check_trailing(rest)?;
+                Ok(item)
+            })
+            .collect::<Result<Vec<String>, String>>()?;
+        return Ok(Value::Array(items));
+    }
+    let (scalar, rest) = parse_scalar(value)?;
+    check_trailing(rest)?;
+    match scalar.as_str() {
+        _ if value.starts_with(['"', '\'']) => Ok(Value::Scalar(scalar)),
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ => Ok(Value::Scalar(scalar)),
+    }
+}
+
+/// A quoted string or bare word, and whatever follows it.
+fn parse_scalar(value: &str) -> Result<(String, &str), String> {
+    if let Some(literal) = value.strip_prefix('\'') {
+        let (literal, rest) = literal.split_once('\'').ok_or("unterminated string")?;
+        return Ok((literal.to_string(), rest));
+    }
+    let Some(basic) = value.strip_prefix('"') else {
+        let end = value.find([' ', '\t', '#']).unwrap_or(value.len());
+        if end == 0 {
+            return Err("missing value".into());
+        }
+        return Ok((value[..end].to_string(), &value[end..]));
+    };
+    let mut string = String::new();
+    let mut chars = basic.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((string, &basic[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => string.push('\n'),
+                Some((_, 't')) => string.push('\t'),
+                Some((_, c @ ('"' | '\\'))) => string.push(c),
+                _ => return Err("unsupported escape in string".into()),
+            },
+            c => string.push(c),
+        }
+    }
+    Err("unterminated string".into())
+}
+
+/// Only a comment may follow a value.
+fn check_trailing(rest: &str) -> Result<(), String> {
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Ok(())
+    } else {
+        Err(format!("unexpected `{}` after value", rest))
+    }
+}
+
+/// Turns config entries into command line arguments for `command`, to go before
+/// the real ones. Entries for arguments that were given on the command line
+/// (`from_cli`) are skipped so that the command line wins, and unknown keys are warned about.
+pub fn to_args(
+    command: &Command,
+    entries: &[(String, Value)],
+    from_cli: impl Fn(&str) -> bool,
+) -> Vec<String> {
+    let mut args = vec![];
+    for (key, value) in entries {
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == key.as_str() && !CLI_ONLY.contains(&key.as_str()))
+        else {
+            warning!("ignoring unknown key `{}` in the config file", key);
+            continue;
+        };
+        let Some(long) = arg.get_long() else {
+            warning!("ignoring `{}` in the config file, it isn't a flag", key);
+            continue;
+        };
+        if from_cli(key) || conflicts_with_cli(command, arg, &from_cli) {
+            continue;
+        }
+        match value {
+            Value::Bool(true) if is_flag(arg.get_action()) => args.push(format!("--{}", long)),
+            Value::Bool(false) if is_flag(arg.get_action()) => {}
+            Value::Bool(value) => args.push(format!("--{}={}", long, value)),
+            Value::Scalar(value) => args.push(format!("--{}={}", long, value)),
+            Value::Array(values) => {
+                args.extend(values.iter().map(|value| format!("--{}={}", long, value)))
+            }
+        }
+    }
+    args
+}
+
+/// Whether the command line has a flag that can't be combined with `arg`,
+/// e.g. `--json` when the config file sets `format`.
+fn conflicts_with_cli(command: &Command, arg: &Arg, from_cli: impl Fn(&str) -> bool) -> bool {
+    let conflicts = |a: &Arg, b: &Arg| {
+        command
+            .get_arg_conflicts_with(a)
+            .iter()
+            .any(|conflict| conflict.get_id() == b.get_id())
+    };
+    command
+        .get_arguments()
+        .filter(|other| from_cli(other.get_id().as_str()))
+        .any(|other| conflicts(arg, other) || conflicts(other, arg))
+}
+
+fn is_flag(action: &ArgAction) -> bool {
+    matches!(action, ArgAction::SetTrue)
+}
+
+/// The effective value of every flag as a config file, for --dump-config.
+/// Flags that are unset and have no default are left out.
+pub fn dump(command: &Command, matches: &ArgMatches) -> String {
+    let mut lines = vec![];
+    for arg in command.get_arguments() {
+        let id = arg.get_id().as_str();
+        if arg.is_positional() || CLI_ONLY.contains(&id) {
+            continue;
+        }
+        if is_flag(arg.get_action()) {
+            lines.push(format!("{} = {}", id, matches.get_flag(id)));
+            continue;
+        }
+        let Some(values) = matches.get_raw(id) else {
+            continue;
+        };
+        let values = values
+            .map(|value| toml_scalar(&value.to_string_lossy()))
+            .collect::<Vec<String>>();
+        if matches!(arg.get_action(), ArgAction::Append) {
+            lines.push(format!("{} = [{}]", id, values.join(", ")));
+        } else if let Some(value) = values.last() {
+            lines.push(format!("{} = {}", id, value));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Numbers are left bare and everything else is quoted.
+fn toml_scalar(value: &str) -> String {
+    if value.parse::<f64>().is_ok() {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_flat_toml() {
+        let contents = r#"
+# shared defaults for the cluster
+verbose = true
+bottleneck = false
+min-runtime = 5 # minutes
+format = "json"
+exclude_users = ["root", 'jupyter']
+gpus = [0, 1]
+"#;
+        assert_eq!(
+            parse(contents).unwrap(),
+            vec![
+                ("verbose".into(), Value::Bool(true)),
+                ("bottleneck".into(), Value::Bool(false)),
+                ("min_runtime".into(), Value::Scalar("5".into())),
+                ("format".into(), Value::Scalar("json".into())),
+                (
+                    "exclude_users".into(),
+                    Value::Array(vec!["root".into(), "jupyter".into()])
+                ),
+                ("gpus".into(), Value::Array(vec!["0".into(), "1".into()])),
+            ]
+        );
+        // a quoted boolean is a string
+        assert_eq!(parse_value("\"true\""), Ok(Value::Scalar("true".into())));
+
+        assert_eq!(parse("[diagnostics]").unwrap_err().0, 1);
+        assert_eq!(parse("verbose = true\nverbose").unwrap_err().0, 2);
+        assert!(parse("format = \"json").is_err());
+        assert!(parse("gpus = [0, 1").is_err());
+        assert!(parse("min_runtime = 5 minutes").is_err());
+    }
+
+    fn command() -> Command {
+        Command::new("bmon")
+            .arg(
+                Arg::new("verbose")
+                    .long("verbose")
+                    .action(ArgAction::SetTrue),
+            )
+            .arg(
+                Arg::new("json")
+                    .long("json")
+                    .action(ArgAction::SetTrue)
+                    .conflicts_with("format"),
+            )
+            .arg(Arg::new("format").long("format").default_value("table"))
+            .arg(
+                Arg::new("min_runtime")
+                    .long("min-runtime")
+                    .default_value("0"),
+            )
+            .arg(
+                Arg::new("gpus")
+                    .long("gpus")
+                    .value_delimiter(',')
+                    .action(ArgAction::Append),
+            )
+            .arg(Arg::new("config").long("config"))
+    }
+
+    #[test]
+    fn command_line_wins_over_config() {
+        let entries = vec![
+            ("verbose".into(), Value::Bool(true)),
+            ("json".into(), Value::Bool(true)),
+            ("min_runtime".into(), Value::Scalar("5".into())),
+            ("gpus".into(), Value::Array(vec!["0".into(), "1".into()])),
+            ("config".into(), Value::Scalar("other.toml".into())),
+            ("colour".into(), Value::Bool(true)),
+        ];
+        assert_eq!(
+            to_args(&command(), &entries, |id| ["min_runtime", "format"]
+                .contains(&id)),
+            vec!["--verbose", "--gpus=0", "--gpus=1"]
+        );
+    }
+
+    #[test]
+    fn dumps_effective_config() {
+        let matches = command().get_matches_from(["bmon", "--verbose", "--gpus", "0,1"]);
+        assert_eq!(
+            dump(&command(), &matches),
+            "verbose = true\njson = false\nformat = \"table\"\nmin_runtime = 0\ngpus = [0, 1]"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::log::debug;
+use crate::parallel;
+
+const DOCKER_SOCKET: &str = "/var/run/docker.sock";
+
+// the daemon normally answers in a few ms, don't hang bmon if it is stuck
+const DOCKER_TIMEOUT_MS: u64 = 200;
+
+// container names by ID, kept across snapshots as they rarely change
+static CONTAINER_NAMES: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// Returns the ID of the Docker container the process runs in, or None if it isn't containerized.
+pub fn get_container_id(pid: u32) -> Option<String> {
+    let cgroup = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    parse_container_id(&cgroup)
+}
+
+/// Returns the names of the containers with `ids`, by ID. Each container is
+/// looked up at most once, and not at all if its name was found before; ones
+/// the Docker socket can't name are left out.
+pub fn get_container_names(ids: &[String]) -> HashMap<String, String> {
+    let mut cache = CONTAINER_NAMES.lock().unwrap_or_else(|e| e.into_inner());
+    let cache = cache.get_or_insert_with(HashMap::new);
+    let mut missing = ids
+        .iter()
+        .filter(|id| !cache.contains_key(*id))
+        .collect::<Vec<&String>>();
+    missing.sort();
+    missing.dedup();
+    let results = parallel::map(&missing, |id| query_container_name(id));
+    for (id, result) in missing.into_iter().zip(results) {
+        match result.and_then(|result| result.map_err(|e| e.to_string())) {
+            Ok(Some(name)) => {
+                cache.insert(id.clone(), name);
+            }
+            Ok(None) => {}
+            Err(e) => debug!("failed to look up container {}: {}", id, e),
+        }
+    }
+    ids.iter()
+        .filter_map(|id| Some((id.clone(), cache.get(id)?.clone())))
+        .collect()
+}
+
+/// Finds the container ID in /proc/<pid>/cgroup, e.g. in
+/// `0::/system.slice/docker-<id>.scope` (systemd cgroup driver) or
+/// `12:memory:/docker/<id>` (cgroupfs driver, also used by containerd).
+fn parse_container_id(cgroup: &str) -> Option<String> {
+    cgroup.lines().find_map(|line| {
+        let path = line.splitn(3, ':').nth(2)?;
+        let last = path.rsplit('/').next()?;
+        let id = if path.contains("docker/") || path.contains("containerd/") {
+            last
+        } else {
+            last.strip_prefix("docker-")?.strip_suffix(".scope")?
+        };
+        is_container_id(id).then(|| id.to_string())
+    })
+}
+
+/// Container IDs are 64 hex characters.
+fn is_container_id(id: &str) -> bool {
+    id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Asks the Docker daemon to inspect the container, see
+/// https://docs.docker.com/engine/api/v1.43/#tag/Container/operation/ContainerInspect
+fn query_container_name(id: &str) -> std::io::Result<Option<String>> {
+    let mut socket = UnixStream::connect(DOCKER_SOCKET)?;
+    let timeout = Some(Duration::from_millis(DOCKER_TIMEOUT_MS));
+    socket.set_read_timeout(timeout)?;
+    socket.set_write_timeout(timeout)?;
+
+    // HTTP/1.0 so the daemon closes the connection instead of using chunked encoding
+    write!(
+        socket,
+        "GET /containers/{}/json HTTP/1.0\r\nHost: docker\r\n\r\n",
+        id
+    )?;
+    let mut response = String::new();
+    socket.read_to_string(&mut response)?;
+    Ok(parse_container_name(&response))
+}
+
+/// Extracts e.g. `trainer` from `..."Name":"/trainer",...` in an inspect response.
+fn parse_container_name(response: &str) -> Option<String> {
+    let (_, body) = response.split_once("\r\n\r\n")?;
+    let start = body.find("\"Name\":\"")? + "\"Name\":\"".len();
+    let name = &body[start..];
+    let name = &name[..name.find('"')?];
+    Some(name.trim_start_matches('/').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "4c01db0b339c6f1a6e7e4f0b9c5c8a5d4e3f2a1b0c9d8e7f6a5b4c3d2e1f0a9b";
+
+    #[test]
+    fn finds_container_ids() {
+        let cgroupfs = format!("12:memory:/docker/{}\n11:cpu:/docker/{}\n", ID, ID);
+        assert_eq!(parse_container_id(&cgroupfs), Some(ID.to_string()));
+
+        let systemd = format!("0::/system.slice/docker-{}.scope\n", ID);
+        assert_eq!(parse_container_id(&systemd), Some(ID.to_string()));
+
+        let containerd = format!("0::/kubepods/besteffort/containerd/{}\n", ID);
+        assert_eq!(parse_container_id(&containerd), Some(ID.to_string()));
+
+        assert_eq!(parse_container_id("0::/user.slice/session-3.scope\n"), None);
+        assert_eq!(parse_container_id("0::/docker/not-an-id\n"), None);
+    }
+
+    #[test]
+    fn reuses_container_names_found_before() {
+        CONTAINER_NAMES
+            .lock()
+            .unwrap()
+            .get_or_insert_with(HashMap::new)
+            .insert(ID.to_string(), "trainer".to_string());
+        // not a running container, so it can't be named whether or not Docker is running
+        let unknown = "0".repeat(64);
+        let names = get_container_names(&[ID.to_string(), unknown, ID.to_string()]);
+        assert_eq!(names.len(), 1);
+        assert_eq!(names[ID], "trainer");
+    }
+
+    #[test]
+    fn parses_inspect_response() {
+        let response = "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n\
+                        {\"Id\":\"4c01db0b339c\",\"Created\":\"2023-10-12T09:00:00Z\",\"Name\":\"/trainer\",\"RestartCount\":0}";
+        assert_eq!(parse_container_name(response), Some("trainer".to_string()));
+
+        let not_found = "HTTP/1.0 404 Not Found\r\n\r\n{\"message\":\"No such container\"}";
+        assert_eq!(parse_container_name(not_found), None);
+    }
+}
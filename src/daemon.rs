@@ -0,0 +1,182 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use bmon::json::Json;
+use bmon::BmonError;
+
+pub const PID_FILE: &str = "/tmp/bmon.pid";
+
+// set by the daemon's SIGTERM handler
+static TERMINATED: AtomicBool = AtomicBool::new(false);
+
+const TERMINATE_POLL: Duration = Duration::from_millis(100);
+
+extern "C" fn on_sigterm(_: libc::c_int) {
+    TERMINATED.store(true, Ordering::Relaxed);
+}
+
+/// Forks into the background and appends a JSON snapshot (one per line)
+/// to `log_file` every `interval`. A failed snapshot is logged as an
+/// `error` line and retried on the next tick. Only returns in the parent
+/// process; the child removes the PID file when it exits.
+pub fn start(
+    log_file: &Path,
+    interval: Duration,
+    snapshot: impl Fn() -> Result<Json, BmonError>,
+) -> io::Result<()> {
+    // open the log file before forking, so errors are reported to the user
+    let mut log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_file)?;
+
+    // SAFETY: bmon is single-threaded at this point and nothing has touched NVML yet
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    if pid > 0 {
+        println!("bmon daemon started with PID {}", pid);
+        return Ok(());
+    }
+
+    // child: detach from the terminal and silence stdio
+    unsafe {
+        libc::setsid();
+        let devnull = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
+        if devnull >= 0 {
+            libc::dup2(devnull, libc::STDIN_FILENO);
+            libc::dup2(devnull, libc::STDOUT_FILENO);
+            libc::dup2(devnull, libc::STDERR_FILENO);
+        }
+    }
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe {
+        libc::signal(
+            libc::SIGTERM,
+            on_sigterm as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        )
+    };
+    fs::write(PID_FILE, std::process::id().to_string())?;
+    let result = run(&mut log, interval, snapshot);
+    let _ = fs::remove_file(PID_FILE);
+    result?;
+    std::process::exit(0);
+}
+
+/// Logs a snapshot every `interval` until SIGTERM arrives or the log can't be written.
+fn run(
+    log: &mut impl Write,
+    interval: Duration,
+    snapshot: impl Fn() -> Result<Json, BmonError>,
+) -> io::Result<()> {
+    while !TERMINATED.load(Ordering::Relaxed) {
+        let mut json = snapshot()
+            .unwrap_or_else(|e| Json::Object(vec![("error".to_string(), e.to_string().into())]));
+        if let Json::Object(fields) = &mut json {
+            fields.insert(0, ("timestamp".to_string(), timestamp().into()));
+        }
+        writeln!(log, "{}", json)?;
+
+        // sleep in short steps so that SIGTERM is handled promptly
+        let wake_at = Instant::now() + interval;
+        while Instant::now() < wake_at && !TERMINATED.load(Ordering::Relaxed) {
+            thread::sleep(TERMINATE_POLL.min(wake_at.saturating_duration_since(Instant::now())));
+        }
+    }
+    Ok(())
+}
+
+/// Sends SIGTERM to the daemon recorded in the PID file and removes the file.
+/// Refuses to signal the PID if it no longer belongs to bmon, e.g. because the
+/// daemon died and the PID was reused; the stale file is removed instead.
+pub fn stop() -> io::Result<()> {
+    let pid = fs::read_to_string(PID_FILE)?
+        .trim()
+        .parse::<libc::pid_t>()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let cmdline = fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+    if !is_bmon(&cmdline) {
+        fs::remove_file(PID_FILE)?;
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "PID {} is not a running bmon daemon, removed stale {}",
+                pid, PID_FILE
+            ),
+        ));
+    }
+
+    // SAFETY: kill has no memory safety requirements
+    if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    fs::remove_file(PID_FILE)?;
+    println!("Stopped bmon daemon with PID {}", pid);
+    Ok(())
+}
+
+/// Whether a `/proc/<pid>/cmdline` belongs to bmon, judging by the name of argv[0].
+fn is_bmon(cmdline: &[u8]) -> bool {
+    let argv0 = cmdline.split(|&b| b == 0).next().unwrap_or_default();
+    Path::new(std::str::from_utf8(argv0).unwrap_or_default())
+        .file_name()
+        .is_some_and(|name| name == "bmon")
+}
+
+/// Seconds since the Unix epoch.
+pub fn timestamp() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_bmon_by_argv0() {
+        assert!(is_bmon(b"bmon\0--daemon\0--log-file\0/tmp/bmon.log\0"));
+        assert!(is_bmon(b"/usr/local/bin/bmon\0--daemon\0"));
+        assert!(!is_bmon(b"/usr/bin/python3\0bmon\0"));
+        assert!(!is_bmon(b"bmonitor\0"));
+        // kernel threads and exited processes have an empty cmdline
+        assert!(!is_bmon(b""));
+    }
+
+    #[test]
+    fn logs_a_failed_snapshot_and_keeps_going() {
+        let calls = std::cell::Cell::new(0);
+        let mut log = vec![];
+        run(&mut log, Duration::ZERO, || {
+            calls.set(calls.get() + 1);
+            if calls.get() == 2 {
+                TERMINATED.store(true, Ordering::Relaxed);
+            }
+            match calls.get() {
+                1 => Err(BmonError::NoSuchProcess { pid: 42 }),
+                _ => Ok(Json::Object(vec![(
+                    "gpus".to_string(),
+                    Json::Array(vec![]),
+                )])),
+            }
+        })
+        .unwrap();
+        let lines: Vec<_> = std::str::from_utf8(&log).unwrap().lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(r#"{"timestamp":"#));
+        assert!(
+            lines[0].ends_with(r#","error":"process 42 doesn't exist"}"#),
+            "{}",
+            lines[0]
+        );
+        assert!(lines[1].ends_with(r#","gpus":[]}"#), "{}", lines[1]);
+    }
+}
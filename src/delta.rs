@@ -0,0 +1,128 @@
+use crate::Machine;
+
+/// The GPU readings that --delta compares between refreshes.
+pub struct Snapshot {
+    gpus: Vec<GpuReading>,
+}
+
+struct GpuReading {
+    idx: u32,
+    utilizations: (u32, u32), // (gpu, memory) in %
+    temp: u32,                // in °C
+    memory_used: u64,         // in bytes
+}
+
+impl Snapshot {
+    pub fn from_machine(machine: &Machine) -> Self {
+        let gpus = machine
+            .gpus
+            .iter()
+            .map(|gpu| GpuReading {
+                idx: gpu.idx,
+                utilizations: gpu.utilizations,
+                temp: gpu.temp,
+                memory_used: gpu.memory.0 .0,
+            })
+            .collect();
+        Self { gpus }
+    }
+}
+
+/// Signed change of one GPU's readings between two snapshots.
+pub struct GpuDelta {
+    pub idx: u32,
+    pub utilizations: (i64, i64), // (gpu, memory) in percentage points
+    pub temp: i64,                // in °C
+    pub memory_used: i64,         // in bytes
+}
+
+impl GpuDelta {
+    /// e.g. `GPU  +5% VRAM  -3%`, aligned like the absolute values
+    pub fn display_utilizations(&self) -> String {
+        format!(
+            "GPU {:>+4}% VRAM {:>+4}%",
+            self.utilizations.0, self.utilizations.1
+        )
+    }
+
+    pub fn display_temp(&self) -> String {
+        format!("{:+}°C", self.temp)
+    }
+
+    /// e.g. `+512MB`, or `-1.50GB` for larger changes
+    pub fn display_memory(&self) -> String {
+        let mib = self.memory_used as f64 / 1024.0 / 1024.0;
+        if mib.abs() >= 1024.0 {
+            format!("{:+.2}GB", mib / 1024.0)
+        } else {
+            format!("{:+.0}MB", mib)
+        }
+    }
+}
+
+/// Changes between two snapshots. GPUs missing from the previous snapshot are left out.
+pub struct Delta {
+    pub gpus: Vec<GpuDelta>,
+}
+
+impl Delta {
+    pub fn from_snapshots(prev: &Snapshot, current: &Snapshot) -> Self {
+        let gpus = current
+            .gpus
+            .iter()
+            .filter_map(|current| {
+                let prev = prev.gpus.iter().find(|prev| prev.idx == current.idx)?;
+                let diff = |prev: u64, current: u64| current as i64 - prev as i64;
+                Some(GpuDelta {
+                    idx: current.idx,
+                    utilizations: (
+                        diff(prev.utilizations.0 as u64, current.utilizations.0 as u64),
+                        diff(prev.utilizations.1 as u64, current.utilizations.1 as u64),
+                    ),
+                    temp: diff(prev.temp as u64, current.temp as u64),
+                    memory_used: diff(prev.memory_used, current.memory_used),
+                })
+            })
+            .collect();
+        Self { gpus }
+    }
+
+    pub fn gpu(&self, idx: u32) -> Option<&GpuDelta> {
+        self.gpus.iter().find(|gpu| gpu.idx == idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    fn snapshot(readings: &[(u32, u32, u32, u64)]) -> Snapshot {
+        Snapshot {
+            gpus: readings
+                .iter()
+                .map(|(idx, utilization, temp, memory_used)| GpuReading {
+                    idx: *idx,
+                    utilizations: (*utilization, 0),
+                    temp: *temp,
+                    memory_used: *memory_used,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn computes_signed_changes() {
+        let prev = snapshot(&[(0, 90, 70, 10240 * MIB), (1, 0, 40, 0)]);
+        let current = snapshot(&[(0, 95, 67, 10752 * MIB), (1, 0, 40, 0), (2, 50, 50, 0)]);
+        let delta = Delta::from_snapshots(&prev, &current);
+        assert_eq!(delta.gpus.len(), 2);
+
+        let gpu = delta.gpu(0).unwrap();
+        assert_eq!(gpu.display_utilizations(), "GPU   +5% VRAM   +0%");
+        assert_eq!(gpu.display_temp(), "-3°C");
+        assert_eq!(gpu.display_memory(), "+512MB");
+        assert!(delta.gpu(2).is_none());
+    }
+}
@@ -0,0 +1,548 @@
+use nvml_wrapper::bitmasks::device::ThrottleReasons;
+use nvml_wrapper::enum_wrappers::device::{
+    Brand, Clock, InfoRom, PcieUtilCounter, RetirementCause, TemperatureSensor,
+    TemperatureThreshold,
+};
+use nvml_wrapper::error::{nvml_try, NvmlError};
+use nvml_wrapper::struct_wrappers::device::{
+    EncoderSessionInfo, MemoryInfo, ProcessInfo, Utilization,
+};
+use nvml_wrapper::Device;
+use std::ffi::{c_uint, c_void, CStr};
+
+/// The NVML queries bmon makes of each GPU, so that collection can be tested
+/// without one. Wrapped types are simplified where bmon only needs part of them.
+pub trait GpuDevice {
+    fn index(&self) -> Result<u32, NvmlError>;
+    fn name(&self) -> Result<String, NvmlError>;
+    // GPU core temperature in °C
+    fn temperature(&self) -> Result<u32, NvmlError>;
+    fn temperature_threshold(&self, threshold: TemperatureThreshold) -> Result<u32, NvmlError>;
+    // power readings are in milliwatts
+    fn power_usage(&self) -> Result<u32, NvmlError>;
+    fn enforced_power_limit(&self) -> Result<u32, NvmlError>;
+    // (min, max)
+    fn power_limit_constraints(&self) -> Result<(u32, u32), NvmlError>;
+    // in millijoules since the driver was loaded
+    fn total_energy_consumption(&self) -> Result<u64, NvmlError>;
+    fn utilization_rates(&self) -> Result<Utilization, NvmlError>;
+    fn memory_info(&self) -> Result<MemoryInfo, NvmlError>;
+    // in bytes, set aside by the driver and firmware so in neither used nor free memory
+    fn memory_reserved(&self) -> Result<u64, NvmlError>;
+    fn cuda_compute_capability(&self) -> Result<(i32, i32), NvmlError>; // (major, minor)
+    fn num_cores(&self) -> Result<u32, NvmlError>;
+    fn brand(&self) -> Result<Brand, NvmlError>;
+    fn current_throttle_reasons(&self) -> Result<ThrottleReasons, NvmlError>;
+    // number of pages retired for `cause`
+    fn retired_pages(&self, cause: RetirementCause) -> Result<u32, NvmlError>;
+    fn current_pcie_link_gen(&self) -> Result<u32, NvmlError>;
+    fn current_pcie_link_width(&self) -> Result<u32, NvmlError>;
+    fn max_pcie_link_gen(&self) -> Result<u32, NvmlError>;
+    fn max_pcie_link_width(&self) -> Result<u32, NvmlError>;
+    // in KB/s
+    fn pcie_throughput(&self, counter: PcieUtilCounter) -> Result<u32, NvmlError>;
+    // in MHz
+    fn clock_info(&self, clock: Clock) -> Result<u32, NvmlError>;
+    fn max_clock_info(&self, clock: Clock) -> Result<u32, NvmlError>;
+    // e.g. "00000000:3B:00.0"
+    fn pci_bus_id(&self) -> Result<String, NvmlError>;
+    fn is_in_persistent_mode(&self) -> Result<bool, NvmlError>;
+    fn info_rom_version(&self, object: InfoRom) -> Result<String, NvmlError>;
+    fn vbios_version(&self) -> Result<String, NvmlError>;
+    fn num_fans(&self) -> Result<u32, NvmlError>;
+    // in % of the maximum speed
+    fn fan_speed(&self, fan: u32) -> Result<u32, NvmlError>;
+    fn is_display_connected(&self) -> Result<bool, NvmlError>;
+    fn is_display_active(&self) -> Result<bool, NvmlError>;
+    fn encoder_sessions(&self) -> Result<Vec<EncoderSessionInfo>, NvmlError>;
+    fn running_compute_processes(&self) -> Result<Vec<ProcessInfo>, NvmlError>;
+    fn running_graphics_processes(&self) -> Result<Vec<ProcessInfo>, NvmlError>;
+    // in MHz
+    fn supported_memory_clocks(&self) -> Result<Vec<u32>, NvmlError>;
+    // the SM clocks that can be paired with `memory_clock`, in MHz
+    fn supported_graphics_clocks(&self, memory_clock: u32) -> Result<Vec<u32>, NvmlError>;
+}
+
+// the inherent methods take precedence, so these don't recurse
+impl GpuDevice for Device<'_> {
+    fn index(&self) -> Result<u32, NvmlError> {
+        self.index()
+    }
+
+    fn name(&self) -> Result<String, NvmlError> {
+        self.name()
+    }
+
+    fn temperature(&self) -> Result<u32, NvmlError> {
+        self.temperature(TemperatureSensor::Gpu)
+    }
+
+    fn temperature_threshold(&self, threshold: TemperatureThreshold) -> Result<u32, NvmlError> {
+        self.temperature_threshold(threshold)
+    }
+
+    fn power_usage(&self) -> Result<u32, NvmlError> {
+        self.power_usage()
+    }
+
+    fn enforced_power_limit(&self) -> Result<u32, NvmlError> {
+        self.enforced_power_limit()
+    }
+
+    fn power_limit_constraints(&self) -> Result<(u32, u32), NvmlError> {
+        self.power_management_limit_constraints()
+            .map(|constraints| (constraints.min_limit, constraints.max_limit))
+    }
+
+    fn total_energy_consumption(&self) -> Result<u64, NvmlError> {
+        self.total_energy_consumption()
+    }
+
+    fn utilization_rates(&self) -> Result<Utilization, NvmlError> {
+        self.utilization_rates()
+    }
+
+    fn memory_info(&self) -> Result<MemoryInfo, NvmlError> {
+        self.memory_info()
+    }
+
+    fn memory_reserved(&self) -> Result<u64, NvmlError> {
+        memory_info_v2(self).map(|memory| memory.reserved)
+    }
+
+    fn cuda_compute_capability(&self) -> Result<(i32, i32), NvmlError> {
+        self.cuda_compute_capability()
+            .map(|capability| (capability.major, capability.minor))
+    }
+
+    fn num_cores(&self) -> Result<u32, NvmlError> {
+        self.num_cores()
+    }
+
+    fn brand(&self) -> Result<Brand, NvmlError> {
+        self.brand()
+    }
+
+    fn current_throttle_reasons(&self) -> Result<ThrottleReasons, NvmlError> {
+        self.current_throttle_reasons()
+    }
+
+    fn retired_pages(&self, cause: RetirementCause) -> Result<u32, NvmlError> {
+        self.retired_pages(cause).map(|pages| pages.len() as u32)
+    }
+
+    fn current_pcie_link_gen(&self) -> Result<u32, NvmlError> {
+        self.current_pcie_link_gen()
+    }
+
+    fn current_pcie_link_width(&self) -> Result<u32, NvmlError> {
+        self.current_pcie_link_width()
+    }
+
+    fn max_pcie_link_gen(&self) -> Result<u32, NvmlError> {
+        self.max_pcie_link_gen()
+    }
+
+    fn max_pcie_link_width(&self) -> Result<u32, NvmlError> {
+        self.max_pcie_link_width()
+    }
+
+    fn pcie_throughput(&self, counter: PcieUtilCounter) -> Result<u32, NvmlError> {
+        self.pcie_throughput(counter)
+    }
+
+    fn clock_info(&self, clock: Clock) -> Result<u32, NvmlError> {
+        self.clock_info(clock)
+    }
+
+    fn max_clock_info(&self, clock: Clock) -> Result<u32, NvmlError> {
+        self.max_clock_info(clock)
+    }
+
+    fn pci_bus_id(&self) -> Result<String, NvmlError> {
+        self.pci_info().map(|pci| pci.bus_id)
+    }
+
+    fn is_in_persistent_mode(&self) -> Result<bool, NvmlError> {
+        self.is_in_persistent_mode()
+    }
+
+    fn info_rom_version(&self, object: InfoRom) -> Result<String, NvmlError> {
+        self.info_rom_version(object)
+    }
+
+    fn vbios_version(&self) -> Result<String, NvmlError> {
+        self.vbios_version()
+    }
+
+    fn num_fans(&self) -> Result<u32, NvmlError> {
+        self.num_fans()
+    }
+
+    fn fan_speed(&self, fan: u32) -> Result<u32, NvmlError> {
+        self.fan_speed(fan)
+    }
+
+    fn is_display_connected(&self) -> Result<bool, NvmlError> {
+        self.is_display_connected()
+    }
+
+    fn is_display_active(&self) -> Result<bool, NvmlError> {
+        self.is_display_active()
+    }
+
+    fn encoder_sessions(&self) -> Result<Vec<EncoderSessionInfo>, NvmlError> {
+        self.encoder_sessions()
+    }
+
+    fn running_compute_processes(&self) -> Result<Vec<ProcessInfo>, NvmlError> {
+        self.running_compute_processes()
+    }
+
+    fn running_graphics_processes(&self) -> Result<Vec<ProcessInfo>, NvmlError> {
+        self.running_graphics_processes()
+    }
+
+    fn supported_memory_clocks(&self) -> Result<Vec<u32>, NvmlError> {
+        self.supported_memory_clocks()
+    }
+
+    fn supported_graphics_clocks(&self, memory_clock: u32) -> Result<Vec<u32>, NvmlError> {
+        self.supported_graphics_clocks(memory_clock)
+    }
+}
+
+// nvmlMemory_v2_t
+#[repr(C)]
+#[derive(Default)]
+struct MemoryInfoV2 {
+    version: u32,
+    total: u64,
+    reserved: u64,
+    free: u64,
+    used: u64,
+}
+
+// the names NVML may have been loaded under
+const NVML_LIBRARIES: [&CStr; 2] = [c"libnvidia-ml.so.1", c"libnvidia-ml.so"];
+
+/// `nvmlDeviceGetMemoryInfo_v2`, which nvml-wrapper doesn't wrap. It's looked
+/// up in the library NVML was already loaded from, and is NotSupported by
+/// drivers older than R510 that don't have it.
+fn memory_info_v2(device: &Device) -> Result<MemoryInfoV2, NvmlError> {
+    type GetMemoryInfoV2 = unsafe extern "C" fn(*mut c_void, *mut MemoryInfoV2) -> c_uint;
+    let library = NVML_LIBRARIES
+        .iter()
+        .map(|name| unsafe { libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_NOLOAD) })
+        .find(|library| !library.is_null())
+        .ok_or(NvmlError::NotSupported)?;
+    let symbol = unsafe { libc::dlsym(library, c"nvmlDeviceGetMemoryInfo_v2".as_ptr()) };
+    let result = if symbol.is_null() {
+        Err(NvmlError::NotSupported)
+    } else {
+        // NVML_STRUCT_VERSION(Memory, 2)
+        let mut memory = MemoryInfoV2 {
+            version: size_of::<MemoryInfoV2>() as u32 | 2 << 24,
+            ..Default::default()
+        };
+        let get_memory_info: GetMemoryInfoV2 = unsafe { std::mem::transmute(symbol) };
+        let code = unsafe { get_memory_info(device.handle() as *mut c_void, &mut memory) };
+        nvml_try(code).map(|()| memory)
+    };
+    // only drops the reference NOLOAD added, NVML stays loaded
+    unsafe { libc::dlclose(library) };
+    result
+}
+
+/// A fake GPU for tests. Every query succeeds with the field's value, or fails
+/// with NotSupported if it is None, unless it is listed in `errors`.
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+    use nvml_wrapper::enums::device::UsedGpuMemory;
+    use std::cell::RefCell;
+
+    pub struct MockDevice {
+        pub index: u32,
+        pub name: Option<String>,
+        pub temperature: Option<u32>,
+        pub temperature_slowdown: Option<u32>,
+        pub power_usage: Option<u32>,
+        pub power_limit: Option<u32>,
+        pub energy: Option<u64>,
+        pub utilization: Option<(u32, u32)>, // (gpu, memory)
+        pub memory: Option<(u64, u64)>,      // (used, total)
+        pub memory_reserved: Option<u64>,
+        pub throttle_reasons: Option<ThrottleReasons>,
+        pub retired_pages_dbe: Option<u32>,
+        pub num_fans: Option<u32>,
+        pub fan_speeds: Vec<u32>,
+        pub compute_processes: Option<Vec<(u32, u64)>>, // (pid, used memory)
+        pub clocks: Option<(u32, u32)>,                 // (SM, memory) in MHz
+        pub max_clocks: Option<(u32, u32)>,             // (SM, memory) in MHz
+        pub supported_clocks: Option<Vec<(u32, Vec<u32>)>>, // (memory clock, SM clocks)
+        // queries that fail with an unexpected error, e.g. "memory_info"
+        pub errors: Vec<&'static str>,
+        // every query made so far, in order
+        pub queries: RefCell<Vec<&'static str>>,
+    }
+
+    impl MockDevice {
+        /// A healthy, idle GPU with every query supported.
+        pub fn new(index: u32) -> Self {
+            Self {
+                index,
+                name: Some("NVIDIA A100-SXM4-80GB".to_string()),
+                temperature: Some(35),
+                temperature_slowdown: Some(90),
+                power_usage: Some(60_000),
+                power_limit: Some(400_000),
+                energy: Some(1_000_000),
+                utilization: Some((0, 0)),
+                memory: Some((0, 80 * 1024 * 1024 * 1024)),
+                memory_reserved: Some(0),
+                throttle_reasons: Some(ThrottleReasons::GPU_IDLE),
+                retired_pages_dbe: Some(0),
+                num_fans: Some(0),
+                fan_speeds: vec![],
+                compute_processes: Some(vec![]),
+                clocks: Some((210, 1593)),
+                max_clocks: Some((1410, 1593)),
+                supported_clocks: Some(vec![(1593, vec![1410, 1395, 1380])]),
+                errors: vec![],
+                queries: RefCell::new(vec![]),
+            }
+        }
+
+        /// A GPU whose every query is unsupported, like an old or virtual GPU.
+        pub fn unsupported(index: u32) -> Self {
+            Self {
+                index,
+                name: None,
+                temperature: None,
+                temperature_slowdown: None,
+                power_usage: None,
+                power_limit: None,
+                energy: None,
+                utilization: None,
+                memory: None,
+                memory_reserved: None,
+                throttle_reasons: None,
+                retired_pages_dbe: None,
+                num_fans: None,
+                fan_speeds: vec![],
+                compute_processes: None,
+                clocks: None,
+                max_clocks: None,
+                supported_clocks: None,
+                errors: vec![],
+                queries: RefCell::new(vec![]),
+            }
+        }
+
+        fn query<T>(&self, name: &'static str, value: Option<T>) -> Result<T, NvmlError> {
+            self.queries.borrow_mut().push(name);
+            if self.errors.contains(&name) {
+                return Err(NvmlError::Unknown);
+            }
+            value.ok_or(NvmlError::NotSupported)
+        }
+    }
+
+    impl GpuDevice for MockDevice {
+        fn index(&self) -> Result<u32, NvmlError> {
+            Ok(self.index)
+        }
+
+        fn name(&self) -> Result<String, NvmlError> {
+            self.query("name", self.name.clone())
+        }
+
+        fn temperature(&self) -> Result<u32, NvmlError> {
+            self.query("temperature", self.temperature)
+        }
+
+        fn temperature_threshold(&self, threshold: TemperatureThreshold) -> Result<u32, NvmlError> {
+            match threshold {
+                TemperatureThreshold::Slowdown => {
+                    self.query("temperature_threshold", self.temperature_slowdown)
+                }
+                _ => self.query("temperature_threshold", None),
+            }
+        }
+
+        fn power_usage(&self) -> Result<u32, NvmlError> {
+            self.query("power_usage", self.power_usage)
+        }
+
+        fn enforced_power_limit(&self) -> Result<u32, NvmlError> {
+            self.query("enforced_power_limit", self.power_limit)
+        }
+
+        fn power_limit_constraints(&self) -> Result<(u32, u32), NvmlError> {
+            self.query("power_limit_constraints", None)
+        }
+
+        fn total_energy_consumption(&self) -> Result<u64, NvmlError> {
+            self.query("total_energy_consumption", self.energy)
+        }
+
+        fn utilization_rates(&self) -> Result<Utilization, NvmlError> {
+            let utilization = self
+                .utilization
+                .map(|(gpu, memory)| Utilization { gpu, memory });
+            self.query("utilization_rates", utilization)
+        }
+
+        fn memory_info(&self) -> Result<MemoryInfo, NvmlError> {
+            let memory = self.memory.map(|(used, total)| MemoryInfo {
+                free: total.saturating_sub(used),
+                total,
+                used,
+            });
+            self.query("memory_info", memory)
+        }
+
+        fn memory_reserved(&self) -> Result<u64, NvmlError> {
+            self.query("memory_reserved", self.memory_reserved)
+        }
+
+        fn cuda_compute_capability(&self) -> Result<(i32, i32), NvmlError> {
+            self.query("cuda_compute_capability", Some((8, 0)))
+        }
+
+        fn num_cores(&self) -> Result<u32, NvmlError> {
+            self.query("num_cores", None)
+        }
+
+        fn brand(&self) -> Result<Brand, NvmlError> {
+            self.query("brand", None)
+        }
+
+        fn current_throttle_reasons(&self) -> Result<ThrottleReasons, NvmlError> {
+            self.query("current_throttle_reasons", self.throttle_reasons)
+        }
+
+        fn retired_pages(&self, cause: RetirementCause) -> Result<u32, NvmlError> {
+            match cause {
+                RetirementCause::DoubleBitEccError => {
+                    self.query("retired_pages", self.retired_pages_dbe)
+                }
+                RetirementCause::MultipleSingleBitEccErrors => {
+                    self.query("retired_pages", self.retired_pages_dbe.map(|_| 0))
+                }
+            }
+        }
+
+        fn current_pcie_link_gen(&self) -> Result<u32, NvmlError> {
+            self.query("current_pcie_link_gen", Some(4))
+        }
+
+        fn current_pcie_link_width(&self) -> Result<u32, NvmlError> {
+            self.query("current_pcie_link_width", Some(16))
+        }
+
+        fn max_pcie_link_gen(&self) -> Result<u32, NvmlError> {
+            self.query("max_pcie_link_gen", Some(4))
+        }
+
+        fn max_pcie_link_width(&self) -> Result<u32, NvmlError> {
+            self.query("max_pcie_link_width", Some(16))
+        }
+
+        fn pcie_throughput(&self, _counter: PcieUtilCounter) -> Result<u32, NvmlError> {
+            self.query("pcie_throughput", None)
+        }
+
+        fn clock_info(&self, clock: Clock) -> Result<u32, NvmlError> {
+            let clock = match clock {
+                Clock::Memory => self.clocks.map(|(_, memory)| memory),
+                _ => self.clocks.map(|(sm, _)| sm),
+            };
+            self.query("clock_info", clock)
+        }
+
+        fn max_clock_info(&self, clock: Clock) -> Result<u32, NvmlError> {
+            let clock = match clock {
+                Clock::Memory => self.max_clocks.map(|(_, memory)| memory),
+                _ => self.max_clocks.map(|(sm, _)| sm),
+            };
+            self.query("max_clock_info", clock)
+        }
+
+        fn pci_bus_id(&self) -> Result<String, NvmlError> {
+            self.query("pci_bus_id", None)
+        }
+
+        fn is_in_persistent_mode(&self) -> Result<bool, NvmlError> {
+            self.query("is_in_persistent_mode", Some(false))
+        }
+
+        fn info_rom_version(&self, _object: InfoRom) -> Result<String, NvmlError> {
+            self.query("info_rom_version", None)
+        }
+
+        fn vbios_version(&self) -> Result<String, NvmlError> {
+            self.query("vbios_version", None)
+        }
+
+        fn num_fans(&self) -> Result<u32, NvmlError> {
+            self.query("num_fans", self.num_fans)
+        }
+
+        fn fan_speed(&self, fan: u32) -> Result<u32, NvmlError> {
+            self.query("fan_speed", self.fan_speeds.get(fan as usize).copied())
+        }
+
+        fn is_display_connected(&self) -> Result<bool, NvmlError> {
+            self.query("is_display_connected", Some(false))
+        }
+
+        fn is_display_active(&self) -> Result<bool, NvmlError> {
+            self.query("is_display_active", Some(false))
+        }
+
+        fn encoder_sessions(&self) -> Result<Vec<EncoderSessionInfo>, NvmlError> {
+            self.query("encoder_sessions", None)
+        }
+
+        fn running_compute_processes(&self) -> Result<Vec<ProcessInfo>, NvmlError> {
+            let processes = self.compute_processes.as_ref().map(|processes| {
+                processes
+                    .iter()
+                    .map(|(pid, bytes)| ProcessInfo {
+                        pid: *pid,
+                        used_gpu_memory: UsedGpuMemory::Used(*bytes),
+                        gpu_instance_id: None,
+                        compute_instance_id: None,
+                    })
+                    .collect()
+            });
+            self.query("running_compute_processes", processes)
+        }
+
+        fn running_graphics_processes(&self) -> Result<Vec<ProcessInfo>, NvmlError> {
+            self.query("running_graphics_processes", Some(vec![]))
+        }
+
+        fn supported_memory_clocks(&self) -> Result<Vec<u32>, NvmlError> {
+            let memory_clocks = self.supported_clocks.as_ref().map(|clocks| {
+                clocks
+                    .iter()
+                    .map(|(memory_clock, _)| *memory_clock)
+                    .collect()
+            });
+            self.query("supported_memory_clocks", memory_clocks)
+        }
+
+        fn supported_graphics_clocks(&self, memory_clock: u32) -> Result<Vec<u32>, NvmlError> {
+            let sm_clocks = self.supported_clocks.as_ref().and_then(|clocks| {
+                clocks
+                    .iter()
+                    .find(|(clock, _)| *clock == memory_clock)
+                    .map(|(_, sm_clocks)| sm_clocks.clone())
+            });
+            self.query("supported_graphics_clocks", sm_clocks)
+        }
+    }
+}
@@ -0,0 +1,1569 @@
+use nvml_wrapper::bitmasks::device::ThrottleReasons;
+
+use crate::color::Color;
+use crate::gpu::{
+    pcie_bandwidth_gbps, throttle_reason_names, GPUStats, BENIGN_THROTTLE_REASONS,
+    TEMP_WARNING_MARGIN, THROTTLE_REMEDIATIONS,
+};
+use crate::json::Json;
+use crate::schema;
+use crate::units::Bytes;
+use crate::{CollectOptions, Machine};
+
+// GPU utilization (%) above which a GPU is considered under load
+const PCIE_LOAD_THRESHOLD: u32 = 50;
+
+// share of the link's bandwidth above which a GPU's PCIe traffic is considered saturating
+const PCIE_SATURATION_FRACTION: f32 = 0.8;
+
+// number of processes listed under a GPU that is nearly out of memory
+const TOP_MEMORY_CONSUMERS: usize = 3;
+
+// a GPU at or below this utilization (%) in every sample is considered idle
+const IDLE_UTILIZATION_THRESHOLD: u32 = 1;
+
+// memory held by a process on an idle GPU above which it's worth chasing the owner
+const IDLE_MEMORY_THRESHOLD: Bytes = Bytes::GIB;
+
+// gap (percentage points) between the busiest GPU of a job and its peers above
+// which the job is considered imbalanced
+const LOAD_IMBALANCE_THRESHOLD: u32 = 25;
+
+// disk utilization (%) above which a disk is considered saturated
+const DISK_BUSY_THRESHOLD: f32 = 90.0;
+
+// average NFS RPC round trip (ms) above which a mount is considered slow
+const NFS_LATENCY_THRESHOLD_MS: f32 = 20.0;
+
+// share of time (%) all tasks were stalled on IO or memory, from PSI, above which
+// the stalls are considered the bottleneck
+const IO_PRESSURE_THRESHOLD: f32 = 20.0;
+const MEMORY_PRESSURE_THRESHOLD: f32 = 10.0;
+
+// /dev/shm usage (%) above which DataLoader workers risk a bus error
+const SHM_FULL_THRESHOLD: f32 = 90.0;
+
+// share of the available cores that a GPU's processes must use for the input
+// pipeline to be considered CPU-bound
+const INPUT_PIPELINE_CPU_FRACTION: f32 = 0.9;
+
+// GPU utilization (%) at or above which a GPU is considered saturated
+const SATURATED_UTILIZATION: u32 = 90;
+// memory controller utilization (%) above which a saturated GPU is memory-bandwidth-bound
+const MEMORY_BANDWIDTH_BOUND: u32 = 80;
+// share of the link's bandwidth below which PCIe traffic is considered negligible
+const PCIE_IDLE_FRACTION: f32 = 0.1;
+
+/// The limits the diagnosis compares readings against, set on the command line.
+#[derive(Clone, Copy)]
+pub struct Thresholds {
+    // GPU temperature (°C) to warn at, None for each GPU's slowdown temperature less a margin
+    pub temp: Option<u32>,
+    // GPU memory usage (%) above which the diagnosis warns of an OOM
+    pub memory_pct: f32,
+    // iowait or IO pressure (%) above which low GPU utilization is blamed on IO
+    pub iowait: f32,
+    // GPU utilization (%) below which a GPU with processes is considered underutilized,
+    // and possibly starved by the input pipeline
+    pub low_util: u32,
+    // context switches per second per core considered excessive
+    pub ctxt: f32,
+}
+
+impl Thresholds {
+    /// The temperature (°C) at which to warn about `gpu`, None if it is unknown.
+    fn temp(&self, gpu: &GPUStats) -> Option<u32> {
+        temp_threshold(self.temp, gpu.temp_slowdown)
+    }
+}
+
+/// `warn_temp` if set, otherwise the point at which the temperature is shown in yellow.
+fn temp_threshold(warn_temp: Option<u32>, temp_slowdown: u32) -> Option<u32> {
+    match warn_temp {
+        Some(temp) => Some(temp),
+        // 0 if NVML doesn't report a slowdown temperature
+        None if temp_slowdown == 0 => None,
+        None => Some(temp_slowdown.saturating_sub(TEMP_WARNING_MARGIN)),
+    }
+}
+
+/// Parses a percentage, rejecting values outside 0-100.
+pub fn parse_pct(value: &str) -> Result<f32, String> {
+    let pct = value
+        .parse::<f32>()
+        .map_err(|_| format!("`{}` isn't a number", value))?;
+    if !(0.0..=100.0).contains(&pct) {
+        return Err(format!("{} is not between 0 and 100", pct));
+    }
+    Ok(pct)
+}
+
+/// Parses a rate, rejecting values that aren't finite and more than 0.
+pub fn parse_rate(value: &str) -> Result<f32, String> {
+    let rate = value
+        .parse::<f32>()
+        .map_err(|_| format!("`{}` isn't a number", value))?;
+    if !rate.is_finite() || rate <= 0.0 {
+        return Err(format!("{} is not a positive number", rate));
+    }
+    Ok(rate)
+}
+
+/// How urgently a finding needs attention.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Info,
+    Warn,
+    Crit,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Info => "INFO",
+            Severity::Warn => "WARN",
+            Severity::Crit => "CRIT",
+        }
+    }
+
+    /// e.g. `critical`, for JSON.
+    pub fn id(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warn => "warning",
+            Severity::Crit => "critical",
+        }
+    }
+
+    pub fn color(self) -> Color {
+        match self {
+            Severity::Info => Color::Green,
+            Severity::Warn => Color::Yellow,
+            Severity::Crit => Color::Red,
+        }
+    }
+}
+
+/// What a finding is about. The ids are written to JSON for downstream tooling
+/// to switch on, so they must never change once released; add new kinds instead.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    /// `retired_pages`: pages retired due to double bit ECC errors
+    RetiredPages,
+    /// `memory_nearly_full`: GPU memory above the threshold
+    MemoryNearlyFull,
+    /// `idle_memory_held`: an idle GPU whose memory is held by processes
+    IdleMemoryHeld,
+    /// `load_imbalance`: one GPU of a multi-GPU job far busier than its peers
+    LoadImbalance,
+    /// `missing_ranks`: a multi-GPU job with processes on fewer GPUs than expected
+    MissingRanks,
+    /// `input_pipeline_cpu`: a starved GPU whose processes use all their CPU
+    InputPipelineCpu,
+    /// `input_pipeline_io`: a starved GPU while the host waits on IO
+    InputPipelineIo,
+    /// `cpu_load`: system load far above the core count
+    CpuLoad,
+    /// `context_switches`: excessive context switching per core
+    ContextSwitches,
+    /// `disk_busy`: a saturated physical disk
+    DiskBusy,
+    /// `io_pressure`: all tasks stalled on IO, from PSI
+    IoPressure,
+    /// `memory_pressure`: all tasks stalled on memory, from PSI
+    MemoryPressure,
+    /// `blocked_process`: a GPU process stuck in D state
+    BlockedProcess,
+    /// `slow_netfs`: iowait with a slow NFS mount
+    SlowNetworkFilesystem,
+    /// `shm_full`: /dev/shm nearly full
+    ShmFull,
+    /// `swapping`: the system is swapping in pages
+    Swapping,
+    /// `persistence_daemon_not_running`: legacy persistence mode without the daemon
+    PersistenceDaemonNotRunning,
+    /// `high_temperature`: a GPU at or above the warning temperature
+    HighTemperature,
+    /// `thermal_throttle`: clocks reduced by a thermal slowdown
+    ThermalThrottle,
+    /// `hardware_slowdown`: clocks reduced by a hardware slowdown or power brake
+    HardwareSlowdown,
+    /// `power_throttle`: clocks reduced by the power cap
+    PowerThrottle,
+    /// `clock_throttle`: clocks reduced for any other reason, e.g. sync boost
+    ClockThrottle,
+    /// `pcie_bound`: PCIe traffic near the link bandwidth while the SMs idle
+    PcieBound,
+    /// `pcie_link_degraded`: a PCIe link below its max gen or width under load
+    PcieLinkDegraded,
+}
+
+impl Kind {
+    pub fn id(self) -> &'static str {
+        match self {
+            Kind::RetiredPages => "retired_pages",
+            Kind::MemoryNearlyFull => "memory_nearly_full",
+            Kind::IdleMemoryHeld => "idle_memory_held",
+            Kind::LoadImbalance => "load_imbalance",
+            Kind::MissingRanks => "missing_ranks",
+            Kind::InputPipelineCpu => "input_pipeline_cpu",
+            Kind::InputPipelineIo => "input_pipeline_io",
+            Kind::CpuLoad => "cpu_load",
+            Kind::ContextSwitches => "context_switches",
+            Kind::DiskBusy => "disk_busy",
+            Kind::IoPressure => "io_pressure",
+            Kind::MemoryPressure => "memory_pressure",
+            Kind::BlockedProcess => "blocked_process",
+            Kind::SlowNetworkFilesystem => "slow_netfs",
+            Kind::ShmFull => "shm_full",
+            Kind::Swapping => "swapping",
+            Kind::PersistenceDaemonNotRunning => "persistence_daemon_not_running",
+            Kind::HighTemperature => "high_temperature",
+            Kind::ThermalThrottle => "thermal_throttle",
+            Kind::HardwareSlowdown => "hardware_slowdown",
+            Kind::PowerThrottle => "power_throttle",
+            Kind::ClockThrottle => "clock_throttle",
+            Kind::PcieBound => "pcie_bound",
+            Kind::PcieLinkDegraded => "pcie_link_degraded",
+        }
+    }
+}
+
+/// One line of the bottleneck diagnosis, with any follow-up advice and the
+/// readings it was based on.
+pub struct Finding {
+    pub severity: Severity,
+    pub kind: Kind,
+    pub gpu: Option<u32>, // None for host-wide findings
+    pub message: String,
+    pub details: Vec<String>,
+    pub evidence: Vec<(&'static str, Json)>,
+}
+
+impl Finding {
+    pub fn new(severity: Severity, kind: Kind, message: String) -> Self {
+        Self {
+            severity,
+            kind,
+            gpu: None,
+            message,
+            details: vec![],
+            evidence: vec![],
+        }
+    }
+
+    pub fn warn(kind: Kind, message: String) -> Self {
+        Self::new(Severity::Warn, kind, message)
+    }
+
+    pub fn crit(kind: Kind, message: String) -> Self {
+        Self::new(Severity::Crit, kind, message)
+    }
+
+    pub fn on_gpu(mut self, idx: u32) -> Self {
+        self.gpu = Some(idx);
+        self
+    }
+
+    pub fn with_details(mut self, details: Vec<String>) -> Self {
+        self.details = details;
+        self
+    }
+
+    pub fn with_evidence(mut self, evidence: Vec<(&'static str, Json)>) -> Self {
+        self.evidence = evidence;
+        self
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("gpu", self.gpu.into()),
+            ("kind", self.kind.id().into()),
+            ("severity", self.severity.id().into()),
+            ("message", (&self.message).into()),
+            ("details", self.details.clone().into()),
+            ("evidence", Json::object(self.evidence.clone())),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        schema::object(vec![
+            ("gpu", schema::nullable(schema::integer())),
+            ("kind", schema::string()),
+            ("severity", schema::string()),
+            ("message", schema::string()),
+            ("details", schema::array(schema::string())),
+            // depends on the kind
+            ("evidence", schema::any_object()),
+        ])
+    }
+}
+
+/// Thermal and hardware slowdowns mean the GPU is at risk or badly cooled, while
+/// the power cap is a configuration choice. Anything else is informational.
+pub fn throttle_severity(reasons: ThrottleReasons) -> Severity {
+    let critical = ThrottleReasons::HW_SLOWDOWN
+        | ThrottleReasons::HW_THERMAL_SLOWDOWN
+        | ThrottleReasons::SW_THERMAL_SLOWDOWN
+        | ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN;
+    if reasons.intersects(critical) {
+        Severity::Crit
+    } else if reasons.contains(ThrottleReasons::SW_POWER_CAP) {
+        Severity::Warn
+    } else {
+        Severity::Info
+    }
+}
+
+/// The kind of a throttling finding, named after the most serious reason.
+fn throttle_kind(reasons: ThrottleReasons) -> Kind {
+    if reasons
+        .intersects(ThrottleReasons::SW_THERMAL_SLOWDOWN | ThrottleReasons::HW_THERMAL_SLOWDOWN)
+    {
+        Kind::ThermalThrottle
+    } else if reasons
+        .intersects(ThrottleReasons::HW_SLOWDOWN | ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN)
+    {
+        Kind::HardwareSlowdown
+    } else if reasons.contains(ThrottleReasons::SW_POWER_CAP) {
+        Kind::PowerThrottle
+    } else {
+        Kind::ClockThrottle
+    }
+}
+
+// the stats `diagnose` reads, so that collecting for a diagnosis doesn't skip any of them
+pub const REQUIRED_STATS: CollectOptions =
+    CollectOptions::GPUS_ONLY.processes(true).cpu(true).io(true);
+
+/// Runs every diagnosis, most serious hardware problems first.
+pub fn diagnose(machine: &Machine, thresholds: &Thresholds) -> Vec<Finding> {
+    health_check(machine, thresholds)
+        .into_iter()
+        .chain(memory_diagnostics(machine, thresholds.memory_pct))
+        .chain(idle_memory_diagnostics(machine))
+        .chain(load_imbalance_diagnostics(machine))
+        .chain(bottleneck_diagnostics(machine, thresholds))
+        .chain(input_pipeline_diagnostics(
+            machine,
+            thresholds.low_util,
+            thresholds.iowait,
+        ))
+        .collect()
+}
+
+/// e.g. `2 warnings, 1 critical issue on 8 GPUs`, or `No issues found on 8 GPUs`.
+pub fn summarize(findings: &[Finding], num_gpus: usize) -> String {
+    let count = |severity: Severity| {
+        findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .count()
+    };
+    let plural = |n: usize, singular: &str, plural: &str| {
+        format!("{} {}", n, if n == 1 { singular } else { plural })
+    };
+    let gpus = plural(num_gpus, "GPU", "GPUs");
+
+    let mut counts = vec![];
+    let warnings = count(Severity::Warn);
+    if warnings > 0 {
+        counts.push(plural(warnings, "warning", "warnings"));
+    }
+    let critical = count(Severity::Crit);
+    if critical > 0 {
+        counts.push(plural(critical, "critical issue", "critical issues"));
+    }
+    if counts.is_empty() {
+        return format!("No issues found on {}", gpus);
+    }
+    format!("{} on {}", counts.join(", "), gpus)
+}
+
+/// Hardware problems that need attention regardless of the workload.
+pub fn health_check(machine: &Machine, thresholds: &Thresholds) -> Vec<Finding> {
+    let mut findings = vec![];
+    for gpu in &machine.gpus {
+        // a thermal slowdown is reported with the other throttling instead
+        let thermal_throttled = gpu.throttling.intersects(
+            ThrottleReasons::SW_THERMAL_SLOWDOWN | ThrottleReasons::HW_THERMAL_SLOWDOWN,
+        );
+        if let Some(temp) = thresholds.temp(gpu) {
+            if gpu.temp >= temp && !thermal_throttled {
+                findings.push(
+                    Finding::warn(
+                        Kind::HighTemperature,
+                        format!(
+                            "GPU {} is at {}°C (warning at {}°C, slowdown at {}°C), check fans/airflow",
+                            gpu.idx, gpu.temp, temp, gpu.temp_slowdown
+                        ),
+                    )
+                    .on_gpu(gpu.idx)
+                    .with_evidence(vec![
+                        ("temp", gpu.temp.into()),
+                        ("warn_temp", temp.into()),
+                        ("temp_slowdown", gpu.temp_slowdown.into()),
+                    ]),
+                );
+            }
+        }
+        // double bit errors are uncorrectable, so any retirement means failing memory
+        if gpu.retired_pages_dbe > 0 {
+            findings.push(
+                Finding::crit(
+                    Kind::RetiredPages,
+                    format!(
+                        "GPU {} has {} pages retired due to double bit ECC errors, its memory is failing and the GPU should be replaced",
+                        gpu.idx, gpu.retired_pages_dbe
+                    ),
+                )
+                .on_gpu(gpu.idx)
+                .with_evidence(vec![
+                    ("retired_pages_dbe", gpu.retired_pages_dbe.into()),
+                    ("retired_pages_sbe", gpu.retired_pages_sbe.into()),
+                ]),
+            );
+        }
+    }
+    findings
+}
+
+/// GPUs whose memory is more than `threshold` (%) full, with the processes using the most of it.
+pub fn memory_diagnostics(machine: &Machine, threshold: f32) -> Vec<Finding> {
+    let mut findings = vec![];
+    for gpu in &machine.gpus {
+        let (used, total) = gpu.memory;
+        let used_pct = if total.0 > 0 {
+            used.0 as f32 / total.0 as f32 * 100.0
+        } else {
+            0.0
+        };
+        if used_pct <= threshold {
+            continue;
+        }
+        let mut consumers = gpu.process_memory.iter().collect::<Vec<(&u32, &Bytes)>>();
+        consumers.sort_by(|a, b| b.1.cmp(a.1));
+        let details = consumers
+            .iter()
+            .take(TOP_MEMORY_CONSUMERS)
+            .map(|(pid, bytes)| format!("pid {} uses {:.1}GB", pid, bytes.as_gib()))
+            .collect();
+        let pids = consumers
+            .iter()
+            .take(TOP_MEMORY_CONSUMERS)
+            .map(|(pid, _)| **pid)
+            .collect::<Vec<u32>>();
+        findings.push(
+            Finding::warn(
+                Kind::MemoryNearlyFull,
+                format!(
+                    "GPU {} memory is {:.0}% full ({:.1}GB / {:.1}GB), a larger batch or fragmentation may cause an OOM",
+                    gpu.idx,
+                    used_pct,
+                    used.as_gib(),
+                    total.as_gib()
+                ),
+            )
+            .on_gpu(gpu.idx)
+            .with_details(details)
+            .with_evidence(vec![
+                ("memory_used_bytes", used.into()),
+                ("memory_total_bytes", total.into()),
+                ("top_pids", pids.into()),
+            ]),
+        );
+    }
+    findings
+}
+
+/// True if every utilization sample is near zero. A single sample isn't enough,
+/// since a busy GPU can read 0% when caught between kernels.
+fn is_idle(utilization_samples: &[u32]) -> bool {
+    utilization_samples.len() >= 2
+        && utilization_samples
+            .iter()
+            .all(|utilization| *utilization <= IDLE_UTILIZATION_THRESHOLD)
+}
+
+/// Idle GPUs whose memory is held by processes, e.g. a crashed or forgotten
+/// notebook, listing the owners so they can be asked to free it.
+pub fn idle_memory_diagnostics(machine: &Machine) -> Vec<Finding> {
+    let mut findings = vec![];
+    for gpu in &machine.gpus {
+        if !is_idle(&gpu.utilization_samples) {
+            continue;
+        }
+        let mut holders = gpu
+            .process_memory
+            .iter()
+            .filter(|(_, bytes)| **bytes >= IDLE_MEMORY_THRESHOLD)
+            .collect::<Vec<(&u32, &Bytes)>>();
+        if holders.is_empty() {
+            continue;
+        }
+        holders.sort_by(|a, b| b.1.cmp(a.1));
+        let details = holders
+            .iter()
+            .map(|(pid, bytes)| {
+                match machine
+                    .processes
+                    .iter()
+                    .find(|process| process.pid == **pid)
+                {
+                    Some(process) => format!(
+                        "pid {} ({}, running for {}) holds {:.1}GB",
+                        pid,
+                        process.user,
+                        process.elapsed,
+                        bytes.as_gib()
+                    ),
+                    // e.g. hidden by --exclude-users or running in another PID namespace
+                    None => format!("pid {} holds {:.1}GB", pid, bytes.as_gib()),
+                }
+            })
+            .collect();
+        let held = holders.iter().map(|(_, bytes)| **bytes).sum::<Bytes>();
+        let pids = holders.iter().map(|(pid, _)| **pid).collect::<Vec<u32>>();
+        findings.push(
+            Finding::warn(
+                Kind::IdleMemoryHeld,
+                format!(
+                    "GPU {} is idle but {:.1}GB of its memory is held by {} process(es), which blocks other users",
+                    gpu.idx,
+                    held.as_gib(),
+                    holders.len()
+                ),
+            )
+            .on_gpu(gpu.idx)
+            .with_details(details)
+            .with_evidence(vec![
+                ("utilization_samples", gpu.utilization_samples.clone().into()),
+                ("held_bytes", held.into()),
+                ("pids", pids.into()),
+            ]),
+        );
+    }
+    findings
+}
+
+/// The GPU whose utilization deviates most from the job's median, by more than
+/// `threshold` percentage points, given (idx, utilization) pairs, along with the
+/// median of the other GPUs. Two GPUs are always equally far from their median, so
+/// of those the busier one is named if they are more than `threshold` apart, as
+/// the one the other waits on.
+fn find_straggler(utilizations: &[(u32, u32)], threshold: u32) -> Option<(u32, f32)> {
+    let &(straggler, util) = match utilizations {
+        [] | [_] => return None,
+        [_, _] => utilizations.iter().max_by_key(|(_, util)| *util)?,
+        _ => {
+            let job_median = median(utilizations.iter().map(|(_, util)| *util).collect());
+            let deviation = |util: u32| (util as f32 - job_median).abs();
+            utilizations.iter().reduce(|most, next| {
+                if deviation(next.1) > deviation(most.1) {
+                    next
+                } else {
+                    most
+                }
+            })?
+        }
+    };
+    let peers_median = median(
+        utilizations
+            .iter()
+            .filter(|(idx, _)| *idx != straggler)
+            .map(|(_, util)| *util)
+            .collect(),
+    );
+    ((util as f32 - peers_median).abs() > threshold as f32).then_some((straggler, peers_median))
+}
+
+fn median(mut values: Vec<u32>) -> f32 {
+    values.sort_unstable();
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) as f32 / 2.0
+    } else {
+        values[mid] as f32
+    }
+}
+
+/// Multi-GPU jobs, i.e. processes sharing a process group (or a command line
+/// where the group is unknown), that are held back by one GPU or are missing ranks.
+pub fn load_imbalance_diagnostics(machine: &Machine) -> Vec<Finding> {
+    // (job, GPU indices, expected number of GPUs)
+    let mut jobs: Vec<(String, Vec<u32>, Option<u32>)> = vec![];
+    for gpu in &machine.gpus {
+        for pid in &gpu.processes {
+            let Some(process) = machine.processes.iter().find(|process| process.pid == *pid) else {
+                continue;
+            };
+            let job = match process.pgid {
+                Some(pgid) => format!("Job with process group {}", pgid),
+                None => format!("Job `{}`", process.command.trim_end()),
+            };
+            let index = match jobs.iter().position(|(name, _, _)| *name == job) {
+                Some(index) => index,
+                None => {
+                    jobs.push((job, vec![], None));
+                    jobs.len() - 1
+                }
+            };
+            let (_, gpus, expected) = &mut jobs[index];
+            if !gpus.contains(&gpu.idx) {
+                gpus.push(gpu.idx);
+            }
+            *expected = (*expected).max(process.local_world_size);
+        }
+    }
+
+    let mut findings = vec![];
+    for (job, gpus, expected) in &jobs {
+        let list = gpus
+            .iter()
+            .map(|idx| idx.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        let utilizations = machine
+            .gpus
+            .iter()
+            .filter(|gpu| gpus.contains(&gpu.idx))
+            .map(|gpu| (gpu.idx, gpu.utilizations.0))
+            .collect::<Vec<(u32, u32)>>();
+        if let Some((straggler, peers_median)) =
+            find_straggler(&utilizations, LOAD_IMBALANCE_THRESHOLD)
+        {
+            let straggler_util = utilizations
+                .iter()
+                .find(|(idx, _)| *idx == straggler)
+                .map_or(0, |(_, util)| *util);
+            findings.push(
+                Finding::warn(
+                    Kind::LoadImbalance,
+                    format!(
+                        "{} is imbalanced across GPUs {}: GPU {} is at {}% while its peers' median is {:.0}%, so GPU {} is likely the straggler the others wait on",
+                        job, list, straggler, straggler_util, peers_median, straggler
+                    ),
+                )
+                .on_gpu(straggler)
+                .with_evidence(vec![
+                    ("gpus", gpus.clone().into()),
+                    ("straggler_utilization", straggler_util.into()),
+                    ("peers_median_utilization", peers_median.into()),
+                ]),
+            );
+        }
+        // with --gpus or --exclude-gpus the missing ranks may just be hidden
+        if let Some(expected) = expected.filter(|expected| *expected as usize > gpus.len()) {
+            findings.push(
+                Finding::warn(
+                    Kind::MissingRanks,
+                    format!(
+                        "{} expects {} GPUs (LOCAL_WORLD_SIZE) but only has processes on GPU(s) {}, a rank may have crashed",
+                        job, expected, list
+                    ),
+                )
+                .with_evidence(vec![
+                    ("gpus", gpus.clone().into()),
+                    ("expected_gpus", expected.into()),
+                ]),
+            );
+        }
+    }
+    findings
+}
+
+/// GPUs whose compute processes can't be fed fast enough, either because they
+/// are using all the CPU they are allowed or because the host is waiting on IO.
+pub fn input_pipeline_diagnostics(
+    machine: &Machine,
+    util_threshold: u32,
+    io_threshold: f32,
+) -> Vec<Finding> {
+    let mut findings = vec![];
+    let cores = available_cores(machine);
+    let iowait = machine.disk.as_ref().map_or(0.0, |disk| disk.iowait_pct);
+    let io_pressure = machine
+        .pressure
+        .as_ref()
+        .map_or(0.0, |pressure| pressure.io.some);
+
+    for gpu in &machine.gpus {
+        let has_compute = gpu
+            .process_types
+            .values()
+            .any(|process_type| process_type.contains('C'));
+        // every sample must be low, a single dip between steps is normal
+        let starved = !gpu.utilization_samples.is_empty()
+            && gpu
+                .utilization_samples
+                .iter()
+                .all(|utilization| *utilization < util_threshold);
+        if !has_compute || !starved {
+            continue;
+        }
+
+        let cpu_pct = gpu_process_cpu_pct(machine, gpu.idx);
+        let samples = gpu
+            .utilization_samples
+            .iter()
+            .map(|utilization| format!("{}%", utilization))
+            .collect::<Vec<String>>()
+            .join(", ");
+        if cpu_pct >= INPUT_PIPELINE_CPU_FRACTION * 100.0 * cores {
+            findings.push(
+                Finding::warn(
+                    Kind::InputPipelineCpu,
+                    format!(
+                        "GPU {} appears starved by the input pipeline: utilization {} while its processes use {:.0}% CPU of {} cores. Try more dataloader workers or cheaper preprocessing",
+                        gpu.idx, samples, cpu_pct, cores
+                    ),
+                )
+                .on_gpu(gpu.idx)
+                .with_evidence(vec![
+                    ("utilization_samples", gpu.utilization_samples.clone().into()),
+                    ("cpu_pct", cpu_pct.into()),
+                    ("cores", cores.into()),
+                ]),
+            );
+        } else if iowait > io_threshold || io_pressure > io_threshold {
+            findings.push(
+                Finding::warn(
+                    Kind::InputPipelineIo,
+                    format!(
+                        "GPU {} appears starved by the input pipeline: utilization {} while iowait is {:.0}% and IO pressure is {:.0}%. The dataloader is waiting on storage",
+                        gpu.idx, samples, iowait, io_pressure
+                    ),
+                )
+                .on_gpu(gpu.idx)
+                .with_evidence(vec![
+                    ("utilization_samples", gpu.utilization_samples.clone().into()),
+                    ("iowait_pct", iowait.into()),
+                    ("io_pressure_pct", io_pressure.into()),
+                ]),
+            );
+        }
+    }
+    findings
+}
+
+// ps reports 100% per fully used core, and a cgroup may allow fewer cores than the host has
+fn available_cores(machine: &Machine) -> f32 {
+    machine
+        .cpu
+        .cgroup
+        .cpus
+        .unwrap_or(machine.num_cpus())
+        .min(machine.num_cpus())
+}
+
+// the combined CPU usage (%) of the processes running on GPU `idx`
+fn gpu_process_cpu_pct(machine: &Machine, idx: u32) -> f32 {
+    machine
+        .processes
+        .iter()
+        .filter(|process| process.gpu_indices.contains(&idx))
+        .map(|process| process.cpu_pct)
+        .sum()
+}
+
+pub fn bottleneck_diagnostics(machine: &Machine, thresholds: &Thresholds) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    // load far above the core count while the GPUs sit idle suggests
+    // the input pipeline is CPU-bound
+    let (load, _, _) = machine.load_average;
+    if load > 2.0 * machine.num_cpus() {
+        for gpu in &machine.gpus {
+            if !gpu.processes.is_empty() && gpu.utilizations.0 < thresholds.low_util {
+                findings.push(
+                    Finding::warn(
+                        Kind::CpuLoad,
+                        format!(
+                            "GPU {} has low utilization ({}%) while system load ({:.1}) is over twice the core count ({}), the input pipeline may be CPU-bound",
+                            gpu.idx, gpu.utilizations.0, load, machine.cpu.num_cpus
+                        ),
+                    )
+                    .on_gpu(gpu.idx)
+                    .with_evidence(vec![
+                        ("gpu_utilization", gpu.utilizations.0.into()),
+                        ("load_average", load.into()),
+                        ("num_cpus", machine.cpu.num_cpus.into()),
+                    ]),
+                );
+            }
+        }
+    }
+
+    // workers thrashing each other show up as a huge context switch rate
+    if let Some((ctxt_rate, _)) = machine.event_rates {
+        let ctxt_per_core = ctxt_rate / machine.num_cpus();
+        if ctxt_per_core > thresholds.ctxt {
+            for gpu in &machine.gpus {
+                if !gpu.processes.is_empty() && gpu.utilizations.0 < thresholds.low_util {
+                    findings.push(
+                        Finding::warn(
+                            Kind::ContextSwitches,
+                            format!(
+                                "GPU {} has low utilization ({}%) while the CPU is context switching {:.0} times/s per core, the dataloader may have too many workers (try reducing num_workers)",
+                                gpu.idx, gpu.utilizations.0, ctxt_per_core
+                            ),
+                        )
+                        .on_gpu(gpu.idx)
+                        .with_evidence(vec![
+                            ("gpu_utilization", gpu.utilizations.0.into()),
+                            ("ctxt_per_core", ctxt_per_core.into()),
+                        ]),
+                    );
+                }
+            }
+        }
+    }
+
+    // a saturated disk starves the dataloader just like a saturated CPU
+    let busiest_disk = machine
+        .devices
+        .iter()
+        .filter(|device| device.physical)
+        .max_by(|a, b| a.util_pct.total_cmp(&b.util_pct));
+    if let Some(disk) = busiest_disk.filter(|disk| disk.util_pct > DISK_BUSY_THRESHOLD) {
+        for gpu in &machine.gpus {
+            if !gpu.processes.is_empty() && gpu.utilizations.0 < thresholds.low_util {
+                findings.push(
+                    Finding::warn(
+                        Kind::DiskBusy,
+                        format!(
+                            "GPU {} has low utilization ({}%) while disk {} is {:.0}% busy, the dataloader may be IO-bound",
+                            gpu.idx, gpu.utilizations.0, disk.name, disk.util_pct
+                        ),
+                    )
+                    .on_gpu(gpu.idx)
+                    .with_evidence(vec![
+                        ("gpu_utilization", gpu.utilizations.0.into()),
+                        ("disk", (&disk.name).into()),
+                        ("disk_util_pct", disk.util_pct.into()),
+                    ]),
+                );
+            }
+        }
+    }
+
+    // PSI measures stalls directly, so it catches IO and memory bottlenecks that
+    // utilization based checks miss (e.g. a slow network filesystem)
+    if let Some(pressure) = &machine.pressure {
+        for gpu in &machine.gpus {
+            if gpu.processes.is_empty() || gpu.utilizations.0 >= thresholds.low_util {
+                continue;
+            }
+            if pressure.io.full > IO_PRESSURE_THRESHOLD {
+                findings.push(
+                    Finding::warn(
+                        Kind::IoPressure,
+                        format!(
+                            "GPU {} has low utilization ({}%) while all tasks were stalled on IO {:.0}% of the last 10s, the dataloader is starved by IO",
+                            gpu.idx, gpu.utilizations.0, pressure.io.full
+                        ),
+                    )
+                    .on_gpu(gpu.idx)
+                    .with_evidence(vec![
+                        ("gpu_utilization", gpu.utilizations.0.into()),
+                        ("io_full_pct", pressure.io.full.into()),
+                    ]),
+                );
+            }
+            if pressure.memory.full > MEMORY_PRESSURE_THRESHOLD {
+                findings.push(
+                    Finding::warn(
+                        Kind::MemoryPressure,
+                        format!(
+                            "GPU {} has low utilization ({}%) while all tasks were stalled on memory {:.0}% of the last 10s, the host is short of RAM",
+                            gpu.idx, gpu.utilizations.0, pressure.memory.full
+                        ),
+                    )
+                    .on_gpu(gpu.idx)
+                    .with_evidence(vec![
+                        ("gpu_utilization", gpu.utilizations.0.into()),
+                        ("memory_full_pct", pressure.memory.full.into()),
+                    ]),
+                );
+            }
+        }
+    }
+
+    // blocked processes show ~0% CPU, so would otherwise look idle
+    for process in &machine.processes {
+        if process.on_gpu && process.blocked {
+            findings.push(
+                Finding::warn(
+                    Kind::BlockedProcess,
+                    format!(
+                        "Process {} stayed in uninterruptible sleep (D state) for the whole sample window, it is blocked on IO (e.g. a hung NFS mount or failing disk)",
+                        process.pid
+                    ),
+                )
+                .with_evidence(vec![
+                    ("pid", process.pid.into()),
+                ]),
+            );
+        }
+    }
+
+    // a slow NFS server shows up as iowait without any local disk being busy
+    let iowait = machine.disk.as_ref().map_or(0.0, |disk| disk.iowait_pct);
+    if iowait > thresholds.iowait {
+        let slowest_mount = machine
+            .netfs
+            .iter()
+            .filter_map(|mount| Some((mount, mount.avg_rpc_latency_ms?)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((mount, latency)) =
+            slowest_mount.filter(|(_, latency)| *latency > NFS_LATENCY_THRESHOLD_MS)
+        {
+            for gpu in &machine.gpus {
+                if !gpu.processes.is_empty() && gpu.utilizations.0 < thresholds.low_util {
+                    findings.push(
+                        Finding::warn(
+                            Kind::SlowNetworkFilesystem,
+                            format!(
+                                "GPU {} has low utilization ({}%) while iowait is {:.0}% and NFS mount {} averages {:.0}ms per RPC, the dataloader may be waiting on the NFS server",
+                                gpu.idx, gpu.utilizations.0, iowait, mount.mount, latency
+                            ),
+                        )
+                        .on_gpu(gpu.idx)
+                        .with_evidence(vec![
+                            ("gpu_utilization", gpu.utilizations.0.into()),
+                            ("iowait_pct", iowait.into()),
+                            ("mount", (&mount.mount).into()),
+                            ("avg_rpc_latency_ms", latency.into()),
+                        ]),
+                    );
+                }
+            }
+        }
+    }
+
+    // DataLoader workers crash with a bus error once /dev/shm is full,
+    // and containers only get 64MB by default
+    if let Some(shm) = machine
+        .shm
+        .as_ref()
+        .filter(|shm| shm.used_pct > SHM_FULL_THRESHOLD)
+    {
+        findings.push(
+            Finding::warn(
+                Kind::ShmFull,
+                format!(
+                    "/dev/shm is {:.0}% full ({}), DataLoader workers may be killed with a bus error. Raise it with `docker run --shm-size` or reduce num_workers",
+                    shm.used_pct,
+                    shm.display_usage()
+                ),
+            )
+            .with_evidence(vec![
+                ("used_pct", shm.used_pct.into()),
+            ]),
+        );
+    }
+
+    // swapping stalls the dataloader, which starves the GPUs
+    if let Some(swap_in_rate) = machine.swap_in_rate.filter(|rate| *rate > 0.0) {
+        findings.push(
+            Finding::warn(
+                Kind::Swapping,
+                format!(
+                    "System is actively swapping ({:.0} pages/s swapped in, Swap: {}), this is a likely cause of low GPU utilization",
+                    swap_in_rate,
+                    machine.cpu.display_swap()
+                ),
+            )
+            .with_evidence(vec![
+                ("pages_swapped_in_per_sec", swap_in_rate.into()),
+            ]),
+        );
+    }
+
+    // legacy persistence mode is deprecated in favour of the daemon
+    let persistent_gpus = machine
+        .gpus
+        .iter()
+        .filter(|gpu| gpu.persistence_mode)
+        .map(|gpu| gpu.idx.to_string())
+        .collect::<Vec<String>>();
+    if !persistent_gpus.is_empty() && !machine.persistence_daemon_running {
+        findings.push(
+            Finding::new(
+                Severity::Info,
+                Kind::PersistenceDaemonNotRunning,
+                format!(
+                    "Persistence mode is enabled on GPU(s) {} but nvidia-persistenced isn't running, legacy persistence mode is deprecated in favour of the daemon",
+                    persistent_gpus.join(", ")
+                ),
+            )
+            .with_evidence(vec![("gpus", persistent_gpus.into())]),
+        );
+    }
+
+    for gpu in &machine.gpus {
+        // idle GPUs always report GpuIdle, which isn't worth mentioning
+        let throttling = gpu.throttling.difference(BENIGN_THROTTLE_REASONS);
+        if !throttling.is_empty() {
+            let remediations = THROTTLE_REMEDIATIONS
+                .iter()
+                .filter(|(reason, _, _)| throttling.contains(*reason))
+                .map(|(_, name, remediation)| format!("{} -> {}", name, remediation))
+                .collect();
+            findings.push(
+                Finding::new(
+                    throttle_severity(throttling),
+                    throttle_kind(throttling),
+                    format!(
+                        "GPU {} is throttling due to: {}",
+                        gpu.idx,
+                        throttle_reason_names(throttling).join(", ")
+                    ),
+                )
+                .on_gpu(gpu.idx)
+                .with_details(remediations)
+                .with_evidence(vec![
+                    ("reasons", throttle_reason_names(throttling).into()),
+                    ("temp", gpu.temp.into()),
+                    ("power_usage_mw", gpu.power.0.into()),
+                    ("power_limit_mw", gpu.power.1.into()),
+                ]),
+            );
+        }
+
+        // copies saturating the link leave the SMs waiting, e.g. when
+        // batches are moved from pageable host memory every step
+        let ((gen, width), _) = gpu.pcie_link;
+        let pcie_bound = pcie_bandwidth_gbps(gen, width).is_some_and(|bandwidth| {
+            gpu.pcie_throughput_gbps() >= PCIE_SATURATION_FRACTION * bandwidth
+        }) && gpu.utilizations.0 < thresholds.low_util;
+        if pcie_bound {
+            findings.push(
+                Finding::warn(
+                    Kind::PcieBound,
+                    format!(
+                        "GPU {} looks PCIe-bound ({:.1} GB/s on a Gen{} x{} link) with {}% utilization, try pinned memory, larger batches or keeping data on the GPU",
+                        gpu.idx,
+                        gpu.pcie_throughput_gbps(),
+                        gen,
+                        width,
+                        gpu.utilizations.0
+                    ),
+                )
+                .on_gpu(gpu.idx)
+                .with_evidence(vec![
+                    ("gpu_utilization", gpu.utilizations.0.into()),
+                    ("pcie_tx_kbps", gpu.pcie_throughput.0.into()),
+                    ("pcie_rx_kbps", gpu.pcie_throughput.1.into()),
+                    ("link_gen", gen.into()),
+                    ("link_width", width.into()),
+                ]),
+            );
+        }
+
+        // idle GPUs downshift their link to save power, so only
+        // a degraded link under load (compute or copies) is worth reporting
+        if gpu.pcie_link_degraded() && (gpu.utilizations.0 >= PCIE_LOAD_THRESHOLD || pcie_bound) {
+            let ((gen, width), (max_gen, max_width)) = gpu.pcie_link;
+            findings.push(
+                Finding::warn(
+                    Kind::PcieLinkDegraded,
+                    format!(
+                        "GPU {} PCIe link is running at Gen{} x{} under load (max Gen{} x{}), which can slow host-to-device copies",
+                        gpu.idx, gen, width, max_gen, max_width
+                    ),
+                )
+                .on_gpu(gpu.idx)
+                .with_evidence(vec![
+                    ("link_gen", gen.into()),
+                    ("link_width", width.into()),
+                    ("max_link_gen", max_gen.into()),
+                    ("max_link_width", max_width.into()),
+                ]),
+            );
+        }
+    }
+    findings
+}
+
+/// A guess at what limits a training job on one GPU, for `--bottleneck`.
+/// Unlike a `Finding`, it's a suggestion rather than a problem, so several can
+/// apply to one GPU, most likely first.
+#[derive(Debug, PartialEq)]
+pub struct BottleneckHint {
+    pub gpu_idx: Option<u32>, // None for host-wide hints
+    pub message: String,
+    pub confidence: f32, // from 0 to 1
+}
+
+impl BottleneckHint {
+    fn new(gpu_idx: u32, message: String, confidence: f32) -> Self {
+        Self {
+            gpu_idx: Some(gpu_idx),
+            message,
+            confidence,
+        }
+    }
+
+    /// e.g. "GPU 0: GPU util high (97%) ... (70% confidence)"
+    pub fn format(&self) -> String {
+        let prefix = match self.gpu_idx {
+            Some(idx) => format!("GPU {}: ", idx),
+            None => String::new(),
+        };
+        format!(
+            "{}{} ({:.0}% confidence)",
+            prefix,
+            self.message,
+            self.confidence * 100.0
+        )
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("gpu", self.gpu_idx.into()),
+            ("message", (&self.message).into()),
+            ("confidence", self.confidence.into()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        schema::object(vec![
+            ("gpu", schema::nullable(schema::integer())),
+            ("message", schema::string()),
+            ("confidence", schema::number()),
+        ])
+    }
+}
+
+// findings about a GPU that make the CPU-bound and IO-bound hints redundant
+const CPU_BOUND_KINDS: [Kind; 2] = [Kind::InputPipelineCpu, Kind::CpuLoad];
+const IO_BOUND_KINDS: [Kind; 4] = [
+    Kind::InputPipelineIo,
+    Kind::DiskBusy,
+    Kind::IoPressure,
+    Kind::SlowNetworkFilesystem,
+];
+
+impl Machine {
+    /// The likely bottlenecks of the GPUs running compute processes, most
+    /// confident first. Needs the stats in `REQUIRED_STATS`. A CPU-bound or
+    /// IO-bound input pipeline that `findings` (from `diagnose`) already report
+    /// for a GPU isn't repeated as a hint.
+    pub fn detect_bottleneck(
+        &self,
+        thresholds: &Thresholds,
+        findings: &[Finding],
+    ) -> Vec<BottleneckHint> {
+        let cores = available_cores(self);
+        let iowait = self.disk.as_ref().map_or(0.0, |disk| disk.iowait_pct);
+        let mut hints = vec![];
+        for gpu in &self.gpus {
+            let has_compute = gpu
+                .process_types
+                .values()
+                .any(|process_type| process_type.contains('C'));
+            if !has_compute {
+                continue;
+            }
+            // the lowest sample, so a single busy reading doesn't hide starvation
+            let utilization = gpu
+                .utilization_samples
+                .iter()
+                .copied()
+                .min()
+                .unwrap_or(gpu.utilizations.0);
+            let ((gen, width), _) = gpu.pcie_link;
+            let pcie_share = pcie_bandwidth_gbps(gen, width)
+                .map(|bandwidth| gpu.pcie_throughput_gbps() / bandwidth);
+
+            if utilization < thresholds.low_util {
+                let cpu_pct = gpu_process_cpu_pct(self, gpu.idx);
+                let reported = |kinds: &[Kind]| {
+                    findings.iter().any(|finding| {
+                        finding.gpu == Some(gpu.idx) && kinds.contains(&finding.kind)
+                    })
+                };
+                let cpu_reported = reported(&CPU_BOUND_KINDS);
+                let io_reported = reported(&IO_BOUND_KINDS);
+                // a reported cause isn't a reason to guess at synchronization either
+                let mut found = cpu_reported || io_reported;
+                if cpu_pct >= INPUT_PIPELINE_CPU_FRACTION * 100.0 * cores && !cpu_reported {
+                    found = true;
+                    hints.push(BottleneckHint::new(
+                        gpu.idx,
+                        format!(
+                            "GPU util low ({}%) while its processes use all {} cores ({:.0}% CPU): likely CPU-bound preprocessing, consider cheaper transforms or moving them to the GPU",
+                            utilization, cores, cpu_pct
+                        ),
+                        0.8,
+                    ));
+                }
+                if iowait > thresholds.iowait && !io_reported {
+                    found = true;
+                    hints.push(BottleneckHint::new(
+                        gpu.idx,
+                        format!(
+                            "GPU util low ({}%) but iowait high ({:.0}%): likely data loading bottleneck, consider increasing DataLoader workers or caching the dataset locally",
+                            utilization, iowait
+                        ),
+                        (0.5 + iowait / 100.0).min(0.9),
+                    ));
+                }
+                if pcie_share.is_some_and(|share| share >= PCIE_SATURATION_FRACTION) {
+                    found = true;
+                    hints.push(BottleneckHint::new(
+                        gpu.idx,
+                        format!(
+                            "GPU util low ({}%) but PCIe busy ({:.1} GB/s): likely host to device copies, consider pinned memory or larger batches",
+                            utilization,
+                            gpu.pcie_throughput_gbps()
+                        ),
+                        0.8,
+                    ));
+                }
+                if !found {
+                    hints.push(BottleneckHint::new(
+                        gpu.idx,
+                        format!(
+                            "GPU util low ({}%) with no busy host resource: likely synchronization (e.g. waiting on other ranks or host syncs) or batches too small to fill the GPU",
+                            utilization
+                        ),
+                        0.3,
+                    ));
+                }
+            } else if utilization >= SATURATED_UTILIZATION {
+                if gpu.utilizations.1 >= MEMORY_BANDWIDTH_BOUND {
+                    hints.push(BottleneckHint::new(
+                        gpu.idx,
+                        format!(
+                            "GPU util high ({}%) and memory bandwidth busy ({}%): training is memory-bandwidth-bound, consider mixed precision or fused kernels",
+                            utilization, gpu.utilizations.1
+                        ),
+                        0.6,
+                    ));
+                } else if pcie_share.is_some_and(|share| share < PCIE_IDLE_FRACTION) {
+                    hints.push(BottleneckHint::new(
+                        gpu.idx,
+                        format!(
+                            "GPU util high ({}%) but PCIe bandwidth low ({:.1} GB/s): model fits in VRAM, training is compute-bound",
+                            utilization,
+                            gpu.pcie_throughput_gbps()
+                        ),
+                        0.7,
+                    ));
+                }
+            }
+        }
+        hints.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        hints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::mock::MockDevice;
+    use crate::disk::{DeviceStats, DiskStats};
+    use crate::fs::FsStats;
+    use crate::netfs::NetFsStats;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn thresholds() -> Thresholds {
+        Thresholds {
+            temp: None,
+            memory_pct: 95.0,
+            iowait: 10.0,
+            low_util: 40,
+            ctxt: 10000.0,
+        }
+    }
+
+    #[test]
+    fn diagnoses_mock_gpus() {
+        let mut full = MockDevice::new(0);
+        full.memory = Some((79 * GIB, 80 * GIB));
+        full.compute_processes = Some(vec![(1234, 70 * GIB), (5678, 9 * GIB)]);
+        let mut hot = MockDevice::new(1);
+        hot.temperature = Some(84);
+        let mut failing = MockDevice::new(2);
+        failing.retired_pages_dbe = Some(3);
+        let machine = Machine::with_gpus(
+            [full, hot, failing, MockDevice::unsupported(3)]
+                .iter()
+                .map(GPUStats::from_device)
+                .collect(),
+        );
+
+        let memory = memory_diagnostics(&machine, 95.0);
+        assert_eq!(memory.len(), 1);
+        assert_eq!(memory[0].kind, Kind::MemoryNearlyFull);
+        assert_eq!(memory[0].gpu, Some(0));
+        assert_eq!(memory[0].details[0], "pid 1234 uses 70.0GB");
+
+        // the unsupported GPU reports 0°C and no slowdown temperature, so is never hot
+        let health = health_check(&machine, &thresholds());
+        let kinds = health
+            .iter()
+            .map(|finding| (finding.gpu, finding.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                (Some(1), Kind::HighTemperature),
+                (Some(2), Kind::RetiredPages)
+            ]
+        );
+
+        let lenient = Thresholds {
+            temp: Some(85),
+            ..thresholds()
+        };
+        assert_eq!(health_check(&machine, &lenient).len(), 1);
+    }
+
+    #[test]
+    fn suggests_likely_bottlenecks() {
+        let mut compute_bound = MockDevice::new(0);
+        compute_bound.utilization = Some((97, 30));
+        compute_bound.compute_processes = Some(vec![(1234, GIB)]);
+        let mut starved = MockDevice::new(1);
+        starved.utilization = Some((23, 5));
+        starved.compute_processes = Some(vec![(5678, GIB)]);
+        let mut machine = Machine::with_gpus(
+            [compute_bound, starved, MockDevice::new(2)]
+                .iter()
+                .map(GPUStats::from_device)
+                .collect(),
+        );
+
+        let hints = machine.detect_bottleneck(&thresholds(), &[]);
+        let guesses = hints
+            .iter()
+            .map(|hint| (hint.gpu_idx, hint.confidence))
+            .collect::<Vec<_>>();
+        assert_eq!(guesses, vec![(Some(0), 0.7), (Some(1), 0.3)]);
+        assert!(hints[0]
+            .format()
+            .ends_with("compute-bound (70% confidence)"));
+
+        machine.disk = Some(DiskStats {
+            iowait_pct: 45.0,
+            steal_pct: 0.0,
+            idle_pct: 50.0,
+            user_pct: 5.0,
+            system_pct: 0.0,
+        });
+        let hints = machine.detect_bottleneck(&thresholds(), &[]);
+        assert_eq!(hints[0].gpu_idx, Some(1));
+        assert!(hints[0]
+            .message
+            .starts_with("GPU util low (23%) but iowait high (45%): likely data loading"));
+        assert_eq!(hints.len(), 2);
+
+        // the diagnosis already blames GPU 1 on IO, so --bottleneck only says it once
+        let findings = diagnose(&machine, &thresholds());
+        assert!(findings
+            .iter()
+            .any(|finding| finding.kind == Kind::InputPipelineIo && finding.gpu == Some(1)));
+        let hints = machine.detect_bottleneck(&thresholds(), &findings);
+        assert!(hints.iter().all(|hint| hint.gpu_idx != Some(1)));
+        assert_eq!(hints.len(), 1);
+    }
+
+    #[test]
+    fn diagnoses_swapping_from_the_sampled_rate() {
+        let mut machine = Machine::with_gpus(vec![]);
+        let swapping = |machine: &Machine| {
+            diagnose(machine, &thresholds())
+                .iter()
+                .any(|finding| finding.kind == Kind::Swapping)
+        };
+        assert!(!swapping(&machine));
+        machine.swap_in_rate = Some(0.0);
+        assert!(!swapping(&machine));
+        machine.swap_in_rate = Some(120.0);
+        assert!(swapping(&machine));
+    }
+
+    #[test]
+    fn required_stats_cover_the_io_diagnoses() {
+        let mut starved = MockDevice::new(0);
+        starved.utilization = Some((10, 5));
+        starved.compute_processes = Some(vec![(1234, GIB)]);
+        let mut machine = Machine::with_gpus(vec![GPUStats::from_device(&starved)]);
+        machine.disk = Some(DiskStats {
+            iowait_pct: 45.0,
+            steal_pct: 0.0,
+            idle_pct: 50.0,
+            user_pct: 5.0,
+            system_pct: 0.0,
+        });
+        machine.devices = vec![DeviceStats {
+            name: "nvme0n1".to_string(),
+            read_bytes_per_sec: 2e9,
+            write_bytes_per_sec: 0.0,
+            iops: 20000.0,
+            util_pct: 99.0,
+            physical: true,
+        }];
+        machine.netfs = vec![NetFsStats {
+            mount: "/data".to_string(),
+            fstype: "nfs4".to_string(),
+            read_bytes_per_sec: 1e6,
+            ops_per_sec: 100.0,
+            avg_rpc_latency_ms: Some(500.0),
+        }];
+        machine.shm = Some(FsStats {
+            paths: vec!["/dev/shm".into()],
+            usage: (63 * 1024 * 1024, 64 * 1024 * 1024),
+            used_pct: 98.0,
+        });
+        machine.skip_uncollected(&REQUIRED_STATS);
+
+        let kinds = diagnose(&machine, &thresholds())
+            .into_iter()
+            .map(|finding| finding.kind)
+            .collect::<Vec<Kind>>();
+        for kind in [Kind::DiskBusy, Kind::SlowNetworkFilesystem, Kind::ShmFull] {
+            assert!(kinds.contains(&kind), "{:?} missing from {:?}", kind, kinds);
+        }
+    }
+
+    #[test]
+    fn classifies_throttle_reasons() {
+        assert_eq!(
+            throttle_severity(ThrottleReasons::HW_THERMAL_SLOWDOWN),
+            Severity::Crit
+        );
+        assert_eq!(
+            throttle_severity(ThrottleReasons::SW_POWER_CAP | ThrottleReasons::SW_THERMAL_SLOWDOWN),
+            Severity::Crit
+        );
+        assert_eq!(
+            throttle_severity(ThrottleReasons::SW_POWER_CAP),
+            Severity::Warn
+        );
+        assert_eq!(throttle_severity(ThrottleReasons::GPU_IDLE), Severity::Info);
+        assert_eq!(
+            throttle_severity(ThrottleReasons::SYNC_BOOST),
+            Severity::Info
+        );
+    }
+
+    #[test]
+    fn names_the_throttle_reasons() {
+        let mut capped = MockDevice::new(0);
+        capped.throttle_reasons = Some(ThrottleReasons::GPU_IDLE | ThrottleReasons::SW_POWER_CAP);
+        let machine = Machine::with_gpus(vec![GPUStats::from_device(&capped)]);
+        let messages = diagnose(&machine, &thresholds())
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect::<Vec<String>>();
+        assert!(
+            messages.contains(&"GPU 0 is throttling due to: SwPowerCap".to_string()),
+            "{:?}",
+            messages
+        );
+    }
+
+    #[test]
+    fn idle_needs_a_confirming_sample() {
+        assert!(is_idle(&[0, 1]));
+        assert!(!is_idle(&[0]));
+        // caught between kernels
+        assert!(!is_idle(&[0, 87]));
+        assert!(!is_idle(&[]));
+    }
+
+    #[test]
+    fn finds_the_straggler_gpu() {
+        let utilizations = [(0, 60), (1, 98), (2, 62), (3, 58)];
+        assert_eq!(find_straggler(&utilizations, 25), Some((1, 60.0)));
+        // the outlier is the idle GPU, not the busiest one
+        let utilizations = [(0, 98), (1, 97), (2, 96), (3, 60)];
+        assert_eq!(find_straggler(&utilizations, 25), Some((3, 97.0)));
+
+        // of two GPUs the busier one is named, whichever index it has
+        assert_eq!(find_straggler(&[(0, 60), (1, 98)], 25), Some((1, 60.0)));
+        assert_eq!(find_straggler(&[(0, 98), (1, 60)], 25), Some((0, 60.0)));
+        assert!(find_straggler(&[(0, 95), (1, 90)], 25).is_none());
+        // a single GPU has no peers to compare with
+        assert!(find_straggler(&[(0, 98)], 25).is_none());
+    }
+
+    #[test]
+    fn names_throttle_kinds_after_the_most_serious_reason() {
+        let thermal = ThrottleReasons::SW_POWER_CAP | ThrottleReasons::SW_THERMAL_SLOWDOWN;
+        assert_eq!(throttle_kind(thermal).id(), "thermal_throttle");
+        assert_eq!(
+            throttle_kind(ThrottleReasons::SW_POWER_CAP).id(),
+            "power_throttle"
+        );
+        assert_eq!(
+            throttle_kind(ThrottleReasons::SYNC_BOOST).id(),
+            "clock_throttle"
+        );
+    }
+
+    #[test]
+    fn findings_serialize_with_stable_keys() {
+        let finding = Finding::crit(Kind::ThermalThrottle, "GPU 0 is hot".to_string())
+            .on_gpu(0)
+            .with_evidence(vec![("temp", 91u32.into())]);
+        assert_eq!(
+            finding.to_json().to_string(),
+            r#"{"gpu":0,"kind":"thermal_throttle","severity":"critical","message":"GPU 0 is hot","details":[],"evidence":{"temp":91}}"#
+        );
+    }
+
+    #[test]
+    fn summarizes_findings() {
+        let finding = |severity| Finding::new(severity, Kind::CpuLoad, String::new());
+        let findings = vec![
+            finding(Severity::Warn),
+            finding(Severity::Info),
+            finding(Severity::Crit),
+            finding(Severity::Warn),
+        ];
+        assert_eq!(
+            summarize(&findings, 8),
+            "2 warnings, 1 critical issue on 8 GPUs"
+        );
+        assert_eq!(
+            summarize(&[finding(Severity::Info)], 1),
+            "No issues found on 1 GPU"
+        );
+        assert_eq!(
+            summarize(&[finding(Severity::Crit), finding(Severity::Crit)], 2),
+            "2 critical issues on 2 GPUs"
+        );
+    }
+
+    #[test]
+    fn warns_below_slowdown_temperature_unless_set() {
+        assert_eq!(temp_threshold(None, 90), Some(80));
+        assert_eq!(temp_threshold(Some(85), 90), Some(85));
+        // NVML reports no slowdown temperature on some consumer GPUs
+        assert_eq!(temp_threshold(None, 0), None);
+    }
+
+    #[test]
+    fn rejects_out_of_range_percentages() {
+        assert_eq!(parse_pct("97"), Ok(97.0));
+        assert_eq!(parse_pct("0.5"), Ok(0.5));
+        assert!(parse_pct("150").is_err());
+        assert!(parse_pct("-1").is_err());
+        assert!(parse_pct("lots").is_err());
+    }
+
+    #[test]
+    fn rejects_rates_that_arent_positive() {
+        assert_eq!(parse_rate("10000"), Ok(10000.0));
+        assert!(parse_rate("0").is_err());
+        assert!(parse_rate("-5").is_err());
+        assert!(parse_rate("inf").is_err());
+        assert!(parse_rate("NaN").is_err());
+    }
+}
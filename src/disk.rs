@@ -1,31 +1,355 @@
-use std::process::Command;
-
-pub fn get_io_stats() -> (String, String, String) {
-    let iostat = Command::new("iostat")
-        .arg("-c")
-        .output()
-        .expect("failed to execute iostat command");
-
-    let iostat_output = String::from_utf8(iostat.stdout).unwrap();
-
-    let iowait = iostat_output
-        .split_whitespace()
-        .rev()
-        .nth(2)
-        .unwrap()
-        .to_string();
-    let iowait = format!("{}%", iowait);
-
-    let steal = iostat_output
-        .split_whitespace()
-        .rev()
-        .nth(1)
-        .unwrap()
-        .to_string();
-
-    let steal = format!("{}%", steal);
-    let idle = iostat_output.split_whitespace().last().unwrap().to_string();
-    let idle = format!("{}%", idle);
-
-    (iowait, steal, idle)
+use std::fs;
+use std::time::Duration;
+#[cfg(feature = "render")]
+use tabled::Tabled;
+
+use crate::json::Json;
+use crate::schema;
+use crate::stat::CpuTimes;
+
+// /proc/diskstats always counts 512 byte sectors, regardless of the device
+const SECTOR_BYTES: u64 = 512;
+
+// virtual devices that are hidden unless --all-disks is passed
+const VIRTUAL_DEVICE_PREFIXES: [&str; 5] = ["loop", "ram", "dm-", "zram", "sr"];
+
+/// CPU time breakdown (%), in the same categories as `iostat -c`.
+pub struct DiskStats {
+    pub iowait_pct: f32,
+    pub steal_pct: f32,
+    pub idle_pct: f32,
+    pub user_pct: f32,
+    pub system_pct: f32,
+}
+
+impl DiskStats {
+    pub fn format_header(&self) -> String {
+        format!(
+            "IO Wait: {:.2}%  Steal: {:.2}%  Idle: {:.2}%",
+            self.iowait_pct, self.steal_pct, self.idle_pct
+        )
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("iowait_pct", self.iowait_pct.into()),
+            ("steal_pct", self.steal_pct.into()),
+            ("idle_pct", self.idle_pct.into()),
+            ("user_pct", self.user_pct.into()),
+            ("system_pct", self.system_pct.into()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        schema::object(vec![
+            ("iowait_pct", schema::number()),
+            ("steal_pct", schema::number()),
+            ("idle_pct", schema::number()),
+            ("user_pct", schema::number()),
+            ("system_pct", schema::number()),
+        ])
+    }
+}
+
+/// Computes the CPU time breakdown between two /proc/stat samples.
+/// Unlike `iostat -c` without an interval, this reflects current load rather
+/// than averages since boot.
+pub fn get_io_stats(before: &CpuTimes, after: &CpuTimes) -> DiskStats {
+    let total = after.total().saturating_sub(before.total());
+    let pct = |ticks: fn(&CpuTimes) -> u64| -> f32 {
+        if total == 0 {
+            return 0.0;
+        }
+        100.0 * ticks(after).saturating_sub(ticks(before)) as f32 / total as f32
+    };
+
+    DiskStats {
+        iowait_pct: pct(|t| t.iowait),
+        steal_pct: pct(|t| t.steal),
+        idle_pct: pct(|t| t.idle),
+        // same groupings as iostat
+        user_pct: pct(|t| t.user + t.nice),
+        system_pct: pct(|t| t.system + t.irq + t.softirq),
+    }
+}
+
+/// Throughput and utilization of a single block device, averaged over a sample window.
+#[cfg_attr(feature = "render", derive(Tabled))]
+#[cfg_attr(feature = "render", tabled(rename_all = "PascalCase"))]
+pub struct DeviceStats {
+    #[cfg_attr(feature = "render", tabled(rename = "Device"))]
+    pub name: String,
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "Read", display_with("Self::display_read", self))
+    )]
+    pub read_bytes_per_sec: f32,
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "Write", display_with("Self::display_write", self))
+    )]
+    pub write_bytes_per_sec: f32,
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "IOPS", display_with("Self::display_iops", self))
+    )]
+    pub iops: f32,
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "Util", display_with("Self::display_util", self))
+    )]
+    pub util_pct: f32,
+    // false for loop/ram/dm devices and partitions
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub physical: bool,
+}
+
+impl DeviceStats {
+    #[cfg(feature = "render")]
+    fn display_read(&self) -> String {
+        format!("{:.1}MB/s", self.read_bytes_per_sec / 1e6)
+    }
+
+    #[cfg(feature = "render")]
+    fn display_write(&self) -> String {
+        format!("{:.1}MB/s", self.write_bytes_per_sec / 1e6)
+    }
+
+    #[cfg(feature = "render")]
+    fn display_iops(&self) -> String {
+        format!("{:.0}", self.iops)
+    }
+
+    #[cfg(feature = "render")]
+    fn display_util(&self) -> String {
+        format!("{:.0}%", self.util_pct)
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("name", (&self.name).into()),
+            ("read_bytes_per_sec", self.read_bytes_per_sec.into()),
+            ("write_bytes_per_sec", self.write_bytes_per_sec.into()),
+            ("iops", self.iops.into()),
+            ("util_pct", self.util_pct.into()),
+            ("physical", self.physical.into()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        schema::object(vec![
+            ("name", schema::string()),
+            ("read_bytes_per_sec", schema::number()),
+            ("write_bytes_per_sec", schema::number()),
+            ("iops", schema::number()),
+            ("util_pct", schema::number()),
+            ("physical", schema::boolean()),
+        ])
+    }
+}
+
+/// Cumulative counters for one line of /proc/diskstats.
+pub struct DiskCounters {
+    name: String,
+    reads: u64,
+    sectors_read: u64,
+    writes: u64,
+    sectors_written: u64,
+    io_time: Duration, // spent doing IO
+}
+
+/// Returns the counters of every block device, or nothing if /proc/diskstats is unavailable.
+pub fn read_diskstats() -> Vec<DiskCounters> {
+    fs::read_to_string("/proc/diskstats")
+        .map(|diskstats| parse_diskstats(&diskstats))
+        .unwrap_or_default()
+}
+
+/// Parses lines like "259  0 nvme0n1 1228107 0 394968240 172708 ...",
+/// see Documentation/admin-guide/iostats.rst for the field order.
+fn parse_diskstats(diskstats: &str) -> Vec<DiskCounters> {
+    diskstats
+        .lines()
+        .filter_map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<&str>>();
+            let field = |i: usize| -> Option<u64> { fields.get(i)?.parse::<u64>().ok() };
+            Some(DiskCounters {
+                name: fields.get(2)?.to_string(),
+                reads: field(3)?,
+                sectors_read: field(5)?,
+                writes: field(7)?,
+                sectors_written: field(9)?,
+                io_time: Duration::from_millis(field(12)?),
+            })
+        })
+        .collect()
+}
+
+/// Computes per-device rates from two sets of counters taken `window` apart.
+/// Devices that appeared in between are skipped.
+pub fn get_device_stats(
+    before: &[DiskCounters],
+    after: &[DiskCounters],
+    window: Duration,
+) -> Vec<DeviceStats> {
+    let secs = window.as_secs_f32();
+    if secs == 0.0 {
+        return vec![];
+    }
+    after
+        .iter()
+        .filter_map(|after| {
+            let before = before.iter().find(|before| before.name == after.name)?;
+            let delta = |counter: fn(&DiskCounters) -> u64| {
+                counter(after).saturating_sub(counter(before)) as f32
+            };
+            Some(DeviceStats {
+                name: after.name.clone(),
+                read_bytes_per_sec: delta(|c| c.sectors_read) * SECTOR_BYTES as f32 / secs,
+                write_bytes_per_sec: delta(|c| c.sectors_written) * SECTOR_BYTES as f32 / secs,
+                iops: (delta(|c| c.reads) + delta(|c| c.writes)) / secs,
+                // the IO time can't exceed wall time, but clamp in case of rounding
+                util_pct: (100.0 * after.io_time.saturating_sub(before.io_time).as_secs_f32()
+                    / secs)
+                    .min(100.0),
+                physical: is_physical(&after.name),
+            })
+        })
+        .collect()
+}
+
+/// False for virtual devices (loop, ram, device-mapper etc.) and partitions.
+fn is_physical(name: &str) -> bool {
+    if VIRTUAL_DEVICE_PREFIXES
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+    {
+        return false;
+    }
+    !is_partition(name)
+}
+
+/// Partitions are named after their disk, e.g. sda1, vdb2, nvme0n1p1, or mmcblk0p1.
+fn is_partition(name: &str) -> bool {
+    if name.starts_with("nvme") || name.starts_with("mmcblk") {
+        // the disk itself ends in a digit too, so look for the "p<N>" suffix
+        let trimmed = name.trim_end_matches(|c: char| c.is_ascii_digit());
+        return trimmed.len() < name.len()
+            && trimmed.ends_with('p')
+            && trimmed[..trimmed.len() - 1].ends_with(|c: char| c.is_ascii_digit());
+    }
+    name.ends_with(|c: char| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stat::parse_proc_stat;
+
+    // captured 250ms apart on a 64 core node during a training run (truncated)
+    const BEFORE: &str = "\
+cpu  418223599 2207 31922671 3245717028 18207581 0 1180394 26610 0 0
+cpu0 6537391 34 498528 50701095 284460 0 18443 415 0 0
+intr 9050097753 26 0 0 0 0 0 0 0 0
+ctxt 16706401279
+btime 1697100000
+processes 41488912
+procs_running 20
+procs_blocked 5
+";
+    const AFTER: &str = "\
+cpu  418224199 2207 31922731 3245718088 18207831 0 1180414 26620 0 0
+cpu0 6537401 34 498529 50701112 284464 0 18443 415 0 0
+intr 9050161753 26 0 0 0 0 0 0 0 0
+ctxt 16706461279
+btime 1697100000
+processes 41488950
+procs_running 21
+procs_blocked 4
+";
+
+    #[test]
+    fn computes_breakdown_from_captured_samples() {
+        let before = parse_proc_stat(BEFORE).unwrap();
+        let after = parse_proc_stat(AFTER).unwrap();
+
+        // 2000 ticks elapsed: 600 user, 60 system, 1060 idle, 250 iowait, 20 softirq, 10 steal
+        let stats = get_io_stats(&before.cpu, &after.cpu);
+        assert_eq!(stats.user_pct, 30.0);
+        assert_eq!(stats.system_pct, 4.0);
+        assert_eq!(stats.idle_pct, 53.0);
+        assert_eq!(stats.iowait_pct, 12.5);
+        assert_eq!(stats.steal_pct, 0.5);
+        assert_eq!(
+            stats.format_header(),
+            "IO Wait: 12.50%  Steal: 0.50%  Idle: 53.00%"
+        );
+    }
+
+    // recorded 1s apart while a dataloader was reading from nvme0n1 (truncated)
+    const DISKSTATS_BEFORE: &str = "\
+   7       0 loop0 1512 0 31704 310 0 0 0 0 0 412 310 0 0 0 0 0 0
+ 259       0 nvme0n1 1228107 5 394968240 172708 903412 2121 73990016 1402001 0 1452430 1586613 0 0 0 0 0 0
+ 259       1 nvme0n1p1 1227900 5 394960000 172600 903412 2121 73990016 1402001 0 1452300 1586500 0 0 0 0 0 0
+   8       0 sda 31122 902 3982117 40211 1213 1507 101992 9033 0 45320 53286 0 0 0 0 0 0
+   8       1 sda1 31000 902 3981000 40200 1213 1507 101992 9033 0 45300 53270 0 0 0 0 0 0
+ 253       0 dm-0 29744 0 3943217 41522 2720 0 101992 19634 0 46180 61156 0 0 0 0 0 0
+";
+    const DISKSTATS_AFTER: &str = "\
+   7       0 loop0 1512 0 31704 310 0 0 0 0 0 412 310 0 0 0 0 0 0
+ 259       0 nvme0n1 1231107 5 395992240 172908 903512 2121 73994112 1402101 0 1453380 1586913 0 0 0 0 0 0
+ 259       1 nvme0n1p1 1230900 5 395984000 172800 903512 2121 73994112 1402101 0 1453250 1586800 0 0 0 0 0 0
+   8       0 sda 31122 902 3982117 40211 1213 1507 101992 9033 0 45320 53286 0 0 0 0 0 0
+   8       1 sda1 31000 902 3981000 40200 1213 1507 101992 9033 0 45300 53270 0 0 0 0 0 0
+ 253       0 dm-0 29744 0 3943217 41522 2720 0 101992 19634 0 46180 61156 0 0 0 0 0 0
+";
+
+    #[test]
+    fn computes_device_rates_from_recorded_snapshots() {
+        let before = parse_diskstats(DISKSTATS_BEFORE);
+        let after = parse_diskstats(DISKSTATS_AFTER);
+        let devices = get_device_stats(&before, &after, Duration::from_secs(1));
+        assert_eq!(devices.len(), 6);
+
+        let nvme = devices.iter().find(|d| d.name == "nvme0n1").unwrap();
+        // 1024000 sectors read and 4096 written, 3000 reads and 100 writes, 950ms busy
+        assert_eq!(nvme.read_bytes_per_sec, 1024000.0 * 512.0);
+        assert_eq!(nvme.write_bytes_per_sec, 4096.0 * 512.0);
+        assert_eq!(nvme.iops, 3100.0);
+        assert_eq!(nvme.util_pct, 95.0);
+        #[cfg(feature = "render")]
+        assert_eq!(nvme.display_read(), "524.3MB/s");
+
+        let sda = devices.iter().find(|d| d.name == "sda").unwrap();
+        assert_eq!(sda.iops, 0.0);
+        assert_eq!(sda.util_pct, 0.0);
+    }
+
+    #[test]
+    fn only_whole_physical_disks_are_physical() {
+        for name in ["sda", "vdb", "xvda", "nvme0n1", "nvme10n2", "mmcblk0"] {
+            assert!(is_physical(name), "{}", name);
+        }
+        for name in [
+            "sda1",
+            "nvme0n1p1",
+            "nvme10n2p12",
+            "mmcblk0p2",
+            "loop0",
+            "ram0",
+            "dm-0",
+            "zram0",
+            "sr0",
+        ] {
+            assert!(!is_physical(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn identical_samples_are_all_zero() {
+        let sample = parse_proc_stat(BEFORE).unwrap();
+        let stats = get_io_stats(&sample.cpu, &sample.cpu);
+        assert_eq!(stats.idle_pct, 0.0);
+        assert_eq!(stats.iowait_pct, 0.0);
+    }
 }
@@ -0,0 +1,29 @@
+use nvml_wrapper::error::NvmlError;
+use std::io;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum BmonError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: &'static str,
+        #[source]
+        source: io::Error,
+    },
+    #[error("NVML error: {0}")]
+    Nvml(#[from] NvmlError),
+    #[error("process {pid} doesn't exist")]
+    NoSuchProcess { pid: u32 },
+    #[error("not allowed to signal process {pid} owned by {user}, run bmon as {user} or root")]
+    NotPermitted { pid: u32, user: String },
+    // reading keys from or drawing to the terminal in the TUI
+    #[error("terminal error: {0}")]
+    Terminal(#[from] io::Error),
+    #[error("failed to send {signal} to process {pid}: {source}")]
+    Signal {
+        pid: u32,
+        signal: &'static str,
+        #[source]
+        source: io::Error,
+    },
+}
@@ -0,0 +1,423 @@
+use clap::ValueEnum;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::diagnostics::{diagnose, BottleneckHint, Finding, Thresholds};
+use crate::gpu::{throttle_reason_names, GPUStats};
+use crate::json::Json;
+use crate::render::Renderer;
+use crate::schema;
+use crate::{CollectOptions, Machine};
+
+/// How snapshots are printed, chosen with --format.
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// the usual tables
+    Table,
+    /// one JSON object per snapshot
+    Json,
+    /// one row per GPU, with a header before the first snapshot
+    Csv,
+    /// Prometheus text exposition format, e.g. for the node_exporter textfile collector
+    Prometheus,
+    /// InfluxDB line protocol, one line per GPU
+    Influx,
+}
+
+// (name, help, value) of the per-GPU metrics written by the CSV, Prometheus and Influx renderers
+type GpuField = (&'static str, &'static str, fn(&GPUStats) -> f64);
+
+const GPU_FIELDS: [GpuField; 7] = [
+    ("utilization_percent", "GPU utilization.", |gpu| {
+        gpu.utilizations.0 as f64
+    }),
+    (
+        "memory_utilization_percent",
+        "Share of time the memory interface was busy.",
+        |gpu| gpu.utilizations.1 as f64,
+    ),
+    ("memory_used_bytes", "GPU memory used.", |gpu| {
+        gpu.memory.0 .0 as f64
+    }),
+    ("memory_total_bytes", "GPU memory in total.", |gpu| {
+        gpu.memory.1 .0 as f64
+    }),
+    ("temperature_celsius", "GPU temperature.", |gpu| {
+        gpu.temp as f64
+    }),
+    ("power_usage_watts", "GPU power draw.", |gpu| {
+        gpu.power.0.as_watts() as f64
+    }),
+    ("power_limit_watts", "Enforced GPU power limit.", |gpu| {
+        gpu.power.1.as_watts() as f64
+    }),
+];
+
+/// Prints each snapshot as one line of JSON.
+pub struct JsonRenderer {
+    // whether to include the `diagnostics` array, with the thresholds for it
+    pub diagnostics: bool,
+    pub thresholds: Thresholds,
+}
+
+impl JsonRenderer {
+    /// The machine as JSON, with a `diagnostics` array of findings and a
+    /// `bottleneck_hints` array (empty if there are none) when the diagnosis is enabled.
+    pub fn to_json(&self, machine: &Machine) -> Json {
+        let mut json = machine.to_json();
+        if let (true, Json::Object(fields)) = (self.diagnostics, &mut json) {
+            let findings = diagnose(machine, &self.thresholds);
+            fields.push((
+                "diagnostics".to_string(),
+                Json::Array(findings.iter().map(Finding::to_json).collect()),
+            ));
+            let hints = machine.detect_bottleneck(&self.thresholds, &findings);
+            fields.push((
+                "bottleneck_hints".to_string(),
+                Json::Array(hints.iter().map(BottleneckHint::to_json).collect()),
+            ));
+        }
+        json
+    }
+
+    /// The JSON Schema (draft 7) of what `to_json` writes.
+    pub fn json_schema(&self) -> Json {
+        let mut schema = Machine::json_schema();
+        if self.diagnostics {
+            schema = schema::extend(
+                schema,
+                vec![
+                    ("diagnostics", schema::array(Finding::json_schema())),
+                    (
+                        "bottleneck_hints",
+                        schema::array(BottleneckHint::json_schema()),
+                    ),
+                ],
+            );
+        }
+        schema::document("bmon --json", schema)
+    }
+}
+
+impl Renderer for JsonRenderer {
+    fn render(&mut self, machine: &Machine, writer: &mut dyn Write) -> io::Result<()> {
+        writeln!(writer, "{}", self.to_json(machine))
+    }
+}
+
+/// Prints one row per GPU, so that watch mode appends to a single table.
+#[derive(Default)]
+pub struct CsvRenderer {
+    header_written: bool,
+}
+
+impl Renderer for CsvRenderer {
+    fn render(&mut self, machine: &Machine, writer: &mut dyn Write) -> io::Result<()> {
+        if !self.header_written {
+            let fields = GPU_FIELDS.iter().map(|(name, _, _)| *name);
+            let header = ["timestamp", "gpu", "name"]
+                .into_iter()
+                .chain(fields)
+                .chain(["throttling"])
+                .collect::<Vec<&str>>();
+            writeln!(writer, "{}", header.join(","))?;
+            self.header_written = true;
+        }
+        let timestamp = unix_time().as_secs();
+        for gpu in &machine.gpus {
+            let mut row = vec![
+                timestamp.to_string(),
+                gpu.idx.to_string(),
+                csv_field(&gpu.name),
+            ];
+            row.extend(
+                GPU_FIELDS
+                    .iter()
+                    .map(|(_, _, value)| value(gpu).to_string()),
+            );
+            row.push(csv_field(&throttle_reason_names(gpu.throttling).join("|")));
+            writeln!(writer, "{}", row.join(","))?;
+        }
+        Ok(())
+    }
+
+    fn collect_options(&self) -> CollectOptions {
+        CollectOptions::GPUS_ONLY
+    }
+}
+
+/// Prints gauges in the Prometheus text format, labelled by GPU index and name.
+pub struct PrometheusRenderer;
+
+impl Renderer for PrometheusRenderer {
+    fn render(&mut self, machine: &Machine, writer: &mut dyn Write) -> io::Result<()> {
+        for (name, help, value) in GPU_FIELDS {
+            writeln!(writer, "# HELP bmon_gpu_{} {}", name, help)?;
+            writeln!(writer, "# TYPE bmon_gpu_{} gauge", name)?;
+            for gpu in &machine.gpus {
+                writeln!(
+                    writer,
+                    "bmon_gpu_{}{{gpu=\"{}\",name=\"{}\"}} {}",
+                    name,
+                    gpu.idx,
+                    prometheus_label(&gpu.name),
+                    value(gpu)
+                )?;
+            }
+        }
+        if let Some(utilization) = machine.cpu_utilization {
+            writeln!(
+                writer,
+                "# HELP bmon_cpu_utilization_percent CPU utilization."
+            )?;
+            writeln!(writer, "# TYPE bmon_cpu_utilization_percent gauge")?;
+            writeln!(writer, "bmon_cpu_utilization_percent {}", utilization)?;
+        }
+        writeln!(writer, "# HELP bmon_load_average 1 minute load average.")?;
+        writeln!(writer, "# TYPE bmon_load_average gauge")?;
+        writeln!(writer, "bmon_load_average {}", machine.load_average.0)
+    }
+
+    fn collect_options(&self) -> CollectOptions {
+        CollectOptions::GPUS_ONLY.cpu(true)
+    }
+}
+
+/// Prints one InfluxDB line protocol point per GPU, tagged by index and name.
+pub struct InfluxRenderer;
+
+impl Renderer for InfluxRenderer {
+    fn render(&mut self, machine: &Machine, writer: &mut dyn Write) -> io::Result<()> {
+        let timestamp = unix_time().as_nanos();
+        for gpu in &machine.gpus {
+            let fields = GPU_FIELDS
+                .iter()
+                .map(|(name, _, value)| format!("{}={}", name, value(gpu)))
+                .collect::<Vec<String>>();
+            writeln!(
+                writer,
+                "bmon_gpu,gpu={},name={} {} {}",
+                gpu.idx,
+                influx_tag(&gpu.name),
+                fields.join(","),
+                timestamp
+            )?;
+        }
+        Ok(())
+    }
+
+    fn collect_options(&self) -> CollectOptions {
+        CollectOptions::GPUS_ONLY
+    }
+}
+
+fn unix_time() -> std::time::Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Quotes a CSV field if it contains a comma, quote, or newline (RFC 4180).
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Escapes a Prometheus label value.
+fn prometheus_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Escapes an InfluxDB tag value, in which commas, spaces, and equals signs are special.
+fn influx_tag(value: &str) -> String {
+    value
+        .replace(',', "\\,")
+        .replace(' ', "\\ ")
+        .replace('=', "\\=")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accounting::AccountingStats;
+    use crate::cgroup::CgroupLimits;
+    use crate::device::mock::MockDevice;
+    use crate::disk::{DeviceStats, DiskStats};
+    use crate::fs::FsStats;
+    use crate::net::InterfaceStats;
+    use crate::netfs::NetFsStats;
+    use crate::numa::NumaNode;
+    use crate::psi::{Pressure, PressureStats};
+    use crate::schema::violations;
+    use crate::units::Bytes;
+    use crate::{ProcessStats, Warning};
+    use nvml_wrapper::bitmasks::device::ThrottleReasons;
+
+    #[test]
+    fn escapes_values_for_each_format() {
+        assert_eq!(csv_field("NVIDIA A100"), "NVIDIA A100");
+        assert_eq!(csv_field("A100, 80GB"), "\"A100, 80GB\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+
+        assert_eq!(prometheus_label("a \"b\" \\c"), "a \\\"b\\\" \\\\c");
+
+        assert_eq!(
+            influx_tag("NVIDIA A100-SXM4=80GB"),
+            "NVIDIA\\ A100-SXM4\\=80GB"
+        );
+    }
+
+    #[test]
+    fn csv_lists_throttle_reasons_by_name() {
+        let mut device = MockDevice::new(0);
+        device.throttle_reasons = Some(ThrottleReasons::GPU_IDLE | ThrottleReasons::SW_POWER_CAP);
+        let machine = Machine::with_gpus(vec![GPUStats::from_device(&device)]);
+        let mut out = vec![];
+        CsvRenderer::default().render(&machine, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let row = out.lines().nth(1).unwrap();
+        assert!(row.ends_with(",GpuIdle|SwPowerCap"), "{}", row);
+    }
+
+    fn thresholds() -> Thresholds {
+        Thresholds {
+            temp: None,
+            memory_pct: 95.0,
+            iowait: 10.0,
+            low_util: 40,
+            ctxt: 10000.0,
+        }
+    }
+
+    // a machine with most of its optional stats filled in, and findings to serialize
+    fn busy_machine() -> Machine {
+        let mut starved = MockDevice::new(0);
+        starved.utilization = Some((10, 30));
+        starved.compute_processes = Some(vec![(1234, 1024)]);
+        let mut machine = Machine::with_gpus(vec![
+            GPUStats::from_device(&starved),
+            GPUStats::from_device(&MockDevice::unsupported(1)),
+        ]);
+        let mut process = ProcessStats::exited(1234);
+        process.io_rates = Some((1e6, 0.0));
+        process.avg_sm_utilization = Some(10);
+        process.peak_gpu_memory = Some(Bytes(1024));
+        process.container_name = Some("trainer".to_string());
+        process.working_dir = Some("/workspace".to_string());
+        process.pgid = Some(1234);
+        process.local_world_size = Some(8);
+        process.k8s_pod = Some("trainer-0".to_string());
+        process.k8s_namespace = Some("default".to_string());
+        machine.processes = vec![process, ProcessStats::exited(5678)];
+        machine.accounting = vec![AccountingStats {
+            gpu: 0,
+            pid: 999,
+            utilizations: (Some(80), None),
+            max_memory: Some(Bytes(1024)),
+            time: std::time::Duration::from_secs(60),
+        }];
+        machine.accounting_enabled = true;
+        machine.cpu_temp = Some(55.0);
+        machine.cpu_utilization = Some(12.5);
+        machine.event_rates = Some((1e4, 2e4));
+        machine.cpu.cgroup = CgroupLimits {
+            cpus: Some(8.0),
+            memory: Some(1 << 30),
+            memory_used: Some(1 << 29),
+        };
+        machine.numa_nodes = vec![NumaNode {
+            id: 0,
+            cpulist: "0-7".to_string(),
+            memory: (1, 2),
+            gpus: vec![0, 1],
+        }];
+        machine.shm = Some(FsStats {
+            paths: vec!["/dev/shm".into()],
+            usage: (1, 2),
+            used_pct: 50.0,
+        });
+        machine.netfs = vec![
+            NetFsStats {
+                mount: "/data".to_string(),
+                fstype: "nfs4".to_string(),
+                read_bytes_per_sec: 1e6,
+                ops_per_sec: 100.0,
+                avg_rpc_latency_ms: Some(2.5),
+            },
+            NetFsStats {
+                mount: "/lustre".to_string(),
+                fstype: "lustre".to_string(),
+                read_bytes_per_sec: 0.0,
+                ops_per_sec: 0.0,
+                avg_rpc_latency_ms: None,
+            },
+        ];
+        machine.warnings = vec![Warning::partial(
+            "GPU 1: failed to query fan speed".to_string(),
+        )];
+        // high enough for a CPU-bound finding
+        machine.load_average = (40.0, 20.0, 10.0);
+        machine.disk = Some(DiskStats {
+            iowait_pct: 30.0,
+            steal_pct: 0.0,
+            idle_pct: 50.0,
+            user_pct: 15.0,
+            system_pct: 5.0,
+        });
+        machine.devices = vec![DeviceStats {
+            name: "nvme0n1".to_string(),
+            read_bytes_per_sec: 1e9,
+            write_bytes_per_sec: 0.0,
+            iops: 5000.0,
+            util_pct: 99.0,
+            physical: true,
+        }];
+        machine.interfaces = vec![InterfaceStats {
+            name: "eth0".to_string(),
+            rx_bytes_per_sec: 1e6,
+            tx_bytes_per_sec: 2e6,
+            physical: true,
+        }];
+        machine.filesystems = vec![FsStats {
+            paths: vec!["/".into()],
+            usage: (1, 2),
+            used_pct: 50.0,
+        }];
+        let pressure = Pressure {
+            some: 1.5,
+            full: 0.0,
+        };
+        machine.pressure = Some(PressureStats {
+            cpu: pressure,
+            memory: pressure,
+            io: pressure,
+        });
+        machine
+    }
+
+    #[test]
+    fn json_matches_its_schema() {
+        // the empty machine leaves out everything optional, and the busy one fills it in
+        let machines = [Machine::with_gpus(vec![]), busy_machine()];
+        assert!(!diagnose(&machines[1], &thresholds()).is_empty());
+        for (machine, diagnostics) in machines
+            .iter()
+            .flat_map(|machine| [(machine, false), (machine, true)])
+        {
+            let renderer = JsonRenderer {
+                diagnostics,
+                thresholds: thresholds(),
+            };
+            let json = renderer.to_json(machine);
+            let schema = renderer.json_schema();
+            assert_eq!(schema["$schema"].as_str(), Some(schema::DRAFT));
+            assert_eq!(violations(&schema, &json, "bmon"), Vec::<String>::new());
+        }
+    }
+}
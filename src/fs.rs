@@ -0,0 +1,193 @@
+use std::ffi::CString;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "render")]
+use tabled::Tabled;
+
+#[cfg(feature = "render")]
+use crate::color::{self, Color};
+use crate::json::Json;
+use crate::log::debug;
+use crate::schema;
+use crate::units::Bytes;
+
+// filesystem usage (%) above which a mount is highlighted
+#[cfg(feature = "render")]
+const FS_FULL_THRESHOLD: f32 = 90.0;
+
+/// Usage of one filesystem, which may be reachable through several of the requested paths.
+#[cfg_attr(feature = "render", derive(Tabled))]
+#[cfg_attr(feature = "render", tabled(rename_all = "PascalCase"))]
+pub struct FsStats {
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "Mount", display_with("Self::display_paths", self))
+    )]
+    pub paths: Vec<PathBuf>,
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "Used", display_with("Self::display_usage", self))
+    )]
+    pub usage: (u64, u64), // (used, total) in bytes
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "Use%", display_with("Self::display_used_pct", self))
+    )]
+    pub used_pct: f32,
+}
+
+impl FsStats {
+    #[cfg(feature = "render")]
+    fn display_paths(&self) -> String {
+        self.paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// e.g. `7.9G/8.0G`
+    pub fn display_usage(&self) -> String {
+        let (used, total) = self.usage;
+        format!("{}/{}", Bytes(used).human(), Bytes(total).human())
+    }
+
+    #[cfg(feature = "render")]
+    fn display_used_pct(&self) -> String {
+        let used_pct = format!("{:.0}%", self.used_pct);
+        if self.used_pct > FS_FULL_THRESHOLD {
+            color::paint(&used_pct, Color::Red)
+        } else {
+            used_pct
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            (
+                "paths",
+                self.paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<String>>()
+                    .into(),
+            ),
+            ("used_bytes", self.usage.0.into()),
+            ("total_bytes", self.usage.1.into()),
+            ("used_pct", self.used_pct.into()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        schema::object(vec![
+            ("paths", schema::array(schema::string())),
+            ("used_bytes", schema::integer()),
+            ("total_bytes", schema::integer()),
+            ("used_pct", schema::number()),
+        ])
+    }
+}
+
+/// The paths checked by --fs when none are given: the root, temp and
+/// shared memory mounts, and wherever the job is running from.
+pub fn default_fs_paths() -> Vec<PathBuf> {
+    let mut paths = vec![
+        PathBuf::from("/"),
+        PathBuf::from("/tmp"),
+        PathBuf::from("/dev/shm"),
+    ];
+    if let Ok(cwd) = std::env::current_dir() {
+        paths.push(cwd);
+    }
+    paths
+}
+
+/// Returns the usage of the filesystem behind each path. Paths on the same
+/// filesystem (e.g. bind mounts, or everything inside a container's overlay)
+/// are merged into one entry. Paths that don't exist are skipped.
+pub fn get_fs_stats(paths: &[PathBuf]) -> Vec<FsStats> {
+    let mut filesystems: Vec<(u64, FsStats)> = vec![]; // (device id, stats)
+    for path in paths {
+        let device = match fs::metadata(path) {
+            Ok(metadata) => metadata.dev(),
+            Err(e) => {
+                debug!("skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        if let Some((_, existing)) = filesystems.iter_mut().find(|(dev, _)| *dev == device) {
+            if !existing.paths.contains(path) {
+                existing.paths.push(path.clone());
+            }
+            continue;
+        }
+        if let Some(stats) = statvfs(path) {
+            filesystems.push((device, stats));
+        }
+    }
+    filesystems.into_iter().map(|(_, stats)| stats).collect()
+}
+
+/// Usage of /dev/shm, where PyTorch DataLoader workers pass batches to the main process.
+/// None if it isn't mounted.
+pub fn get_shm_stats() -> Option<FsStats> {
+    statvfs(Path::new("/dev/shm"))
+}
+
+fn statvfs(path: &Path) -> Option<FsStats> {
+    let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs is plain data and is filled in by the call
+    let mut buf: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut buf) } != 0 {
+        debug!(
+            "statvfs failed for {}: {}",
+            path.display(),
+            std::io::Error::last_os_error()
+        );
+        return None;
+    }
+
+    let block_size = buf.f_frsize as u64;
+    let total = buf.f_blocks as u64 * block_size;
+    let used = (buf.f_blocks as u64).saturating_sub(buf.f_bfree as u64) * block_size;
+    let available = buf.f_bavail as u64 * block_size;
+    // like df, blocks reserved for root count as neither used nor available
+    let used_pct = if used + available > 0 {
+        100.0 * used as f32 / (used + available) as f32
+    } else {
+        0.0
+    };
+    Some(FsStats {
+        paths: vec![path.to_path_buf()],
+        usage: (used, total),
+        used_pct,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_paths_on_the_same_filesystem() {
+        let dir = std::env::temp_dir();
+        let nested = dir.join(format!("bmon-fs-{}", std::process::id()));
+        fs::create_dir_all(&nested).unwrap();
+
+        let stats = get_fs_stats(&[dir.clone(), nested.clone(), dir.clone()]);
+        fs::remove_dir_all(&nested).unwrap();
+
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].paths, vec![dir, nested]);
+        let (used, total) = stats[0].usage;
+        assert!(total > 0 && used <= total);
+    }
+
+    #[test]
+    fn skips_missing_paths() {
+        let missing = PathBuf::from("/bmon-does-not-exist");
+        assert!(get_fs_stats(&[missing]).is_empty());
+    }
+}
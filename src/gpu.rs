@@ -1,107 +1,463 @@
+use nvml_wrapper::enums::device::UsedGpuMemory;
+use nvml_wrapper::error::NvmlError;
 use nvml_wrapper::{
-    bitmasks::device::ThrottleReasons, enum_wrappers::device::TemperatureSensor, Device, Nvml,
+    bitmasks::device::ThrottleReasons,
+    enum_wrappers::device::{
+        Brand, Clock, InfoRom, PcieUtilCounter, RetirementCause, TemperatureThreshold,
+    },
+    struct_wrappers::device::EncoderSessionInfo,
+    Nvml,
 };
+use std::collections::HashMap;
+use std::time::Duration;
+#[cfg(feature = "render")]
 use tabled::Tabled;
 
-#[derive(Tabled)]
-#[tabled(rename_all = "PascalCase")]
+use crate::clocks;
+#[cfg(feature = "render")]
+use crate::color::{self, Color};
+use crate::device::GpuDevice;
+use crate::json::Json;
+use crate::log::{debug, trace};
+use crate::numa::pci_numa_node;
+use crate::schema;
+use crate::units::{Bytes, Milliwatts};
+
+// GPUs within this many °C of their slowdown temperature are shown in yellow
+pub const TEMP_WARNING_MARGIN: u32 = 10;
+
+// throttle reasons that are expected rather than a problem: an idle GPU drops its
+// clocks, and application clocks are a deliberate setting
+pub const BENIGN_THROTTLE_REASONS: ThrottleReasons =
+    ThrottleReasons::GPU_IDLE.union(ThrottleReasons::APPLICATIONS_CLOCKS_SETTING);
+
+// what to do about each throttle reason, as (reason, name, remediation)
+pub const THROTTLE_REMEDIATIONS: [(ThrottleReasons, &str, &str); 7] = [
+    (
+        ThrottleReasons::SW_POWER_CAP,
+        "SwPowerCap",
+        "raise the power limit with `nvidia-smi -pl` (see LimitRange in verbose mode) or accept reduced clocks",
+    ),
+    (
+        ThrottleReasons::HW_SLOWDOWN,
+        "HwSlowdown",
+        "the GPU is overheating or its power supply can't keep up, check cooling and PSU/power cables",
+    ),
+    (
+        ThrottleReasons::HW_THERMAL_SLOWDOWN,
+        "HwThermalSlowdown",
+        "check fans/airflow; memory junction temp may be the limiter",
+    ),
+    (
+        ThrottleReasons::SW_THERMAL_SLOWDOWN,
+        "SwThermalSlowdown",
+        "the GPU is above its max operating temperature, check fans/airflow and the room temperature",
+    ),
+    (
+        ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN,
+        "HwPowerBrakeSlowdown",
+        "an external power brake (e.g. the chassis or PSU) is asserted, check the system's power delivery",
+    ),
+    (
+        ThrottleReasons::SYNC_BOOST,
+        "SyncBoost",
+        "another GPU in the sync group is limiting clocks, look for throttling on the other GPUs",
+    ),
+    (
+        ThrottleReasons::DISPLAY_CLOCK_SETTING,
+        "DisplayClockSetting",
+        "clocks are held down by the display clock setting, avoid driving a display from compute GPUs",
+    ),
+];
+
+#[cfg_attr(feature = "render", derive(Tabled))]
+#[cfg_attr(feature = "render", tabled(rename_all = "PascalCase"))]
 pub struct GPUStats {
     pub idx: u32,
-    #[tabled(display_with("Self::display_name", self))]
+    #[cfg_attr(feature = "render", tabled(display_with("Self::display_name", self)))]
     pub name: String,
-    #[tabled(display_with("Self::display_temp", self))]
+    #[cfg_attr(feature = "render", tabled(display_with("Self::display_temp", self)))]
     pub temp: u32,
-    #[tabled(display_with("Self::display_power", self))]
-    pub power: (u32, u32), // (usage, limit)
-    #[tabled(display_with("Self::display_utilizations", self))]
+    #[cfg_attr(feature = "render", tabled(display_with("Self::display_power", self)))]
+    pub power: (Milliwatts, Milliwatts), // (usage, limit)
+    // total energy since the driver was loaded in mJ, 0 if unsupported (pre-Volta)
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub energy_mj: u64,
+    // NB: memory utilization is the fraction of time the memory interface was
+    // busy, not the fraction of peak bandwidth. NVML has no DRAM read/write byte
+    // counters (only NVLink throughput fields), so bandwidth needs DCGM's profiling metrics
+    #[cfg_attr(
+        feature = "render",
+        tabled(display_with("Self::display_utilizations", self))
+    )]
     pub utilizations: (u32, u32), // (gpu, memory)
-    #[tabled(display_with("Self::display_memory", self))]
-    pub memory: (u64, u64), // (used, total) in bytes
+    #[cfg_attr(feature = "render", tabled(display_with("Self::display_memory", self)))]
+    pub memory: (Bytes, Bytes), // (used, total)
+    // the part of used memory the driver and firmware set aside, so that no process can
+    // allocate it, 0 if it can't be read (drivers before R510)
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub vram_reserved: Bytes,
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "Thr", display_with("Self::display_throttling", self))
+    )]
+    pub throttling: ThrottleReasons,
+    // only shown with --retired-pages
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "Retired", display_with("Self::display_retired_pages", self))
+    )]
+    pub retired_pages_sbe: u32,
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub retired_pages_dbe: u32,
+    // only shown with --thermal-limits or in verbose mode, in °C
+    #[cfg_attr(
+        feature = "render",
+        tabled(
+            rename = "ThermalLimits",
+            display_with("Self::display_thermal_limits", self)
+        )
+    )]
+    pub temp_slowdown: u32,
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub temp_shutdown: u32,
 
     // these are not displayed unless verbose is true
-    #[tabled(display_with("Self::display_capability", self))]
+    #[cfg_attr(
+        feature = "render",
+        tabled(display_with("Self::display_capability", self))
+    )]
     pub capability: (i32, i32), // (major, minor)
+    // valid range for `nvidia-smi -pl`, in milliwatts
+    #[cfg_attr(
+        feature = "render",
+        tabled(
+            rename = "LimitRange",
+            display_with("Self::display_power_limit_range", self)
+        )
+    )]
+    pub power_min_limit: Milliwatts,
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub power_max_limit: Milliwatts,
+    pub brand: String,
     pub cores: u32,
     pub fan: String,
     pub display: String,
-    #[tabled(display_with("Self::display_processes", self))]
+    #[cfg_attr(
+        feature = "render",
+        tabled(display_with("Self::display_encoder", self))
+    )]
+    pub encoder: Option<Vec<EncoderSessionInfo>>, // None if NVENC is not supported
+    #[cfg_attr(
+        feature = "render",
+        tabled(display_with("Self::display_pcie_link", self))
+    )]
+    pub pcie_link: ((u32, u32), (u32, u32)), // ((current gen, current width), (max gen, max width))
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub pcie_throughput: (u32, u32), // (tx, rx) in KB/s, sampled by NVML over 20ms
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub clocks: ((u32, u32), (u32, u32)), // ((SM, memory), (max SM, max memory)) in MHz
+    // firmware versions, mostly useful for hardware support tickets
+    #[cfg_attr(feature = "render", tabled(rename = "InfoROM"))]
+    pub inforom_version: String,
+    #[cfg_attr(feature = "render", tabled(rename = "VBIOS"))]
+    pub vbios_version: String,
+    #[cfg_attr(
+        feature = "render",
+        tabled(display_with("Self::display_processes", self))
+    )]
     pub processes: Vec<u32>,
+    // "C" for compute, "G" for graphics, or "C+G" for both
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub process_types: HashMap<u32, String>,
+    // GPU memory used by each process, for processes NVML reports it for
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub process_memory: HashMap<u32, Bytes>,
+    // whether the driver stays loaded with no clients, see nvidia-persistenced
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub persistence_mode: bool,
+    // NUMA node the PCIe bus is attached to, if any
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub numa_node: Option<u32>,
+    // GPU utilization (%) at the start and end of the sample window, so that
+    // diagnoses don't hinge on a single instantaneous reading
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub utilization_samples: Vec<u32>,
+    // queries that failed for a reason other than being unsupported, e.g.
+    // "failed to query temperature: Unknown Error". Their fields hold defaults
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub errors: Vec<String>,
+}
 
-    #[tabled(skip)]
-    pub throttling: ThrottleReasons,
+/// A GPU's rates between two snapshots, each None when unknown, e.g. because the
+/// energy counter restarted from 0 when the driver was reloaded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GpuRates {
+    pub idx: u32,
+    pub energy_j: Option<f64>, // consumed in between, from the energy counter
+    pub power_w: Option<f64>,  // average draw, which unlike `power` isn't a single reading
+    pub memory_growth: Option<f64>, // change in used memory in bytes/s, negative if freed
 }
 
 impl GPUStats {
-    pub fn from_nvml_device(device: Device) -> Self {
-        let idx = device.index().unwrap();
-        let name = device.name().unwrap();
+    /// Valid (memory clock, SM clocks) combinations in MHz, for `nvidia-smi --lock-gpu-clocks`.
+    /// Queried on demand rather than collected, since it takes an NVML call per memory clock.
+    /// `device` is the GPU these stats came from, and an empty table means it doesn't support the query.
+    pub fn supported_clocks_table(&self, device: &impl GpuDevice) -> Vec<(u32, Vec<u32>)> {
+        debug!("querying supported clocks of GPU {}", self.idx);
+        clocks::supported_clocks(device)
+    }
+
+    /// The rates since `previous`, a snapshot of the same index taken `elapsed` earlier.
+    /// They're unknown if a different GPU now has the index.
+    pub fn rates_since(&self, previous: &GPUStats, elapsed: Duration) -> GpuRates {
+        let secs = elapsed.as_secs_f64();
+        let same = previous.idx == self.idx && previous.name == self.name && secs > 0.0;
+        // GPUs without an energy counter always read 0
+        let energy_j = (same && previous.energy_mj > 0)
+            .then(|| self.energy_mj.checked_sub(previous.energy_mj))
+            .flatten()
+            .map(|energy_mj| energy_mj as f64 / 1000.0);
+        let memory_growth =
+            same.then(|| (self.memory.0 .0 as f64 - previous.memory.0 .0 as f64) / secs);
+        GpuRates {
+            idx: self.idx,
+            energy_j,
+            power_w: energy_j.map(|energy_j| energy_j / secs),
+            memory_growth,
+        }
+    }
+
+    pub fn from_device(device: &impl GpuDevice) -> Self {
+        // NVML queries can fail transiently or be unsupported on some SKUs,
+        // so fall back to defaults rather than crashing on a single error
+        let mut fallbacks = Fallbacks::default();
+        let idx = fallbacks.or_default(device.index(), 0, "index");
+        let name = fallbacks.or_default(device.name(), "N/A".to_string(), "name");
+
+        let temp = fallbacks.or_default(device.temperature(), 0, "temperature");
+        let temp_slowdown = fallbacks.or_default(
+            device.temperature_threshold(TemperatureThreshold::Slowdown),
+            0,
+            "slowdown temperature",
+        );
+        let temp_shutdown = fallbacks.or_default(
+            device.temperature_threshold(TemperatureThreshold::Shutdown),
+            0,
+            "shutdown temperature",
+        );
+
+        let power_usage = fallbacks.or_default(device.power_usage(), 0, "power usage");
+        let power_limit = fallbacks.or_default(device.enforced_power_limit(), 0, "power limit");
+        let power = (Milliwatts(power_usage), Milliwatts(power_limit));
+        let energy_mj =
+            fallbacks.or_default(device.total_energy_consumption(), 0, "energy consumption");
+        let (power_min_limit, power_max_limit) = fallbacks.or_default(
+            device
+                .power_limit_constraints()
+                .map(|(min, max)| (Milliwatts(min), Milliwatts(max))),
+            (Milliwatts(0), Milliwatts(0)),
+            "power limit constraints",
+        );
+
+        // one query per struct, so that its fields are read together and fail together
+        let utilizations = fallbacks.or_default(
+            device.utilization_rates().map(|u| (u.gpu, u.memory)),
+            (0, 0),
+            "utilization",
+        );
+        let memory = fallbacks.or_default(
+            device
+                .memory_info()
+                .map(|m| (Bytes(m.used), Bytes(m.total))),
+            (Bytes(0), Bytes(0)),
+            "memory",
+        );
+        let vram_reserved =
+            Bytes(fallbacks.or_default(device.memory_reserved(), 0, "reserved memory"));
 
-        let temp = device.temperature(TemperatureSensor::Gpu).unwrap();
+        let capability = fallbacks.or_default(
+            device.cuda_compute_capability(),
+            (0, 0),
+            "compute capability",
+        );
+        let cores = fallbacks.or_default(device.num_cores(), 0, "cores");
+        let brand = fallbacks.or_default(
+            device.brand().map(|brand| brand_name(&brand).to_string()),
+            "N/A".to_string(),
+            "brand",
+        );
 
-        let power_usage = device.power_usage().unwrap();
-        let power_limit = device.enforced_power_limit().unwrap();
-        let power = (power_usage, power_limit);
+        let throttling = fallbacks.or_default(
+            device.current_throttle_reasons(),
+            ThrottleReasons::empty(),
+            "throttle reasons",
+        );
 
-        let gpu_utilization = device.utilization_rates().unwrap().gpu;
-        let memory_utilization = device.utilization_rates().unwrap().memory;
-        let utilizations = (gpu_utilization, memory_utilization);
+        // pages retired due to ECC errors, not supported on consumer GPUs
+        let retired_pages_sbe = fallbacks.or_default(
+            device.retired_pages(RetirementCause::MultipleSingleBitEccErrors),
+            0,
+            "retired pages (sbe)",
+        );
+        let retired_pages_dbe = fallbacks.or_default(
+            device.retired_pages(RetirementCause::DoubleBitEccError),
+            0,
+            "retired pages (dbe)",
+        );
 
-        let memory_used = device.memory_info().unwrap().used;
-        let memory_total = device.memory_info().unwrap().total;
-        let memory = (memory_used, memory_total);
+        let current_link = (
+            fallbacks.or_default(device.current_pcie_link_gen(), 0, "pcie link gen"),
+            fallbacks.or_default(device.current_pcie_link_width(), 0, "pcie link width"),
+        );
+        let max_link = (
+            fallbacks.or_default(device.max_pcie_link_gen(), 0, "max pcie link gen"),
+            fallbacks.or_default(device.max_pcie_link_width(), 0, "max pcie link width"),
+        );
+        let pcie_link = (current_link, max_link);
+        let current_clocks = (
+            fallbacks.or_default(device.clock_info(Clock::SM), 0, "sm clock"),
+            fallbacks.or_default(device.clock_info(Clock::Memory), 0, "memory clock"),
+        );
+        let max_clocks = (
+            fallbacks.or_default(device.max_clock_info(Clock::SM), 0, "max sm clock"),
+            fallbacks.or_default(device.max_clock_info(Clock::Memory), 0, "max memory clock"),
+        );
+        let clocks = (current_clocks, max_clocks);
+        let pcie_throughput = (
+            fallbacks.or_default(
+                device.pcie_throughput(PcieUtilCounter::Send),
+                0,
+                "pcie tx throughput",
+            ),
+            fallbacks.or_default(
+                device.pcie_throughput(PcieUtilCounter::Receive),
+                0,
+                "pcie rx throughput",
+            ),
+        );
+        let numa_node = fallbacks.or_default(
+            device.pci_bus_id().map(|bus_id| pci_numa_node(&bus_id)),
+            None,
+            "pci info",
+        );
 
-        let compute_cap = device.cuda_compute_capability().unwrap();
-        let capability = (compute_cap.major, compute_cap.minor);
-        let cores = device.num_cores().unwrap();
+        let persistence_mode =
+            fallbacks.or_default(device.is_in_persistent_mode(), false, "persistence mode");
 
-        let throttling = device.current_throttle_reasons().unwrap();
+        let inforom_version = fallbacks.or_default(
+            device.info_rom_version(InfoRom::OEM),
+            "N/A".to_string(),
+            "inforom version",
+        );
+        let vbios_version =
+            fallbacks.or_default(device.vbios_version(), "N/A".to_string(), "vbios version");
 
-        let n_fans = device.num_fans().unwrap();
-        let fan = if n_fans == 0 {
+        // fans reports average speed of all fans that could be read
+        let n_fans = fallbacks.or_default(device.num_fans(), 0, "number of fans");
+        let fan_speeds = (0..n_fans)
+            .filter_map(|i| {
+                device
+                    .fan_speed(i)
+                    .map_err(|e| fallbacks.log_error("fan speed", e))
+                    .ok()
+            })
+            .collect::<Vec<u32>>();
+        let fan = if fan_speeds.is_empty() {
             "N/A".to_string()
         } else {
-            // fans reports average speed of all fans
-            let mut sum_fans = 0;
-            for i in 0..n_fans {
-                sum_fans += device.fan_speed(i).unwrap();
-            }
-            format!("{:>3}%", sum_fans / n_fans)
+            format!(
+                "{:>3}%",
+                fan_speeds.iter().sum::<u32>() / fan_speeds.len() as u32
+            )
         };
 
-        let display_connected = device.is_display_connected().unwrap();
-        let display_active = device.is_display_active().unwrap();
-        let display = if display_active {
-            "Active".to_string()
-        } else if display_connected {
-            "Connected".to_string()
-        } else {
-            "None".to_string()
+        let display_connected = device.is_display_connected();
+        let display_active = device.is_display_active();
+        let display = match (display_active, display_connected) {
+            (Ok(true), _) => "Active".to_string(),
+            (Ok(false), Ok(true)) => "Connected".to_string(),
+            (Ok(false), Ok(false)) => "None".to_string(),
+            (Err(e), _) | (_, Err(e)) => {
+                fallbacks.log_error("display", e);
+                "N/A".to_string()
+            }
         };
 
-        let compute_processes = device.running_compute_processes().unwrap();
-        let processes = compute_processes
+        // GPUs without NVENC report NotSupported here
+        let encoder = device.encoder_sessions().ok();
+
+        let compute_processes = fallbacks.or_default(
+            device.running_compute_processes(),
+            vec![],
+            "compute processes",
+        );
+        let graphics_processes = fallbacks.or_default(
+            device.running_graphics_processes(),
+            vec![],
+            "graphics processes",
+        );
+        let mut processes: Vec<u32> = vec![];
+        let mut process_types: HashMap<u32, String> = HashMap::new();
+        let mut process_memory: HashMap<u32, Bytes> = HashMap::new();
+        for (process, process_type) in compute_processes
             .iter()
-            .map(|process| process.pid)
-            .collect::<Vec<u32>>();
+            .map(|p| (p, "C"))
+            .chain(graphics_processes.iter().map(|p| (p, "G")))
+        {
+            // C+G processes are listed twice with the same allocation
+            if let UsedGpuMemory::Used(bytes) = process.used_gpu_memory {
+                process_memory.insert(process.pid, Bytes(bytes));
+            }
+            match process_types.get_mut(&process.pid) {
+                Some(existing) if existing != process_type => *existing = "C+G".to_string(),
+                Some(_) => {}
+                None => {
+                    processes.push(process.pid);
+                    process_types.insert(process.pid, process_type.to_string());
+                }
+            }
+        }
 
         Self {
             idx,
             name,
             temp,
             power,
+            energy_mj,
             utilizations,
+            utilization_samples: vec![utilizations.0],
+            errors: fallbacks.errors,
             memory,
+            vram_reserved,
+            throttling,
+            retired_pages_sbe,
+            retired_pages_dbe,
+            temp_slowdown,
+            temp_shutdown,
 
             capability,
+            power_min_limit,
+            power_max_limit,
+            brand,
             cores,
             fan,
             display,
+            encoder,
+            pcie_link,
+            pcie_throughput,
+            clocks,
+            inforom_version,
+            vbios_version,
             processes,
-
-            throttling,
+            process_types,
+            process_memory,
+            persistence_mode,
+            numa_node,
         }
     }
 
+    #[cfg(feature = "render")]
     fn display_name(&self) -> String {
         // IME, the names can be quite long but only the
         // last two words are really useful
@@ -116,27 +472,103 @@ impl GPUStats {
         words.join(" ")
     }
 
+    #[cfg(feature = "render")]
+    fn display_encoder(&self) -> String {
+        let sessions = match &self.encoder {
+            Some(sessions) => sessions,
+            None => return "N/A".to_string(),
+        };
+        if sessions.is_empty() {
+            return "0".to_string();
+        }
+
+        let n_sessions = sessions.len() as u32;
+        let avg_fps = sessions.iter().map(|s| s.average_fps).sum::<u32>() / n_sessions;
+        // latency is reported in microseconds
+        let avg_latency = sessions.iter().map(|s| s.average_latency).sum::<u32>() / n_sessions;
+        format!(
+            "{} sess @ {}fps {:.1}ms",
+            n_sessions,
+            avg_fps,
+            avg_latency as f32 / 1000.0
+        )
+    }
+
+    #[cfg(feature = "render")]
+    fn display_pcie_link(&self) -> String {
+        let ((gen, width), (max_gen, max_width)) = self.pcie_link;
+        format!("Gen{} x{} (max Gen{} x{})", gen, width, max_gen, max_width)
+    }
+
+    /// Traffic in the busier direction of the PCIe link, in GB/s.
+    pub fn pcie_throughput_gbps(&self) -> f32 {
+        let (tx, rx) = self.pcie_throughput;
+        tx.max(rx) as f32 * 1024.0 / 1e9
+    }
+
+    /// True if the PCIe link has negotiated below its max generation or width.
+    pub fn pcie_link_degraded(&self) -> bool {
+        let ((gen, width), (max_gen, max_width)) = self.pcie_link;
+        gen < max_gen || width < max_width
+    }
+
+    #[cfg(feature = "render")]
     fn display_processes(&self) -> String {
         let processes = self.processes.clone();
         processes
             .iter()
-            .map(|pid| pid.to_string())
+            .map(|pid| match self.process_types.get(pid) {
+                Some(process_type) => format!("{}({})", pid, process_type),
+                None => pid.to_string(),
+            })
             .collect::<Vec<String>>()
             .join(", ")
     }
 
+    /// Colored by how close the GPU is to its slowdown temperature, if known.
+    #[cfg(feature = "render")]
     fn display_temp(&self) -> String {
-        format!("{:>2}°C", self.temp)
+        let temp = format!("{:>2}°C", self.temp);
+        if self.temp_slowdown == 0 {
+            return temp;
+        }
+        let color = if self.temp >= self.temp_slowdown {
+            Color::Red
+        } else if self.temp + TEMP_WARNING_MARGIN >= self.temp_slowdown {
+            Color::Yellow
+        } else {
+            Color::Green
+        };
+        color::paint(&temp, color)
+    }
+
+    #[cfg(feature = "render")]
+    fn display_thermal_limits(&self) -> String {
+        format!(
+            "Slow@{}°C Shut@{}°C",
+            self.temp_slowdown, self.temp_shutdown
+        )
     }
 
+    #[cfg(feature = "render")]
     fn display_power(&self) -> String {
         let (power_usage, power_limit) = self.power;
         format!(
             "{:>3}W/{:>3}W",
-            (power_usage as f32 / 1000.0).round(),
-            (power_limit as f32 / 1000.0).round()
+            power_usage.as_watts().round(),
+            power_limit.as_watts().round()
         )
     }
+    #[cfg(feature = "render")]
+    fn display_power_limit_range(&self) -> String {
+        format!(
+            "{}W–{}W",
+            self.power_min_limit.as_watts().round(),
+            self.power_max_limit.as_watts().round()
+        )
+    }
+
+    #[cfg(feature = "render")]
     fn display_utilizations(&self) -> String {
         let (gpu_utilization, memory_utilization) = self.utilizations;
 
@@ -146,32 +578,685 @@ impl GPUStats {
         )
     }
 
+    /// Used/total, or used/reserved/total if the driver reserves any, e.g. "3.20GB/0.50GB/24.00GB".
+    #[cfg(feature = "render")]
     fn display_memory(&self) -> String {
         let (memory_used, memory_total) = self.memory;
+        match self.vram_reserved {
+            Bytes(0) => format!("{:>7}/{}", memory_used.gib(), memory_total.gib()),
+            reserved => format!(
+                "{:>7}/{}/{}",
+                memory_used.gib(),
+                reserved.gib(),
+                memory_total.gib()
+            ),
+        }
+    }
+
+    #[cfg(feature = "render")]
+    fn display_throttling(&self) -> String {
+        let codes = throttle_codes(self.throttling);
+        if codes.is_empty() {
+            "-".to_string()
+        } else {
+            codes.join(",")
+        }
+    }
+
+    #[cfg(feature = "render")]
+    fn display_retired_pages(&self) -> String {
         format!(
-            "{:>5}GB/{:.2}GB",
-            round_to_2dp(memory_used as f32 / 1024.0 / 1024.0 / 1024.0),
-            memory_total as f32 / 1024.0 / 1024.0 / 1024.0
+            "SBE {} DBE {}",
+            self.retired_pages_sbe, self.retired_pages_dbe
         )
     }
 
-    fn display_capability(&self) -> String {
+    pub(crate) fn display_capability(&self) -> String {
         let (major, minor) = self.capability;
         format!("{}.{}", major, minor)
     }
+
+    pub fn to_json(&self) -> Json {
+        let encoder = self.encoder.as_ref().map(|sessions| {
+            sessions
+                .iter()
+                .map(|session| {
+                    Json::object(vec![
+                        ("session_id", session.session_id.into()),
+                        ("pid", session.pid.into()),
+                        ("codec", format!("{:?}", session.codec_type).into()),
+                        ("hres", session.hres.into()),
+                        ("vres", session.vres.into()),
+                        ("average_fps", session.average_fps.into()),
+                        ("average_latency_us", session.average_latency.into()),
+                    ])
+                })
+                .collect::<Vec<Json>>()
+        });
+        let ((gen, width), (max_gen, max_width)) = self.pcie_link;
+        let ((sm_clock, memory_clock), (max_sm_clock, max_memory_clock)) = self.clocks;
+
+        Json::object(vec![
+            ("idx", self.idx.into()),
+            ("name", (&self.name).into()),
+            ("temp", self.temp.into()),
+            ("temp_slowdown", self.temp_slowdown.into()),
+            ("temp_shutdown", self.temp_shutdown.into()),
+            ("power_usage_mw", self.power.0.into()),
+            ("power_limit_mw", self.power.1.into()),
+            ("power_min_limit_mw", self.power_min_limit.into()),
+            ("power_max_limit_mw", self.power_max_limit.into()),
+            ("energy_mj", self.energy_mj.into()),
+            ("gpu_utilization", self.utilizations.0.into()),
+            ("memory_utilization", self.utilizations.1.into()),
+            (
+                "utilization_samples",
+                self.utilization_samples.clone().into(),
+            ),
+            ("memory_used_bytes", self.memory.0.into()),
+            ("memory_total_bytes", self.memory.1.into()),
+            ("memory_reserved_bytes", self.vram_reserved.into()),
+            ("capability", self.display_capability().into()),
+            ("brand", (&self.brand).into()),
+            ("cores", self.cores.into()),
+            ("fan", (&self.fan).into()),
+            ("display", (&self.display).into()),
+            ("encoder_sessions", encoder.into()),
+            ("pcie_link_gen", gen.into()),
+            ("pcie_link_width", width.into()),
+            ("pcie_link_max_gen", max_gen.into()),
+            ("pcie_link_max_width", max_width.into()),
+            ("pcie_tx_kbps", self.pcie_throughput.0.into()),
+            ("pcie_rx_kbps", self.pcie_throughput.1.into()),
+            ("sm_clock_mhz", sm_clock.into()),
+            ("memory_clock_mhz", memory_clock.into()),
+            ("max_sm_clock_mhz", max_sm_clock.into()),
+            ("max_memory_clock_mhz", max_memory_clock.into()),
+            ("inforom_version", (&self.inforom_version).into()),
+            ("vbios_version", (&self.vbios_version).into()),
+            ("processes", self.processes.clone().into()),
+            (
+                "process_types",
+                Json::Object(
+                    self.processes
+                        .iter()
+                        .filter_map(|pid| {
+                            let process_type = self.process_types.get(pid)?;
+                            Some((pid.to_string(), process_type.into()))
+                        })
+                        .collect(),
+                ),
+            ),
+            (
+                "process_memory_bytes",
+                Json::Object(
+                    self.processes
+                        .iter()
+                        .filter_map(|pid| {
+                            let bytes = self.process_memory.get(pid)?;
+                            Some((pid.to_string(), (*bytes).into()))
+                        })
+                        .collect(),
+                ),
+            ),
+            ("throttling", throttle_reason_names(self.throttling).into()),
+            ("retired_pages_sbe", self.retired_pages_sbe.into()),
+            ("retired_pages_dbe", self.retired_pages_dbe.into()),
+            ("persistence_mode", self.persistence_mode.into()),
+            ("numa_node", self.numa_node.into()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        let encoder_session = schema::object(vec![
+            ("session_id", schema::integer()),
+            ("pid", schema::integer()),
+            ("codec", schema::string()),
+            ("hres", schema::integer()),
+            ("vres", schema::integer()),
+            ("average_fps", schema::integer()),
+            ("average_latency_us", schema::integer()),
+        ]);
+        let integers = [
+            "idx",
+            "temp",
+            "temp_slowdown",
+            "temp_shutdown",
+            "power_usage_mw",
+            "power_limit_mw",
+            "power_min_limit_mw",
+            "power_max_limit_mw",
+            "energy_mj",
+            "gpu_utilization",
+            "memory_utilization",
+        ]
+        .map(|field| (field, schema::integer()));
+        let mut properties = vec![("name", schema::string())];
+        properties.extend(integers);
+        properties.extend([
+            ("utilization_samples", schema::array(schema::integer())),
+            ("memory_used_bytes", schema::integer()),
+            ("memory_total_bytes", schema::integer()),
+            ("memory_reserved_bytes", schema::integer()),
+            ("capability", schema::string()),
+            ("brand", schema::string()),
+            ("cores", schema::integer()),
+            ("fan", schema::string()),
+            ("display", schema::string()),
+            (
+                "encoder_sessions",
+                schema::nullable(schema::array(encoder_session)),
+            ),
+        ]);
+        properties.extend(
+            [
+                "pcie_link_gen",
+                "pcie_link_width",
+                "pcie_link_max_gen",
+                "pcie_link_max_width",
+                "pcie_tx_kbps",
+                "pcie_rx_kbps",
+                "sm_clock_mhz",
+                "memory_clock_mhz",
+                "max_sm_clock_mhz",
+                "max_memory_clock_mhz",
+            ]
+            .map(|field| (field, schema::integer())),
+        );
+        properties.extend([
+            ("inforom_version", schema::string()),
+            ("vbios_version", schema::string()),
+            ("processes", schema::array(schema::integer())),
+            // keyed by PID
+            ("process_types", schema::map(schema::string())),
+            ("process_memory_bytes", schema::map(schema::integer())),
+            ("throttling", schema::array(schema::string())),
+            ("retired_pages_sbe", schema::integer()),
+            ("retired_pages_dbe", schema::integer()),
+            ("persistence_mode", schema::boolean()),
+            ("numa_node", schema::nullable(schema::integer())),
+        ]);
+        schema::object(properties)
+    }
+}
+
+/// Compact codes for the active throttle reasons, always in the same order.
+/// Reasons without a code (e.g. application clock settings) are left out.
+#[cfg(feature = "render")]
+fn throttle_codes(reasons: ThrottleReasons) -> Vec<&'static str> {
+    let codes = [
+        (
+            ThrottleReasons::SW_POWER_CAP | ThrottleReasons::HW_POWER_BRAKE_SLOWDOWN,
+            "PWR",
+        ),
+        (
+            ThrottleReasons::SW_THERMAL_SLOWDOWN | ThrottleReasons::HW_THERMAL_SLOWDOWN,
+            "THM",
+        ),
+        (ThrottleReasons::HW_SLOWDOWN, "HW"),
+        (ThrottleReasons::GPU_IDLE, "IDL"),
+    ];
+    codes
+        .iter()
+        .filter(|(flags, _)| reasons.intersects(*flags))
+        .map(|(_, code)| *code)
+        .collect()
+}
+
+/// The names of the active throttle reasons, e.g. "SwPowerCap", always in the same order.
+pub fn throttle_reason_names(reasons: ThrottleReasons) -> Vec<&'static str> {
+    let benign = [
+        (ThrottleReasons::GPU_IDLE, "GpuIdle"),
+        (
+            ThrottleReasons::APPLICATIONS_CLOCKS_SETTING,
+            "ApplicationsClocksSetting",
+        ),
+    ];
+    benign
+        .into_iter()
+        .chain(
+            THROTTLE_REMEDIATIONS
+                .iter()
+                .map(|(reason, name, _)| (*reason, *name)),
+        )
+        .filter(|(reason, _)| reasons.contains(*reason))
+        .map(|(_, name)| name)
+        .collect()
+}
+
+/// Records the NVML queries that fell back to defaults. Unsupported queries
+/// are expected on many GPUs, so only other errors are kept.
+#[derive(Default)]
+struct Fallbacks {
+    errors: Vec<String>,
+}
+
+impl Fallbacks {
+    /// Unwraps the result of an NVML query, falling back to `default` on error.
+    fn or_default<T>(&mut self, result: Result<T, NvmlError>, default: T, field: &str) -> T {
+        trace!("querying {}", field);
+        result.unwrap_or_else(|e| {
+            self.log_error(field, e);
+            default
+        })
+    }
+
+    fn log_error(&mut self, field: &str, e: NvmlError) {
+        debug!("failed to query {}, using default: {}", field, e);
+        if !matches!(e, NvmlError::NotSupported) {
+            self.errors
+                .push(format!("failed to query {}: {}", field, e));
+        }
+    }
+}
+
+/// Maps NVML's brand enum to a short, readable name.
+/// The many virtualization brands are grouped together as "vGPU".
+fn brand_name(brand: &Brand) -> &'static str {
+    match brand {
+        Brand::GeForce | Brand::GeForceRTX => "GeForce",
+        Brand::Quadro | Brand::QuadroRTX => "Quadro",
+        Brand::Tesla => "Datacenter",
+        Brand::Titan | Brand::TitanRTX => "Titan",
+        Brand::NvidiaRTX => "RTX",
+        Brand::Nvidia => "NVIDIA",
+        Brand::NVS => "NVS",
+        Brand::GRID | Brand::VApps | Brand::VPC | Brand::VCS | Brand::VWS => "vGPU",
+        Brand::CloudGaming | Brand::VGaming => "Cloud Gaming",
+        Brand::Unknown => "Unknown",
+    }
+}
+
+/// Usable bandwidth of a PCIe link in each direction, in GB/s, after line
+/// encoding overhead (8b/10b up to Gen2, 128b/130b from Gen3).
+/// None for generations bmon doesn't know about.
+pub fn pcie_bandwidth_gbps(gen: u32, width: u32) -> Option<f32> {
+    let per_lane = match gen {
+        1 => 0.25,
+        2 => 0.5,
+        3 => 0.985,
+        4 => 1.969,
+        5 => 3.938,
+        6 => 7.563,
+        _ => return None,
+    };
+    Some(per_lane * width as f32)
+}
+
+/// Totals and averages over all of a machine's GPUs, for one line per node.
+#[derive(Debug, PartialEq)]
+pub struct AggregateGPUStats {
+    pub num_gpus: usize,
+    // GPUs with at least one process
+    pub in_use: usize,
+    pub memory: (Bytes, Bytes), // (used, total)
+    pub avg_utilization: f32,
+    pub power: (Milliwatts, Milliwatts), // (usage, limit)
+    pub gpu_power: Vec<Milliwatts>,      // usage of each GPU, in order
+    // GPUs that don't report a temperature (0°C) are left out
+    pub avg_temp: Option<f32>,
+    pub max_temp: Option<u32>,
+}
+
+impl AggregateGPUStats {
+    pub fn from_gpus(gpus: &[GPUStats]) -> Self {
+        let temps = gpus
+            .iter()
+            .map(|gpu| gpu.temp)
+            .filter(|temp| *temp > 0)
+            .collect::<Vec<u32>>();
+        let avg = |values: &[u32]| -> Option<f32> {
+            (!values.is_empty()).then(|| values.iter().sum::<u32>() as f32 / values.len() as f32)
+        };
+        let utilizations = gpus
+            .iter()
+            .map(|gpu| gpu.utilizations.0)
+            .collect::<Vec<u32>>();
+        Self {
+            num_gpus: gpus.len(),
+            in_use: gpus.iter().filter(|gpu| !gpu.processes.is_empty()).count(),
+            memory: (
+                gpus.iter().map(|gpu| gpu.memory.0).sum(),
+                gpus.iter().map(|gpu| gpu.memory.1).sum(),
+            ),
+            avg_utilization: avg(&utilizations).unwrap_or(0.0),
+            power: (
+                gpus.iter().map(|gpu| gpu.power.0).sum(),
+                gpus.iter().map(|gpu| gpu.power.1).sum(),
+            ),
+            gpu_power: gpus.iter().map(|gpu| gpu.power.0).collect(),
+            avg_temp: avg(&temps),
+            max_temp: temps.iter().max().copied(),
+        }
+    }
+
+    /// e.g. "Total: 4 GPUs (3 in use) | VRAM 124/320GB | GPU Util avg 73% | Power 900W/1600W (300/300/280/20W) | Temp avg 71°C max 79°C",
+    /// with each GPU's power so that one drawing less than its peers stands out.
+    pub fn format(&self) -> String {
+        let gpu_power = if self.gpu_power.is_empty() {
+            String::new()
+        } else {
+            let watts = self
+                .gpu_power
+                .iter()
+                .map(|power| format!("{:.0}", power.as_watts()))
+                .collect::<Vec<String>>();
+            format!(" ({}W)", watts.join("/"))
+        };
+        let temp = match (self.avg_temp, self.max_temp) {
+            (Some(avg), Some(max)) => format!("avg {:.0}°C max {}°C", avg, max),
+            _ => "N/A".to_string(),
+        };
+        format!(
+            "Total: {} GPUs ({} in use) | VRAM {}/{}GB | GPU Util avg {:.0}% | Power {}W/{}W{} | Temp {}",
+            self.num_gpus,
+            self.in_use,
+            self.memory.0.as_gib().round(),
+            self.memory.1.as_gib().round(),
+            self.avg_utilization,
+            self.power.0.as_watts().round(),
+            self.power.1.as_watts().round(),
+            gpu_power,
+            temp
+        )
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("num_gpus", (self.num_gpus as u64).into()),
+            ("in_use", (self.in_use as u64).into()),
+            ("memory_used_bytes", self.memory.0.into()),
+            ("memory_total_bytes", self.memory.1.into()),
+            ("avg_gpu_utilization", self.avg_utilization.into()),
+            ("power_usage_mw", self.power.0.into()),
+            ("power_limit_mw", self.power.1.into()),
+            (
+                "gpu_power_usage_mw",
+                Json::Array(self.gpu_power.iter().map(|&power| power.into()).collect()),
+            ),
+            ("avg_temp", self.avg_temp.into()),
+            ("max_temp", self.max_temp.into()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        schema::object(vec![
+            ("num_gpus", schema::integer()),
+            ("in_use", schema::integer()),
+            ("memory_used_bytes", schema::integer()),
+            ("memory_total_bytes", schema::integer()),
+            ("avg_gpu_utilization", schema::number()),
+            ("power_usage_mw", schema::integer()),
+            ("power_limit_mw", schema::integer()),
+            ("gpu_power_usage_mw", schema::array(schema::integer())),
+            ("avg_temp", schema::nullable(schema::number())),
+            ("max_temp", schema::nullable(schema::integer())),
+        ])
+    }
 }
 
-fn round_to_2dp(num: f32) -> f32 {
-    (num * 100.0).round() / 100.0
+/// Versions of the installed driver stack.
+#[derive(Clone)]
+pub struct DriverStats {
+    pub cuda_version: String,
+    // as reported by NVML, e.g. 12020 for 12.2, for version comparisons
+    pub cuda_version_int: u32,
+    pub driver_version: String,
+    pub nvml_version: String,
+    // the same as GPUStats::errors
+    pub errors: Vec<String>,
 }
 
-pub fn get_driver_stats(nvml: &Nvml) -> (String, String) {
+pub fn get_driver_stats(nvml: &Nvml) -> DriverStats {
     // NB: cuda version begins as an int e.g. 12000
     // this is converted to a float e.g. 12.0
-    let cuda_version = nvml.sys_cuda_driver_version().unwrap();
-    let cuda_version = cuda_version as f32 / 1000.0;
-    let cuda_version = format!("{:.1}", cuda_version);
-    let driver_version = nvml.sys_driver_version().unwrap();
+    let mut fallbacks = Fallbacks::default();
+    let cuda_version_int = fallbacks.or_default(
+        nvml.sys_cuda_driver_version().map(|version| version as u32),
+        0,
+        "cuda version",
+    );
+    let cuda_version = format!("{:.1}", cuda_version_int as f32 / 1000.0);
+    let driver_version = fallbacks.or_default(
+        nvml.sys_driver_version(),
+        "N/A".to_string(),
+        "driver version",
+    );
+    let nvml_version =
+        fallbacks.or_default(nvml.sys_nvml_version(), "N/A".to_string(), "nvml version");
+
+    DriverStats {
+        cuda_version,
+        cuda_version_int,
+        driver_version,
+        nvml_version,
+        errors: fallbacks.errors,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::mock::MockDevice;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    #[test]
+    fn computes_rates_unless_the_counter_restarted() {
+        let at = |energy_mj: u64, memory_used: u64| {
+            let mut device = MockDevice::new(0);
+            device.energy = Some(energy_mj);
+            device.memory = Some((memory_used, 80 * GIB));
+            GPUStats::from_device(&device)
+        };
+        let elapsed = Duration::from_secs(10);
+        let rates = at(5_000_000, 2 * GIB).rates_since(&at(1_000_000, GIB), elapsed);
+        assert_eq!(rates.energy_j, Some(4000.0));
+        assert_eq!(rates.power_w, Some(400.0));
+        assert_eq!(rates.memory_growth, Some(GIB as f64 / 10.0));
+
+        // the driver was reloaded in between
+        let rates = at(1_000, GIB).rates_since(&at(1_000_000, GIB), elapsed);
+        assert_eq!((rates.energy_j, rates.power_w), (None, None));
+        assert_eq!(rates.memory_growth, Some(0.0));
+
+        // no energy counter
+        let rates = at(0, GIB).rates_since(&at(0, GIB), elapsed);
+        assert_eq!(rates.power_w, None);
+
+        let mut other = MockDevice::new(0);
+        other.name = Some("Tesla V100-SXM2-32GB".to_string());
+        let rates = GPUStats::from_device(&other).rates_since(&at(0, GIB), elapsed);
+        assert_eq!(rates.memory_growth, None);
+    }
+
+    #[test]
+    fn reads_a_busy_gpu() {
+        let mut device = MockDevice::new(2);
+        device.utilization = Some((97, 41));
+        device.memory = Some((60 * GIB, 80 * GIB));
+        device.memory_reserved = Some(GIB / 2);
+        device.num_fans = Some(2);
+        device.fan_speeds = vec![40, 50];
+        device.compute_processes = Some(vec![(1234, 59 * GIB)]);
+
+        let gpu = GPUStats::from_device(&device);
+        assert_eq!(gpu.idx, 2);
+        assert_eq!(gpu.name, "NVIDIA A100-SXM4-80GB");
+        assert_eq!(gpu.utilizations, (97, 41));
+        assert_eq!(gpu.utilization_samples, vec![97]);
+        assert_eq!(gpu.memory, (Bytes(60 * GIB), Bytes(80 * GIB)));
+        assert_eq!(gpu.vram_reserved, Bytes(GIB / 2));
+        #[cfg(feature = "render")]
+        assert_eq!(gpu.display_memory(), "60.00GB/0.50GB/80.00GB");
+        assert_eq!(gpu.power, (Milliwatts(60_000), Milliwatts(400_000)));
+        assert_eq!(gpu.temp_slowdown, 90);
+        assert_eq!(gpu.fan, " 45%");
+        assert_eq!(gpu.processes, vec![1234]);
+        assert_eq!(gpu.process_types[&1234], "C");
+        assert_eq!(gpu.process_memory[&1234], Bytes(59 * GIB));
+        assert_eq!(gpu.pcie_link, ((4, 16), (4, 16)));
+        assert_eq!(gpu.clocks, ((210, 1593), (1410, 1593)));
+    }
+
+    #[test]
+    fn aggregates_all_gpus() {
+        let mut busy = MockDevice::new(0);
+        busy.utilization = Some((90, 50));
+        busy.memory = Some((60 * GIB, 80 * GIB));
+        busy.temperature = Some(79);
+        busy.power_usage = Some(350_000);
+        busy.compute_processes = Some(vec![(1234, 60 * GIB)]);
+        let gpus = [busy, MockDevice::new(1), MockDevice::unsupported(2)]
+            .iter()
+            .map(GPUStats::from_device)
+            .collect::<Vec<GPUStats>>();
+
+        let aggregate = AggregateGPUStats::from_gpus(&gpus);
+        assert_eq!(aggregate.in_use, 1);
+        assert_eq!(aggregate.avg_utilization, 30.0);
+        // the unsupported GPU's 0°C is left out
+        assert_eq!(aggregate.avg_temp, Some(57.0));
+        assert_eq!(
+            aggregate.format(),
+            "Total: 3 GPUs (1 in use) | VRAM 60/160GB | GPU Util avg 30% | Power 410W/800W (350/60/0W) | Temp avg 57°C max 79°C"
+        );
+        assert_eq!(
+            AggregateGPUStats::from_gpus(&[]).format(),
+            "Total: 0 GPUs (0 in use) | VRAM 0/0GB | GPU Util avg 0% | Power 0W/0W | Temp N/A"
+        );
+    }
+
+    #[test]
+    fn unsupported_queries_fall_back_to_defaults() {
+        let gpu = GPUStats::from_device(&MockDevice::unsupported(0));
+        assert_eq!(gpu.name, "N/A");
+        assert_eq!(gpu.temp, 0);
+        assert_eq!(gpu.memory, (Bytes(0), Bytes(0)));
+        assert_eq!(gpu.fan, "N/A");
+        assert_eq!(gpu.brand, "N/A");
+        assert_eq!(gpu.throttling, ThrottleReasons::empty());
+        assert_eq!(gpu.numa_node, None);
+        assert!(gpu.encoder.is_none());
+        assert!(gpu.processes.is_empty());
+        #[cfg(feature = "render")]
+        assert_eq!(gpu.display_memory(), " 0.00GB/0.00GB");
+        #[cfg(feature = "render")]
+        assert_eq!(gpu.display_temp(), " 0°C");
+        // unsupported isn't an error worth warning about
+        assert!(gpu.errors.is_empty());
+    }
+
+    #[test]
+    fn failed_queries_only_lose_their_own_fields() {
+        let mut device = MockDevice::new(1);
+        device.utilization = Some((80, 20));
+        device.num_fans = Some(1);
+        device.errors = vec!["memory_info", "fan_speed", "running_compute_processes"];
+
+        let gpu = GPUStats::from_device(&device);
+        assert_eq!(gpu.memory, (Bytes(0), Bytes(0)));
+        assert_eq!(gpu.fan, "N/A");
+        assert!(gpu.processes.is_empty());
+        assert_eq!(gpu.utilizations, (80, 20));
+        assert_eq!(gpu.temp, 35);
+        let failed = gpu
+            .errors
+            .iter()
+            .map(|e| e.split(':').next().unwrap())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            failed,
+            vec![
+                "failed to query memory",
+                "failed to query fan speed",
+                "failed to query compute processes",
+            ]
+        );
+    }
+
+    #[test]
+    fn structs_are_queried_once() {
+        let device = MockDevice::new(0);
+        GPUStats::from_device(&device);
+        let queries = device.queries.borrow();
+        let count = |name: &str| queries.iter().filter(|query| **query == name).count();
+        assert_eq!(count("utilization_rates"), 1);
+        assert_eq!(count("memory_info"), 1);
+        // queries with an argument are made once per argument
+        assert_eq!(count("clock_info"), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "render")]
+    fn throttle_codes_are_in_a_fixed_order() {
+        assert!(throttle_codes(ThrottleReasons::empty()).is_empty());
+        assert!(throttle_codes(ThrottleReasons::NONE).is_empty());
+        assert_eq!(throttle_codes(ThrottleReasons::GPU_IDLE), vec!["IDL"]);
+
+        let combined = ThrottleReasons::GPU_IDLE
+            | ThrottleReasons::HW_SLOWDOWN
+            | ThrottleReasons::HW_THERMAL_SLOWDOWN
+            | ThrottleReasons::SW_POWER_CAP;
+        assert_eq!(throttle_codes(combined), vec!["PWR", "THM", "HW", "IDL"]);
 
-    (cuda_version, driver_version)
+        // both thermal flags collapse into a single code
+        let thermal = ThrottleReasons::SW_THERMAL_SLOWDOWN | ThrottleReasons::HW_THERMAL_SLOWDOWN;
+        assert_eq!(throttle_codes(thermal), vec!["THM"]);
+
+        // reasons without a code are ignored
+        let clocks = ThrottleReasons::APPLICATIONS_CLOCKS_SETTING | ThrottleReasons::SW_POWER_CAP;
+        assert_eq!(throttle_codes(clocks), vec!["PWR"]);
+    }
+
+    #[test]
+    fn serializes_throttle_reasons_by_name() {
+        let mut device = MockDevice::new(0);
+        device.throttle_reasons = Some(ThrottleReasons::SW_POWER_CAP | ThrottleReasons::GPU_IDLE);
+        let json = GPUStats::from_device(&device).to_json();
+        assert_eq!(
+            json["throttling"],
+            Json::from(vec!["GpuIdle", "SwPowerCap"])
+        );
+        assert_eq!(json["power_usage_mw"].as_i64(), Some(60_000));
+        assert_eq!(json["memory_total_bytes"].as_i64(), Some(80 << 30));
+        assert!(throttle_reason_names(ThrottleReasons::NONE).is_empty());
+    }
+
+    #[test]
+    fn pcie_bandwidth_scales_with_gen_and_width() {
+        assert_eq!(pcie_bandwidth_gbps(3, 16), Some(15.76));
+        assert_eq!(pcie_bandwidth_gbps(4, 16), Some(31.504));
+        assert_eq!(pcie_bandwidth_gbps(1, 1), Some(0.25));
+        // a degraded Gen4 x8 link is no faster than Gen3 x16
+        assert!(pcie_bandwidth_gbps(4, 8) <= pcie_bandwidth_gbps(3, 16));
+        assert_eq!(pcie_bandwidth_gbps(0, 16), None);
+    }
+
+    #[test]
+    fn brand_names_are_short_and_readable() {
+        let cases = [
+            (Brand::Unknown, "Unknown"),
+            (Brand::Quadro, "Quadro"),
+            (Brand::Tesla, "Datacenter"),
+            (Brand::NVS, "NVS"),
+            (Brand::GRID, "vGPU"),
+            (Brand::GeForce, "GeForce"),
+            (Brand::Titan, "Titan"),
+            (Brand::VApps, "vGPU"),
+            (Brand::VPC, "vGPU"),
+            (Brand::VCS, "vGPU"),
+            (Brand::VWS, "vGPU"),
+            (Brand::CloudGaming, "Cloud Gaming"),
+            (Brand::VGaming, "Cloud Gaming"),
+            (Brand::QuadroRTX, "Quadro"),
+            (Brand::NvidiaRTX, "RTX"),
+            (Brand::Nvidia, "NVIDIA"),
+            (Brand::GeForceRTX, "GeForce"),
+            (Brand::TitanRTX, "Titan"),
+        ];
+        for (brand, name) in cases {
+            assert_eq!(brand_name(&brand), name, "{:?}", brand);
+        }
+    }
 }
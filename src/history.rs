@@ -0,0 +1,382 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+#[cfg(feature = "render")]
+use tabled::Tabled;
+
+use crate::gpu::{GPUStats, GpuRates};
+
+// number of samples kept, one sparkline character each
+pub const HISTORY_SIZE: usize = 20;
+
+// samples needed before steady memory growth is called a leak,
+// so that a model still warming up isn't reported
+const MIN_LEAK_SAMPLES: usize = 5;
+
+const SPARK_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Rolling buffer of the most recent utilization samples (%) of one GPU.
+pub struct UtilizationHistory {
+    samples: VecDeque<u32>,
+    max_size: usize,
+}
+
+impl UtilizationHistory {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(max_size),
+            max_size,
+        }
+    }
+
+    /// Adds a sample, dropping the oldest one if the buffer is full.
+    pub fn push(&mut self, utilization: u32) {
+        if self.samples.len() == self.max_size {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(utilization);
+    }
+
+    /// e.g. `      ▁▁▂▅▇██▇▅▂▁▁█` with the newest sample on the right, padded on
+    /// the left to `max_size` characters so the column doesn't change width.
+    pub fn sparkline(&self) -> String {
+        let padding = " ".repeat(self.max_size - self.samples.len());
+        let spark = self
+            .samples
+            .iter()
+            .map(|sample| {
+                let level = (sample.min(&100) * (SPARK_CHARS.len() as u32 - 1) + 50) / 100;
+                SPARK_CHARS[level as usize]
+            })
+            .collect::<String>();
+        padding + &spark
+    }
+}
+
+/// Rolling buffer of the most recent memory usage samples of one GPU, for spotting leaks.
+pub struct MemoryTrend {
+    samples: VecDeque<(Instant, u64)>, // (taken at, used bytes)
+    max_size: usize,
+}
+
+impl MemoryTrend {
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(max_size),
+            max_size,
+        }
+    }
+
+    /// Adds a sample, dropping the oldest one if the buffer is full.
+    pub fn push(&mut self, at: Instant, used: u64) {
+        if self.samples.len() == self.max_size {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((at, used));
+    }
+
+    /// Growth in bytes per minute if usage never dropped and grew overall across
+    /// all samples, from a least squares fit. None otherwise, or with too few samples.
+    pub fn leak_rate(&self) -> Option<f64> {
+        if self.samples.len() < MIN_LEAK_SAMPLES {
+            return None;
+        }
+        let monotonic = self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .all(|((_, prev), (_, next))| next >= prev);
+        let (first_at, first_used) = *self.samples.front()?;
+        let (_, last_used) = *self.samples.back()?;
+        if !monotonic || last_used == first_used {
+            return None;
+        }
+
+        let points = self
+            .samples
+            .iter()
+            .map(|(at, used)| {
+                let minutes = at.duration_since(first_at).as_secs_f64() / 60.0;
+                (minutes, *used as f64)
+            })
+            .collect::<Vec<(f64, f64)>>();
+        let n = points.len() as f64;
+        let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let covariance = points
+            .iter()
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum::<f64>();
+        let variance = points
+            .iter()
+            .map(|(x, _)| (x - mean_x).powi(2))
+            .sum::<f64>();
+        (variance > 0.0).then(|| covariance / variance)
+    }
+}
+
+/// The highest readings of one GPU over a watch session.
+#[derive(Default)]
+#[cfg_attr(feature = "render", derive(Tabled))]
+#[cfg_attr(feature = "render", tabled(rename_all = "PascalCase"))]
+pub struct GpuSessionPeak {
+    pub idx: u32,
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "MaxTemp", display_with("Self::display_max_temp", self))
+    )]
+    pub max_temp: u32, // in °C
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "MaxGPU", display_with("Self::display_max_gpu_util", self))
+    )]
+    pub max_gpu_util: u32,
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "MaxMem", display_with("Self::display_max_mem_util", self))
+    )]
+    pub max_mem_util: u32,
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "MaxPower", display_with("Self::display_max_power", self))
+    )]
+    pub max_power_w: f32,
+    // consumed over the intervals where the energy counter could be read
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "Energy", display_with("Self::display_energy", self))
+    )]
+    pub energy_j: f64,
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "AvgPower", display_with("Self::display_watts_avg", self))
+    )]
+    pub watts_avg: f32,
+    #[cfg_attr(feature = "render", tabled(skip))]
+    energy_secs: f64, // length of those intervals
+}
+
+impl GpuSessionPeak {
+    pub fn new(idx: u32) -> Self {
+        Self {
+            idx,
+            ..Default::default()
+        }
+    }
+
+    /// Folds in the latest readings.
+    pub fn update(&mut self, stats: &GPUStats) {
+        self.max_temp = self.max_temp.max(stats.temp);
+        self.max_gpu_util = self.max_gpu_util.max(stats.utilizations.0);
+        self.max_mem_util = self.max_mem_util.max(stats.utilizations.1);
+        self.max_power_w = self.max_power_w.max(stats.power.0.as_watts());
+    }
+
+    /// Adds the energy consumed over the `elapsed` since the previous readings.
+    /// Intervals where it's unknown, e.g. across a driver reload, are left out.
+    pub fn add_rates(&mut self, rates: &GpuRates, elapsed: Duration) {
+        let Some(energy_j) = rates.energy_j else {
+            return;
+        };
+        self.energy_j += energy_j;
+        self.energy_secs += elapsed.as_secs_f64();
+        if self.energy_secs > 0.0 {
+            self.watts_avg = (self.energy_j / self.energy_secs) as f32;
+        }
+    }
+
+    /// False on GPUs without an energy counter, or before there were two readings.
+    pub fn has_energy(&self) -> bool {
+        self.energy_secs > 0.0
+    }
+
+    #[cfg(feature = "render")]
+    fn display_max_temp(&self) -> String {
+        format!("{}°C", self.max_temp)
+    }
+
+    #[cfg(feature = "render")]
+    fn display_max_gpu_util(&self) -> String {
+        format!("{}%", self.max_gpu_util)
+    }
+
+    #[cfg(feature = "render")]
+    fn display_max_mem_util(&self) -> String {
+        format!("{}%", self.max_mem_util)
+    }
+
+    #[cfg(feature = "render")]
+    fn display_max_power(&self) -> String {
+        format!("{:.0}W", self.max_power_w)
+    }
+
+    #[cfg(feature = "render")]
+    fn display_energy(&self) -> String {
+        if !self.has_energy() {
+            return "N/A".to_string();
+        }
+        format!("{:.0}J", self.energy_j)
+    }
+
+    #[cfg(feature = "render")]
+    fn display_watts_avg(&self) -> String {
+        if !self.has_energy() {
+            return "N/A".to_string();
+        }
+        format!("{:.0}W", self.watts_avg)
+    }
+}
+
+/// The energy one GPU used over a watch session: from its energy counter where it has one,
+/// otherwise integrated from its power draw, so it covers every GPU.
+#[derive(Default)]
+pub struct GpuSession {
+    pub idx: u32,
+    pub session_wh: f64,
+    // whether any interval was integrated from power draw instead of read from the counter
+    pub estimated: bool,
+}
+
+impl GpuSession {
+    pub fn new(idx: u32) -> Self {
+        Self {
+            idx,
+            ..Default::default()
+        }
+    }
+
+    /// Adds the `interval` since the previous readings: the energy counter's delta in `rates`
+    /// if known, otherwise the current power draw over the interval.
+    pub fn add(&mut self, stats: &GPUStats, rates: &GpuRates, interval: Duration) {
+        match rates.energy_j {
+            Some(energy_j) => self.session_wh += energy_j / 3600.0,
+            None => {
+                self.session_wh +=
+                    stats.power.0.as_watts() as f64 * interval.as_secs_f64() / 3600.0;
+                self.estimated = true;
+            }
+        }
+    }
+}
+
+/// e.g. "Session Energy Summary: GPU 0: 12.3 Wh, GPU 1: 11.8 Wh (estimated), Total: 24.1 Wh"
+pub fn session_energy_summary(sessions: &[GpuSession]) -> String {
+    let mut parts = sessions
+        .iter()
+        .map(|session| {
+            let estimated = if session.estimated {
+                " (estimated)"
+            } else {
+                ""
+            };
+            format!(
+                "GPU {}: {:.1} Wh{}",
+                session.idx, session.session_wh, estimated
+            )
+        })
+        .collect::<Vec<String>>();
+    parts.push(format!("Total: {:.1} Wh", session_total_wh(sessions)));
+    format!("Session Energy Summary: {}", parts.join(", "))
+}
+
+pub fn session_total_wh(sessions: &[GpuSession]) -> f64 {
+    sessions
+        .iter()
+        .fold(0.0, |total, session| total + session.session_wh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_samples() {
+        let mut history = UtilizationHistory::new(4);
+        for utilization in [0, 100, 50, 14, 30, 100] {
+            history.push(utilization);
+        }
+        assert_eq!(history.sparkline(), "▅▂▃█");
+    }
+
+    #[test]
+    fn averages_power_over_known_intervals() {
+        let rates = |energy_j: Option<f64>| GpuRates {
+            idx: 0,
+            energy_j,
+            power_w: None,
+            memory_growth: None,
+        };
+        let mut peak = GpuSessionPeak::new(0);
+        assert!(!peak.has_energy());
+        peak.add_rates(&rates(Some(3000.0)), Duration::from_secs(10));
+        // the driver was reloaded in between
+        peak.add_rates(&rates(None), Duration::from_secs(10));
+        peak.add_rates(&rates(Some(1000.0)), Duration::from_secs(10));
+        assert!(peak.has_energy());
+        assert_eq!(peak.energy_j, 4000.0);
+        assert_eq!(peak.watts_avg, 200.0);
+    }
+
+    #[test]
+    fn prefers_the_energy_counter_over_integrating_power() {
+        use crate::device::mock::MockDevice;
+
+        let mut sessions = vec![GpuSession::new(0), GpuSession::new(1)];
+        // GPU 0 has an energy counter, GPU 1 doesn't
+        for (session, energy_j) in sessions.iter_mut().zip([Some(1_080_000.0), None]) {
+            let mut device = MockDevice::new(session.idx);
+            device.power_usage = Some(100_000);
+            let gpu = GPUStats::from_device(&device);
+            let rates = GpuRates {
+                idx: session.idx,
+                energy_j,
+                power_w: None,
+                memory_growth: None,
+            };
+            // two 45 minute intervals
+            session.add(&gpu, &rates, Duration::from_secs(45 * 60));
+            session.add(&gpu, &rates, Duration::from_secs(45 * 60));
+        }
+        assert_eq!(
+            session_energy_summary(&sessions),
+            "Session Energy Summary: GPU 0: 600.0 Wh, GPU 1: 150.0 Wh (estimated), Total: 750.0 Wh"
+        );
+        assert_eq!(
+            session_energy_summary(&[]),
+            "Session Energy Summary: Total: 0.0 Wh"
+        );
+    }
+
+    #[test]
+    fn detects_steady_memory_growth() {
+        const MIB: u64 = 1024 * 1024;
+        let start = Instant::now();
+        let at = |secs: u64| start + std::time::Duration::from_secs(secs);
+
+        // +60MB every 30s, with one refresh where nothing changed
+        let mut trend = MemoryTrend::new(HISTORY_SIZE);
+        for (i, used) in [1000, 1060, 1120, 1120, 1240, 1300].iter().enumerate() {
+            trend.push(at(i as u64 * 30), used * MIB);
+        }
+        let rate = trend.leak_rate().unwrap() / MIB as f64;
+        assert!((110.0..130.0).contains(&rate), "rate was {}", rate);
+
+        // freeing memory at any point means it isn't leaking
+        trend.push(at(180), 1200 * MIB);
+        assert!(trend.leak_rate().is_none());
+
+        let mut flat = MemoryTrend::new(HISTORY_SIZE);
+        for i in 0..MIN_LEAK_SAMPLES as u64 {
+            flat.push(at(i), 1000 * MIB);
+        }
+        assert!(flat.leak_rate().is_none());
+    }
+
+    #[test]
+    fn pads_short_histories() {
+        let mut history = UtilizationHistory::new(HISTORY_SIZE);
+        history.push(0);
+        history.push(100);
+        assert_eq!(history.sparkline().chars().count(), HISTORY_SIZE);
+        assert!(history.sparkline().ends_with("▁█"));
+    }
+}
@@ -0,0 +1,204 @@
+use std::fs;
+use std::path::Path;
+
+// hwmon drivers that report CPU temperatures, most preferred first
+const CPU_DRIVERS: [&str; 4] = ["coretemp", "k10temp", "zenpower", "cpu_thermal"];
+
+// sensor labels for the whole package, most preferred first.
+// on AMD, Tctl may include an offset so Tdie is preferred when present
+const PACKAGE_LABELS: [&str; 4] = ["Package id", "Tdie", "Tctl", "Physical id"];
+
+/// Returns the CPU package temperature in °C, or None if no sensor is found (e.g. in VMs).
+pub fn get_cpu_temp() -> Option<f32> {
+    cpu_temp_from(Path::new("/sys/class/hwmon"))
+}
+
+/// Finds the CPU package temperature under a hwmon class directory.
+/// On multi-socket machines, the hottest package is returned.
+fn cpu_temp_from(hwmon_root: &Path) -> Option<f32> {
+    let devices = fs::read_dir(hwmon_root).ok()?;
+
+    let mut best: Option<(usize, f32)> = None; // (driver rank, temp)
+    for device in devices.flatten() {
+        let path = device.path();
+        let name = match fs::read_to_string(path.join("name")) {
+            Ok(name) => name.trim().to_string(),
+            Err(_) => continue,
+        };
+        let Some(rank) = CPU_DRIVERS.iter().position(|driver| *driver == name) else {
+            continue;
+        };
+        let Some(temp) = package_temp(&path) else {
+            continue;
+        };
+        best = match best {
+            Some((best_rank, best_temp)) if best_rank < rank => Some((best_rank, best_temp)),
+            Some((best_rank, best_temp)) if best_rank == rank => {
+                Some((best_rank, best_temp.max(temp)))
+            }
+            _ => Some((rank, temp)),
+        };
+    }
+    best.map(|(_, temp)| temp)
+}
+
+/// Reads the package sensor of a single hwmon device, preferring labelled
+/// package sensors over per-core ones, and falling back to temp1.
+fn package_temp(device: &Path) -> Option<f32> {
+    let mut sensors = vec![]; // (label rank, temp)
+    for entry in fs::read_dir(device).ok()?.flatten() {
+        let file_name = entry.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+        let Some(sensor) = file_name.strip_suffix("_label") else {
+            continue;
+        };
+        let label = fs::read_to_string(entry.path()).unwrap_or_default();
+        let Some(rank) = PACKAGE_LABELS
+            .iter()
+            .position(|prefix| label.trim().starts_with(prefix))
+        else {
+            continue;
+        };
+        if let Some(temp) = read_millidegrees(&device.join(format!("{}_input", sensor))) {
+            sensors.push((rank, temp));
+        }
+    }
+
+    // lowest rank wins, ties (e.g. multiple packages on one device) take the max
+    match sensors.iter().map(|(rank, _)| *rank).min() {
+        Some(best_rank) => sensors
+            .iter()
+            .filter(|(rank, _)| *rank == best_rank)
+            .map(|(_, temp)| *temp)
+            .reduce(f32::max),
+        None => read_millidegrees(&device.join("temp1_input")),
+    }
+}
+
+fn read_millidegrees(path: &Path) -> Option<f32> {
+    let millidegrees = fs::read_to_string(path).ok()?.trim().parse::<f32>().ok()?;
+    Some(millidegrees / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fake /sys/class/hwmon tree, removed on drop.
+    struct MockHwmon {
+        root: PathBuf,
+    }
+
+    impl MockHwmon {
+        fn new(test_name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!(
+                "bmon-hwmon-{}-{}",
+                std::process::id(),
+                test_name
+            ));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            Self { root }
+        }
+
+        /// Adds a device with the given driver name and (sensor, label, millidegrees) entries.
+        fn device(&self, dir: &str, name: &str, sensors: &[(&str, Option<&str>, u32)]) {
+            let path = self.root.join(dir);
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("name"), format!("{}\n", name)).unwrap();
+            for (sensor, label, millidegrees) in sensors {
+                fs::write(
+                    path.join(format!("{}_input", sensor)),
+                    format!("{}\n", millidegrees),
+                )
+                .unwrap();
+                if let Some(label) = label {
+                    fs::write(
+                        path.join(format!("{}_label", sensor)),
+                        format!("{}\n", label),
+                    )
+                    .unwrap();
+                }
+            }
+        }
+    }
+
+    impl Drop for MockHwmon {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    #[test]
+    fn prefers_package_over_cores() {
+        let hwmon = MockHwmon::new("package");
+        hwmon.device(
+            "hwmon1",
+            "coretemp",
+            &[
+                ("temp2", Some("Core 0"), 71000),
+                ("temp1", Some("Package id 0"), 64000),
+                ("temp3", Some("Core 1"), 69000),
+            ],
+        );
+        assert_eq!(cpu_temp_from(&hwmon.root), Some(64.0));
+    }
+
+    #[test]
+    fn prefers_tdie_over_tctl() {
+        let hwmon = MockHwmon::new("tdie");
+        hwmon.device(
+            "hwmon2",
+            "k10temp",
+            &[
+                ("temp1", Some("Tctl"), 77000),
+                ("temp2", Some("Tdie"), 50000),
+                ("temp3", Some("Tccd1"), 48000),
+            ],
+        );
+        assert_eq!(cpu_temp_from(&hwmon.root), Some(50.0));
+    }
+
+    #[test]
+    fn takes_hottest_socket() {
+        let hwmon = MockHwmon::new("sockets");
+        hwmon.device(
+            "hwmon1",
+            "coretemp",
+            &[("temp1", Some("Package id 0"), 55000)],
+        );
+        hwmon.device(
+            "hwmon2",
+            "coretemp",
+            &[("temp1", Some("Package id 1"), 61500)],
+        );
+        assert_eq!(cpu_temp_from(&hwmon.root), Some(61.5));
+    }
+
+    #[test]
+    fn ignores_non_cpu_sensors() {
+        let hwmon = MockHwmon::new("non-cpu");
+        hwmon.device("hwmon0", "nvme", &[("temp1", Some("Composite"), 40000)]);
+        hwmon.device("hwmon1", "acpitz", &[("temp1", None, 27800)]);
+        assert_eq!(cpu_temp_from(&hwmon.root), None);
+
+        hwmon.device("hwmon2", "k10temp", &[("temp1", Some("Tctl"), 45000)]);
+        assert_eq!(cpu_temp_from(&hwmon.root), Some(45.0));
+    }
+
+    #[test]
+    fn falls_back_to_temp1_without_labels() {
+        let hwmon = MockHwmon::new("unlabelled");
+        hwmon.device("hwmon0", "cpu_thermal", &[("temp1", None, 52000)]);
+        assert_eq!(cpu_temp_from(&hwmon.root), Some(52.0));
+    }
+
+    #[test]
+    fn missing_hwmon_is_none() {
+        let root = std::env::temp_dir().join("bmon-hwmon-does-not-exist");
+        assert_eq!(cpu_temp_from(&root), None);
+    }
+}
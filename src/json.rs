@@ -0,0 +1,414 @@
+use std::fmt;
+
+/// A minimal JSON value, used to build the `--json` output. Its `Display`
+/// is compact JSON text, `parse` reads it back (e.g. for baselines), and the
+/// accessors below let library users pick it apart.
+///
+/// ```
+/// use bmon::json::Json;
+///
+/// let gpu = Json::object(vec![("idx", 0u32.into()), ("name", "NVIDIA A100".into())]);
+/// assert_eq!(gpu.get("idx").and_then(Json::as_i64), Some(0));
+/// assert_eq!(gpu["name"].as_str(), Some("NVIDIA A100"));
+/// assert!(gpu["missing"].is_null());
+/// assert_eq!(gpu.to_string(), r#"{"idx":0,"name":"NVIDIA A100"}"#);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Self {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// The value of `key`, if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(field, _)| field == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Json::Null)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Integers count too, since a float like 5.0 is written as `5`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Int(value) => Some(*value as f64),
+            Json::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    /// Parses JSON text, with the reason and byte offset on failure. Numbers
+    /// without a fraction or exponent are `Int`s.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = Parser { text, pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < text.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(value)
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize, // in bytes
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: &str) -> String {
+        format!("{} at byte {}", reason, self.pos)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected '{}'", expected)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, literal: &str, value: Json) -> Result<Json, String> {
+        if !self.text[self.pos..].starts_with(literal) {
+            return Err(self.error("invalid literal"));
+        }
+        self.pos += literal.len();
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => self.literal("null", Json::Null),
+            Some('t') => self.literal("true", Json::Bool(true)),
+            Some('f') => self.literal("false", Json::Bool(false)),
+            Some('"') => self.string().map(Json::String),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let rest = &self.text[self.pos..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+            .unwrap_or(rest.len());
+        let number = &rest[..len];
+        let value = if number.contains(['.', 'e', 'E']) {
+            number.parse().map(Json::Float).ok()
+        } else {
+            number.parse().map(Json::Int).ok()
+        };
+        let value = value.ok_or_else(|| self.error("invalid number"))?;
+        self.pos += len;
+        Ok(value)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut string = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(string);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let hex = (0..4).filter_map(|_| chars.next()).map(|(_, c)| c);
+                            u32::from_str_radix(&hex.collect::<String>(), 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                // surrogate pairs aren't needed for bmon's output
+                                .unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        Some(c @ ('"' | '\\' | '/')) => c,
+                        _ => {
+                            self.pos += i;
+                            return Err(self.error("invalid escape"));
+                        }
+                    };
+                    string.push(escaped);
+                }
+                c => string.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    fn array(&mut self) -> Result<Json, String> {
+        self.expect('[')?;
+        let mut values = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(values));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String> {
+        self.expect('{')?;
+        let mut fields = vec![];
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+}
+
+// like serde_json, indexing a missing key or a non-object gives null rather than panicking
+impl std::ops::Index<&str> for Json {
+    type Output = Json;
+
+    fn index(&self, key: &str) -> &Json {
+        self.get(key).unwrap_or(&Json::Null)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Self {
+        Json::Bool(value)
+    }
+}
+
+impl From<i32> for Json {
+    fn from(value: i32) -> Self {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<u32> for Json {
+    fn from(value: u32) -> Self {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<u64> for Json {
+    fn from(value: u64) -> Self {
+        Json::Int(value as i64)
+    }
+}
+
+impl From<f32> for Json {
+    fn from(value: f32) -> Self {
+        Json::Float(value as f64)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Self {
+        Json::Float(value)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Self {
+        Json::String(value.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Self {
+        Json::String(value)
+    }
+}
+
+impl From<&String> for Json {
+    fn from(value: &String) -> Self {
+        Json::String(value.clone())
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(values: Vec<T>) -> Self {
+        Json::Array(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        match value {
+            Some(value) => value.into(),
+            None => Json::Null,
+        }
+    }
+}
+
+fn write_escaped(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{}", value),
+            Json::Int(value) => write!(f, "{}", value),
+            // JSON has no representation for NaN or infinity
+            Json::Float(value) if !value.is_finite() => write!(f, "null"),
+            Json::Float(value) => write!(f, "{}", value),
+            Json::String(value) => write_escaped(f, value),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_escaped(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_its_own_output() {
+        let json = Json::object(vec![
+            ("idx", 0u32.into()),
+            ("name", "GPU \"0\"\n\u{1}".into()),
+            ("temp", Json::Null),
+            ("clocks", vec![1410.5f64, -2.5e-3].into()),
+            ("busy", true.into()),
+            ("processes", Json::Array(vec![])),
+            ("nested", Json::object(vec![])),
+        ]);
+        assert_eq!(Json::parse(&json.to_string()), Ok(json));
+        assert_eq!(
+            Json::parse(" [1, \"\\u00e9\"] "),
+            Ok(vec![Json::Int(1), "é".into()].into())
+        );
+
+        assert_eq!(
+            Json::parse("{\"idx\" 0}"),
+            Err("expected ':' at byte 7".to_string())
+        );
+        assert!(Json::parse("[1,]").is_err());
+        assert!(Json::parse("\"unterminated").is_err());
+        assert!(Json::parse("{} {}").is_err());
+    }
+}
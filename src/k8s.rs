@@ -0,0 +1,175 @@
+use std::fs;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use crate::log::debug;
+
+const SERVICE_ACCOUNT_DIR: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+// the API server can be slow on big clusters, but shouldn't hold bmon up for long
+const API_TIMEOUT_SECS: &str = "2";
+
+/// The name and namespace of a Kubernetes pod.
+pub struct Pod {
+    pub uid: String,
+    pub name: String,
+    pub namespace: String,
+}
+
+/// Returns the UID of the pod the process runs in, or None if it isn't in one.
+pub fn get_pod_uid(pid: u32) -> Option<String> {
+    let cgroup = fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?;
+    parse_pod_uid(&cgroup)
+}
+
+/// Finds the pod UID in /proc/<pid>/cgroup, e.g. in
+/// `/kubepods/burstable/pod1b2c3d4e-5f60-4a1b-9c2d-3e4f5a6b7c8d/<container>` (cgroupfs driver) or
+/// `/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod1b2c3d4e_5f60_....slice/...`
+/// (systemd driver, which replaces the dashes with underscores).
+fn parse_pod_uid(cgroup: &str) -> Option<String> {
+    cgroup.lines().find_map(|line| {
+        let path = line.splitn(3, ':').nth(2)?;
+        if !path.contains("kubepods") {
+            return None;
+        }
+        path.split('/').find_map(|segment| {
+            let segment = segment.strip_suffix(".slice").unwrap_or(segment);
+            let (_, uid) = segment.rsplit_once("pod")?;
+            let uid = uid.replace('_', "-");
+            is_uid(&uid).then_some(uid)
+        })
+    })
+}
+
+/// UIDs look like 1b2c3d4e-5f60-4a1b-9c2d-3e4f5a6b7c8d.
+fn is_uid(uid: &str) -> bool {
+    uid.len() == 36 && uid.chars().all(|c| c.is_ascii_hexdigit() || c == '-')
+}
+
+/// Lists the pods on this node through the API server, using the pod's own service
+/// account, with `curl` (7.55 or later). Returns nothing if bmon isn't running in a pod,
+/// curl isn't installed, or the request fails.
+/// Set NODE_NAME through the downward API to only list the pods on this node.
+pub fn get_pods() -> Vec<Pod> {
+    let Ok(host) = std::env::var("KUBERNETES_SERVICE_HOST") else {
+        return vec![];
+    };
+    let port = std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".to_string());
+    let Ok(token) = fs::read_to_string(format!("{}/token", SERVICE_ACCOUNT_DIR)) else {
+        debug!("no service account token, skipping the pod lookup");
+        return vec![];
+    };
+    let mut url = format!("https://{}:{}/api/v1/pods", host, port);
+    if let Ok(node) = std::env::var("NODE_NAME") {
+        url.push_str(&format!("?fieldSelector=spec.nodeName%3D{}", node));
+    }
+
+    // there's no TLS client in bmon, so let curl do the request. The header is
+    // read from stdin (`@-`), so that the token isn't in curl's argv for `ps` to show
+    let child = Command::new("curl")
+        .args(["--silent", "--fail", "--max-time", API_TIMEOUT_SECS])
+        .arg("--cacert")
+        .arg(format!("{}/ca.crt", SERVICE_ACCOUNT_DIR))
+        .args(["--header", "@-"])
+        .arg(&url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            debug!("curl isn't installed, skipping the pod lookup");
+            return vec![];
+        }
+        Err(e) => {
+            debug!("failed to run curl to list pods: {}", e);
+            return vec![];
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // dropping stdin closes it, so that curl stops reading headers
+        if let Err(e) = writeln!(stdin, "Authorization: Bearer {}", token.trim()) {
+            debug!("failed to pass the token to curl: {}", e);
+        }
+    }
+    match child.wait_with_output() {
+        Ok(output) if output.status.success() => {
+            parse_pods(&String::from_utf8_lossy(&output.stdout))
+        }
+        Ok(output) => {
+            debug!("failed to list pods: curl exited with {}", output.status);
+            vec![]
+        }
+        Err(e) => {
+            debug!("failed to run curl to list pods: {}", e);
+            vec![]
+        }
+    }
+}
+
+/// Extracts the name, namespace, and UID of each item in a PodList. The API
+/// server always writes them in that order at the start of `metadata`.
+fn parse_pods(pod_list: &str) -> Vec<Pod> {
+    pod_list
+        .split("\"metadata\":{")
+        .skip(1)
+        .filter_map(|metadata| {
+            let (name, rest) = string_field(metadata, "name")?;
+            let (namespace, rest) = string_field(rest, "namespace")?;
+            let (uid, _) = string_field(rest, "uid")?;
+            Some(Pod {
+                uid: uid.to_string(),
+                name: name.to_string(),
+                namespace: namespace.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Finds the first `"key":"value"` in `json`, returning the value and everything after it.
+fn string_field<'a>(json: &'a str, key: &str) -> Option<(&'a str, &'a str)> {
+    let prefix = format!("\"{}\":\"", key);
+    let start = json.find(&prefix)? + prefix.len();
+    let end = start + json[start..].find('"')?;
+    Some((&json[start..end], &json[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UID: &str = "1b2c3d4e-5f60-4a1b-9c2d-3e4f5a6b7c8d";
+
+    #[test]
+    fn finds_pod_uids() {
+        let cgroupfs = format!("11:memory:/kubepods/burstable/pod{}/4c01db0b339c\n", UID);
+        assert_eq!(parse_pod_uid(&cgroupfs), Some(UID.to_string()));
+
+        let systemd = format!(
+            "0::/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod{}.slice/cri-containerd-4c01db0b339c.scope\n",
+            UID.replace('-', "_")
+        );
+        assert_eq!(parse_pod_uid(&systemd), Some(UID.to_string()));
+
+        assert_eq!(
+            parse_pod_uid("0::/system.slice/docker-4c01db0b339c.scope\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_pod_list() {
+        let pod_list = format!(
+            "{{\"kind\":\"PodList\",\"apiVersion\":\"v1\",\"metadata\":{{\"resourceVersion\":\"1234\"}},\"items\":[\
+             {{\"metadata\":{{\"name\":\"trainer-0\",\"generateName\":\"trainer-\",\"namespace\":\"ml\",\"uid\":\"{}\",\
+             \"ownerReferences\":[{{\"kind\":\"StatefulSet\",\"name\":\"trainer\",\"uid\":\"x\"}}]}},\"spec\":{{\"nodeName\":\"gpu-07\"}}}}]}}",
+            UID
+        );
+        let pods = parse_pods(&pod_list);
+        assert_eq!(pods.len(), 1);
+        assert_eq!(pods[0].name, "trainer-0");
+        assert_eq!(pods[0].namespace, "ml");
+        assert_eq!(pods[0].uid, UID);
+    }
+}
@@ -0,0 +1,1001 @@
+//! Collection of GPU, process, and host stats, and the diagnosis of bottlenecks
+//! from them. Rendering them as tables is behind the default `render` feature.
+//!
+//! ```no_run
+//! use bmon::{CollectOptions, Machine};
+//! use std::time::Duration;
+//!
+//! let options = CollectOptions::new()
+//!     .gpus([0, 1])
+//!     .processes(true)
+//!     .io(false)
+//!     .sample_window(Duration::from_millis(500));
+//! let machine = Machine::new(options)?;
+//! for gpu in &machine.gpus {
+//!     println!("GPU {}: {}%", gpu.idx, gpu.utilizations.0);
+//! }
+//! # Ok::<(), bmon::BmonError>(())
+//! ```
+//!
+//! [`Machine::to_json`] gives the same structure as `bmon --json`, as a [`json::Json`] value.
+//! To take snapshots in a loop, a [`Collector`] keeps NVML initialised between them.
+
+use nvml_wrapper::struct_wrappers::device::AccountingStats as NvmlAccountingStats;
+use nvml_wrapper::Nvml;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub mod accounting;
+pub mod baseline;
+pub mod cgroup;
+pub mod check;
+pub mod clocks;
+mod collector;
+pub mod color;
+pub mod container;
+#[cfg(feature = "render")]
+pub mod delta;
+pub mod device;
+pub mod diagnostics;
+pub mod disk;
+pub mod error;
+#[cfg(feature = "render")]
+pub mod export;
+pub mod fs;
+pub mod gpu;
+pub mod history;
+pub mod hwmon;
+pub mod json;
+pub mod k8s;
+pub mod log;
+pub mod net;
+pub mod netfs;
+pub mod numa;
+mod parallel;
+pub mod process;
+pub mod psi;
+#[cfg(feature = "render")]
+pub mod render;
+pub mod schema;
+pub mod stat;
+pub mod system;
+#[cfg(feature = "render")]
+pub mod tui;
+pub mod units;
+use accounting::{running_process_accounting, AccountingStats};
+use container::{get_container_id, get_container_names};
+use disk::{get_device_stats, get_io_stats, read_diskstats, DeviceStats, DiskStats};
+use fs::{default_fs_paths, get_fs_stats, get_shm_stats, FsStats};
+use gpu::{AggregateGPUStats, DriverStats, GpuRates};
+use hwmon::get_cpu_temp;
+use json::Json;
+use k8s::{get_pod_uid, get_pods, Pod};
+use net::{get_interface_stats, read_net_dev, InterfaceStats};
+use netfs::{get_netfs_stats, read_netfs, NetFsStats};
+use numa::{get_numa_topology, NumaNode};
+use process::{
+    get_busy_pids, get_cpu_model, get_cpu_stats, get_load_average, parse_meminfo, read_pswpin,
+    CpuStats, ProcessRates,
+};
+use psi::{get_pressure, PressureStats};
+use stat::{cpu_utilization, event_rates, read_proc_stat};
+use system::{get_system_info, persistence_daemon_running, SystemInfo};
+use units::Bytes;
+
+pub use collector::Collector;
+pub use error::BmonError;
+pub use gpu::GPUStats;
+pub use process::ProcessStats;
+
+// the default for `CollectOptions::sample_window`, and for --sample-ms
+pub const DEFAULT_SAMPLE_WINDOW: Duration = Duration::from_millis(250);
+
+/// What a snapshot collects and from where, e.g.
+/// `CollectOptions::new().gpus([0, 1]).io(false).sample_window(Duration::from_millis(500))`.
+/// The GPUs are always collected, but skipped parts are left empty, so only
+/// skip what won't be shown or diagnosed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CollectOptions {
+    // `ps` for each GPU process and `pids`, plus per-process GPU accounting
+    pub processes: bool,
+    // also list non-GPU processes using more than BUSY_PROCESS_THRESHOLD, implies `processes`
+    pub all_processes: bool,
+    // CPU utilization and model, interrupts, load, pressure, and NUMA topology
+    pub cpu: bool,
+    // disk, network, network filesystem, filesystem and /dev/shm usage
+    pub io: bool,
+    // indices of the GPUs to query, along with their processes, None for all of them
+    pub gpus: Option<Vec<u32>>,
+    // indices of GPUs never to query, even if they're in `gpus`
+    pub exclude_gpus: Vec<u32>,
+    // listed whether or not they use a GPU, as placeholders if they have exited
+    pub pids: Vec<u32>,
+    // paths whose filesystems are checked with `io`, None for `fs::default_fs_paths()`
+    pub fs_paths: Option<Vec<PathBuf>>,
+    // rates (CPU, disk, network etc.) are averaged over this
+    pub sample_window: Duration,
+}
+
+impl CollectOptions {
+    pub const ALL: Self = Self {
+        processes: true,
+        all_processes: false,
+        cpu: true,
+        io: true,
+        gpus: None,
+        exclude_gpus: vec![],
+        pids: vec![],
+        fs_paths: None,
+        sample_window: DEFAULT_SAMPLE_WINDOW,
+    };
+    pub const GPUS_ONLY: Self = Self::ALL.processes(false).cpu(false).io(false);
+
+    /// Everything from every GPU, the same as `ALL`.
+    pub fn new() -> Self {
+        Self::ALL
+    }
+
+    pub const fn processes(mut self, processes: bool) -> Self {
+        self.processes = processes;
+        self
+    }
+
+    pub const fn all_processes(mut self, all_processes: bool) -> Self {
+        self.all_processes = all_processes;
+        self
+    }
+
+    pub const fn cpu(mut self, cpu: bool) -> Self {
+        self.cpu = cpu;
+        self
+    }
+
+    pub const fn io(mut self, io: bool) -> Self {
+        self.io = io;
+        self
+    }
+
+    pub fn gpus(mut self, gpus: impl IntoIterator<Item = u32>) -> Self {
+        self.gpus = Some(gpus.into_iter().collect());
+        self
+    }
+
+    pub fn exclude_gpus(mut self, gpus: impl IntoIterator<Item = u32>) -> Self {
+        self.exclude_gpus = gpus.into_iter().collect();
+        self
+    }
+
+    pub fn pids(mut self, pids: impl IntoIterator<Item = u32>) -> Self {
+        self.pids = pids.into_iter().collect();
+        self
+    }
+
+    pub fn fs_paths(mut self, paths: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        self.fs_paths = Some(paths.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn sample_window(mut self, sample_window: Duration) -> Self {
+        self.sample_window = sample_window;
+        self
+    }
+
+    /// Whether the GPU at `idx` is queried.
+    pub fn includes_gpu(&self, idx: u32) -> bool {
+        let included = self.gpus.as_ref().is_none_or(|gpus| gpus.contains(&idx));
+        included && !self.exclude_gpus.contains(&idx)
+    }
+
+    /// Collects the parts that either of `self` or `other` needs, from the GPUs,
+    /// processes and paths of `self`.
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            processes: self.processes || other.processes,
+            all_processes: self.all_processes || other.all_processes,
+            cpu: self.cpu || other.cpu,
+            io: self.io || other.io,
+            ..self
+        }
+    }
+
+    // whether there's anything to average over the sample window
+    fn sampled(&self) -> bool {
+        self.processes || self.all_processes || self.cpu || self.io
+    }
+}
+
+impl Default for CollectOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Per-GPU and per-process rates between two snapshots, from `Machine::diff`.
+/// GPUs and processes new since the previous snapshot are included, with their rates unknown.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MachineDelta {
+    pub elapsed: Duration,
+    pub gpus: Vec<GpuRates>,
+    pub processes: Vec<ProcessRates>,
+}
+
+/// What a warning lost from a snapshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WarningKind {
+    // a whole GPU, e.g. one that couldn't be opened
+    Gpu,
+    // the host stats, e.g. because /proc/meminfo couldn't be read
+    Host,
+    // a single field, process, or driver detail, leaving the rest complete
+    Partial,
+}
+
+/// A problem that lost part of a snapshot without failing it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(kind: WarningKind, message: String) -> Self {
+        Self { kind, message }
+    }
+
+    pub fn partial(message: String) -> Self {
+        Self::new(WarningKind::Partial, message)
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+// only the message, the JSON predates the kinds
+impl From<Warning> for Json {
+    fn from(warning: Warning) -> Self {
+        warning.message.into()
+    }
+}
+
+/// A snapshot of the GPUs, their processes, and the host they run on.
+pub struct Machine {
+    pub gpus: Vec<GPUStats>,
+    pub processes: Vec<ProcessStats>,
+    pub all_processes: bool,
+    pub accounting: Vec<AccountingStats>,
+    pub accounting_enabled: bool,
+    pub driver: DriverStats,
+    pub system: SystemInfo,
+    pub cpu_model: String,
+    pub cpu_temp: Option<f32>,           // package temperature in °C
+    pub cpu_utilization: Option<f32>,    // % of time busy across all cores
+    pub event_rates: Option<(f32, f32)>, // (context switches, interrupts) per second
+    pub cpu: CpuStats,
+    pub numa_nodes: Vec<NumaNode>,
+    pub load_average: (f32, f32, f32), // 1, 5, and 15 minute averages
+    pub disk: Option<DiskStats>,       // None if /proc/stat is unavailable
+    pub devices: Vec<DeviceStats>,
+    pub filesystems: Vec<FsStats>,
+    pub shm: Option<FsStats>, // None if /dev/shm isn't mounted
+    pub interfaces: Vec<InterfaceStats>,
+    pub netfs: Vec<NetFsStats>,          // NFS and Lustre mounts
+    pub pressure: Option<PressureStats>, // None if the kernel has no PSI
+    pub swap_in_rate: Option<f32>,       // pages/s over the sample window, None if unknown
+    pub persistence_daemon_running: bool,
+    // problems that lost part of the snapshot without failing it, e.g. a GPU
+    // that couldn't be opened or a field that couldn't be read
+    pub warnings: Vec<Warning>,
+}
+
+impl Machine {
+    /// Rates (CPU, disk, network etc.) are averaged over the sample window, which
+    /// starts before the GPU and process queries so that they overlap with it.
+    /// Only the parts in `options` are collected, and without any of them there's no window.
+    /// Fails only if NVML is unusable; a GPU, process, or host stat that can't
+    /// be read is skipped or partly filled in, with the reason added to `warnings`.
+    pub fn new(options: CollectOptions) -> Result<Self, BmonError> {
+        Collector::new(options)?.collect()
+    }
+
+    /// One snapshot with an already initialised NVML, see `Machine::new`.
+    /// Also gives whether a GPU was lost in a way that needs NVML re-initialised.
+    pub(crate) fn collect(
+        nvml: &Nvml,
+        driver: &DriverStats,
+        options: &CollectOptions,
+    ) -> Result<(Self, bool), BmonError> {
+        let CollectOptions {
+            processes: collect_processes,
+            all_processes,
+            cpu: collect_cpu,
+            io: collect_io,
+            pids: ref extra_pids,
+            sample_window,
+            ..
+        } = *options;
+        let collect_processes = collect_processes || all_processes;
+        let sample_start = Instant::now();
+        let proc_stat_before = collect_cpu.then(read_proc_stat).flatten();
+        let pswpin_before = collect_cpu.then(read_pswpin).flatten();
+        let diskstats_before = collect_io.then(read_diskstats).unwrap_or_default();
+        let net_dev_before = collect_io.then(read_net_dev).unwrap_or_default();
+        let netfs_before = collect_io.then(read_netfs).unwrap_or_default();
+
+        // the host stats that don't need the sample window are collected alongside the GPUs
+        let host_options = options.clone();
+        let host = thread::spawn(move || HostStats::collect(&host_options));
+
+        let driver = driver.clone();
+        let system = get_system_info();
+        let mut warnings = driver
+            .errors
+            .iter()
+            .map(|e| Warning::partial(e.clone()))
+            .collect::<Vec<Warning>>();
+
+        let mut gpus: Vec<GPUStats> = vec![];
+        let mut accounting: Vec<AccountingStats> = vec![];
+        let mut accounting_enabled = false;
+        // if a process runs on several GPUs, keep the stats from the first one
+        let mut process_accounting = HashMap::new();
+        let indices = (0..nvml.device_count()?)
+            .filter(|i| options.includes_gpu(*i))
+            .collect::<Vec<u32>>();
+        // NVML is thread safe, and each thread only queries its own device
+        let queries = parallel::map(&indices, |i| GpuQuery::collect(nvml, *i, collect_processes));
+        let mut stale = false;
+        for (i, query) in indices.iter().zip(queries) {
+            let query = match query {
+                Ok(Ok(query)) => query,
+                Ok(Err(e)) => {
+                    stale |= matches!(&e, BmonError::Nvml(e) if collector::needs_reinit(e));
+                    warnings.push(Warning::new(
+                        WarningKind::Gpu,
+                        format!("GPU {}: failed to open device: {}", i, e),
+                    ));
+                    continue;
+                }
+                Err(e) => {
+                    debug!("GPU {} panicked during collection: {}", i, e);
+                    warnings.push(Warning::new(
+                        WarningKind::Gpu,
+                        format!("GPU {}: collection failed: {}", i, e),
+                    ));
+                    continue;
+                }
+            };
+            let gpu = query.gpu;
+            if gpu.retired_pages_sbe > 0 || gpu.retired_pages_dbe > 0 {
+                warning!(
+                    "ECC errors detected on GPU {}: {} single-bit and {} double-bit retired pages",
+                    i,
+                    gpu.retired_pages_sbe,
+                    gpu.retired_pages_dbe
+                );
+            }
+            warnings.extend(
+                gpu.errors
+                    .iter()
+                    .map(|e| Warning::partial(format!("GPU {}: {}", i, e))),
+            );
+            accounting_enabled |= query.accounting_enabled;
+            accounting.extend(query.accounting);
+            for (pid, stats) in query.process_accounting {
+                process_accounting.entry(pid).or_insert(stats);
+            }
+            gpus.push(gpu);
+        }
+        // a process running on several GPUs is only listed once
+        let mut gpu_indices: HashMap<u32, Vec<u32>> = HashMap::new();
+        let mut gpu_process_pids = vec![];
+        for gpu in &gpus {
+            for pid in &gpu.processes {
+                let indices = gpu_indices.entry(*pid).or_default();
+                if indices.is_empty() {
+                    gpu_process_pids.push(*pid);
+                }
+                indices.push(gpu.idx);
+            }
+        }
+
+        // the RAM total sizes the busy processes of `all_processes`
+        let cpu = if collect_cpu || collect_processes {
+            get_cpu_stats().unwrap_or_else(|e| {
+                warnings.push(Warning::new(WarningKind::Host, e.to_string()));
+                parse_meminfo("")
+            })
+        } else {
+            parse_meminfo("")
+        };
+        let mut pids = if !collect_processes {
+            vec![]
+        } else if all_processes {
+            let mut pids =
+                get_busy_pids(BUSY_PROCESS_THRESHOLD, cpu.ram_total_kib).unwrap_or_else(|e| {
+                    warnings.push(Warning::partial(format!("busy processes: {}", e)));
+                    vec![]
+                });
+            for pid in &gpu_process_pids {
+                if !pids.contains(pid) {
+                    pids.push(*pid);
+                }
+            }
+            pids
+        } else {
+            gpu_process_pids.clone()
+        };
+        for pid in extra_pids.iter().filter(|_| collect_processes) {
+            if !pids.contains(pid) {
+                pids.push(*pid);
+            }
+        }
+
+        // each process is a `ps` spawn, so run them in parallel
+        let ps_results = parallel::map(&pids, |pid| ProcessStats::try_from_pid(*pid));
+        let mut processes = pids
+            .iter()
+            .zip(ps_results)
+            .filter_map(|(pid, result)| {
+                let on_gpu = gpu_process_pids.contains(pid);
+                let manual = extra_pids.contains(pid) && !on_gpu;
+                let mut process = match result.and_then(|result| result) {
+                    Ok(Some(process)) => process,
+                    Ok(None) if manual => return Some(ProcessStats::exited(*pid)),
+                    Ok(None) => return None,
+                    Err(e) => {
+                        warnings.push(Warning::partial(format!("process {}: {}", pid, e)));
+                        return None;
+                    }
+                };
+                process.on_gpu = on_gpu;
+                process.manual = manual;
+                process.gpu_indices = gpu_indices.get(pid).cloned().unwrap_or_default();
+                if let Some(stats) = process_accounting.get(pid) {
+                    process.avg_sm_utilization = stats.gpu_utilization;
+                    process.peak_gpu_memory = stats.max_memory_usage.map(Bytes);
+                }
+                Some(process)
+            })
+            .collect::<Vec<ProcessStats>>();
+        let HostStats {
+            mut numa_nodes,
+            cpu_model,
+            cpu_temp,
+            filesystems,
+            shm,
+            pods,
+        } = host.join().unwrap_or_else(|_| {
+            warnings.push(Warning::new(
+                WarningKind::Host,
+                "failed to collect host stats".to_string(),
+            ));
+            HostStats::default()
+        });
+        // one Docker lookup per container rather than one per process
+        let container_ids = processes
+            .iter()
+            .map(|process| get_container_id(process.pid))
+            .collect::<Vec<Option<String>>>();
+        let container_names =
+            get_container_names(&container_ids.iter().flatten().cloned().collect::<Vec<_>>());
+        for (process, id) in processes.iter_mut().zip(container_ids) {
+            process.container_name = id.and_then(|id| container_names.get(&id).cloned());
+        }
+        if !pods.is_empty() {
+            for process in &mut processes {
+                let Some(uid) = get_pod_uid(process.pid) else {
+                    continue;
+                };
+                if let Some(pod) = pods.iter().find(|pod| pod.uid == uid) {
+                    process.k8s_pod = Some(pod.name.clone());
+                    process.k8s_namespace = Some(pod.namespace.clone());
+                }
+            }
+        }
+
+        for node in &mut numa_nodes {
+            node.gpus = gpus
+                .iter()
+                .filter(|gpu| gpu.numa_node == Some(node.id))
+                .map(|gpu| gpu.idx)
+                .collect();
+        }
+        // only the second GPU utilization sample would need the window, so
+        // skip it when only GPUs are collected
+        let sampled = options.sampled();
+        if sampled {
+            // only sleep for whatever is left of the window after the queries above
+            thread::sleep(sample_window.saturating_sub(sample_start.elapsed()));
+        }
+        for process in &mut processes {
+            process.end_sample_window();
+        }
+        for gpu in gpus.iter_mut().filter(|_| sampled) {
+            if let Ok(utilization) = nvml
+                .device_by_index(gpu.idx)
+                .and_then(|device| device.utilization_rates())
+            {
+                gpu.utilization_samples.push(utilization.gpu);
+            }
+        }
+        let proc_stat = proc_stat_before.zip(read_proc_stat());
+        let pswpin = pswpin_before.zip(read_pswpin());
+        let diskstats_after = collect_io.then(read_diskstats).unwrap_or_default();
+        let net_dev_after = collect_io.then(read_net_dev).unwrap_or_default();
+        let netfs_after = collect_io.then(read_netfs).unwrap_or_default();
+        let sample_window = sample_start.elapsed();
+        let devices = get_device_stats(&diskstats_before, &diskstats_after, sample_window);
+        let interfaces = get_interface_stats(&net_dev_before, &net_dev_after, sample_window);
+        let netfs = get_netfs_stats(&netfs_before, &netfs_after, sample_window);
+        let swap_in_rate = pswpin.map(|(before, after)| {
+            after.saturating_sub(before) as f32 / sample_window.as_secs_f32()
+        });
+        let cpu_utilization = proc_stat
+            .as_ref()
+            .map(|(before, after)| cpu_utilization(&before.cpu, &after.cpu));
+        let event_rates = proc_stat
+            .as_ref()
+            .map(|(before, after)| event_rates(before, after, sample_window));
+        let load_average = if collect_cpu {
+            get_load_average().unwrap_or_else(|| {
+                warnings.push(Warning::partial("failed to read /proc/loadavg".to_string()));
+                (0.0, 0.0, 0.0)
+            })
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+        let pressure = collect_cpu.then(get_pressure).flatten();
+        let disk = proc_stat
+            .as_ref()
+            .map(|(before, after)| get_io_stats(&before.cpu, &after.cpu));
+
+        let machine = Self {
+            gpus,
+            processes,
+            all_processes,
+            accounting,
+            accounting_enabled,
+            driver,
+            system,
+            cpu_model,
+            cpu_temp,
+            cpu_utilization,
+            event_rates,
+            cpu,
+            numa_nodes,
+            load_average,
+            disk,
+            devices,
+            filesystems,
+            shm,
+            interfaces,
+            netfs,
+            pressure,
+            swap_in_rate,
+            persistence_daemon_running: persistence_daemon_running(),
+            warnings,
+        };
+        Ok((machine, stale))
+    }
+
+    /// Whether a whole GPU or the host stats couldn't be collected, so that the
+    /// snapshot can't be trusted to show every problem, unlike a missing field.
+    pub fn is_incomplete(&self) -> bool {
+        self.warnings
+            .iter()
+            .any(|warning| warning.kind != WarningKind::Partial)
+    }
+
+    /// Removes the GPUs that don't match `keep`, along with any processes
+    /// that only ran on the removed GPUs.
+    pub fn retain_gpus(&mut self, keep: impl Fn(&GPUStats) -> bool) {
+        self.gpus.retain(|gpu| keep(gpu));
+        let kept = self.gpus.iter().map(|gpu| gpu.idx).collect::<Vec<u32>>();
+
+        self.processes.retain_mut(|process| {
+            if process.gpu_indices.is_empty() {
+                // not a GPU process, e.g. from --all-processes
+                return true;
+            }
+            process.gpu_indices.retain(|idx| kept.contains(idx));
+            !process.gpu_indices.is_empty()
+        });
+        for node in &mut self.numa_nodes {
+            node.gpus.retain(|idx| kept.contains(idx));
+        }
+    }
+
+    /// The rates between `previous` and this snapshot, taken `elapsed` later, e.g. the
+    /// average power of each GPU from its energy counter. Counters that restarted in
+    /// between, after a driver reload or when a pid was reused, give unknown rates.
+    ///
+    /// ```no_run
+    /// use bmon::{CollectOptions, Collector};
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let collector = Collector::new(CollectOptions::new())?;
+    /// let (previous, taken) = (collector.collect()?, Instant::now());
+    /// std::thread::sleep(Duration::from_secs(5));
+    /// let delta = collector.collect()?.diff(&previous, taken.elapsed());
+    /// for gpu in &delta.gpus {
+    ///     println!("GPU {}: {:?}W", gpu.idx, gpu.power_w);
+    /// }
+    /// # Ok::<(), bmon::BmonError>(())
+    /// ```
+    pub fn diff(&self, previous: &Machine, elapsed: Duration) -> MachineDelta {
+        // a reloaded driver restarts every counter, and may renumber the GPUs
+        let reloaded = previous.driver.driver_version != self.driver.driver_version;
+        let gpus = self
+            .gpus
+            .iter()
+            .map(
+                |gpu| match previous.gpus.iter().find(|prev| prev.idx == gpu.idx) {
+                    Some(prev) if !reloaded => gpu.rates_since(prev, elapsed),
+                    _ => GpuRates {
+                        idx: gpu.idx,
+                        energy_j: None,
+                        power_w: None,
+                        memory_growth: None,
+                    },
+                },
+            )
+            .collect();
+        let processes = self
+            .processes
+            .iter()
+            .map(|process| {
+                match previous
+                    .processes
+                    .iter()
+                    .find(|prev| prev.pid == process.pid)
+                {
+                    Some(prev) => process.rates_since(prev, elapsed),
+                    None => ProcessRates {
+                        pid: process.pid,
+                        cpu_pct: None,
+                        io_rates: None,
+                    },
+                }
+            })
+            .collect();
+        MachineDelta {
+            elapsed,
+            gpus,
+            processes,
+        }
+    }
+
+    pub fn aggregate_gpu_stats(&self) -> AggregateGPUStats {
+        AggregateGPUStats::from_gpus(&self.gpus)
+    }
+
+    pub fn num_cpus(&self) -> f32 {
+        self.cpu.num_cpus.max(1) as f32
+    }
+
+    /// Everything collected, in the same structure as `bmon --json`.
+    ///
+    /// ```no_run
+    /// use bmon::{CollectOptions, Machine};
+    ///
+    /// // only the GPUs, so there's no sample window
+    /// let machine = Machine::new(CollectOptions::GPUS_ONLY)?;
+    /// let json = machine.to_json();
+    /// for gpu in json["gpus"].as_array().unwrap_or_default() {
+    ///     println!("{}: {}", gpu["name"].as_str().unwrap_or("?"), gpu["temp"]);
+    /// }
+    /// # Ok::<(), bmon::BmonError>(())
+    /// ```
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("driver_version", (&self.driver.driver_version).into()),
+            ("cuda_version", (&self.driver.cuda_version).into()),
+            ("cuda_version_int", self.driver.cuda_version_int.into()),
+            ("nvml_version", (&self.driver.nvml_version).into()),
+            ("system", self.system.to_json()),
+            ("cpu_model", (&self.cpu_model).into()),
+            ("cpu_temp", self.cpu_temp.into()),
+            ("cpu_utilization", self.cpu_utilization.into()),
+            (
+                "context_switches_per_sec",
+                self.event_rates.map(|(ctxt, _)| ctxt).into(),
+            ),
+            (
+                "interrupts_per_sec",
+                self.event_rates.map(|(_, intr)| intr).into(),
+            ),
+            ("cpu", self.cpu.to_json()),
+            ("shm", self.shm.as_ref().map(FsStats::to_json).into()),
+            (
+                "load_average",
+                vec![
+                    self.load_average.0,
+                    self.load_average.1,
+                    self.load_average.2,
+                ]
+                .into(),
+            ),
+            (
+                "pressure",
+                self.pressure.as_ref().map(PressureStats::to_json).into(),
+            ),
+            (
+                "persistence_daemon_running",
+                self.persistence_daemon_running.into(),
+            ),
+            ("disk", self.disk.as_ref().map(DiskStats::to_json).into()),
+            (
+                "disks",
+                self.devices
+                    .iter()
+                    .map(DeviceStats::to_json)
+                    .collect::<Vec<Json>>()
+                    .into(),
+            ),
+            (
+                "interfaces",
+                self.interfaces
+                    .iter()
+                    .map(InterfaceStats::to_json)
+                    .collect::<Vec<Json>>()
+                    .into(),
+            ),
+            (
+                "netfs",
+                self.netfs
+                    .iter()
+                    .map(NetFsStats::to_json)
+                    .collect::<Vec<Json>>()
+                    .into(),
+            ),
+            ("aggregate", self.aggregate_gpu_stats().to_json()),
+            (
+                "gpus",
+                self.gpus
+                    .iter()
+                    .map(GPUStats::to_json)
+                    .collect::<Vec<Json>>()
+                    .into(),
+            ),
+            (
+                "processes",
+                self.processes
+                    .iter()
+                    .map(ProcessStats::to_json)
+                    .collect::<Vec<Json>>()
+                    .into(),
+            ),
+            (
+                "filesystems",
+                self.filesystems
+                    .iter()
+                    .map(FsStats::to_json)
+                    .collect::<Vec<Json>>()
+                    .into(),
+            ),
+            (
+                "numa_nodes",
+                self.numa_nodes
+                    .iter()
+                    .map(NumaNode::to_json)
+                    .collect::<Vec<Json>>()
+                    .into(),
+            ),
+            (
+                "accounting",
+                self.accounting
+                    .iter()
+                    .map(AccountingStats::to_json)
+                    .collect::<Vec<Json>>()
+                    .into(),
+            ),
+            ("warnings", self.warnings.clone().into()),
+        ])
+    }
+
+    /// The JSON Schema of `to_json`, with every field listed and required.
+    pub fn json_schema() -> Json {
+        let optional_number = || schema::nullable(schema::number());
+        schema::object(vec![
+            ("driver_version", schema::string()),
+            ("cuda_version", schema::string()),
+            ("cuda_version_int", schema::integer()),
+            ("nvml_version", schema::string()),
+            ("system", SystemInfo::json_schema()),
+            ("cpu_model", schema::string()),
+            ("cpu_temp", optional_number()),
+            ("cpu_utilization", optional_number()),
+            ("context_switches_per_sec", optional_number()),
+            ("interrupts_per_sec", optional_number()),
+            ("cpu", CpuStats::json_schema()),
+            ("shm", schema::nullable(FsStats::json_schema())),
+            // 1, 5 and 15 minute averages
+            ("load_average", schema::array(schema::number())),
+            ("pressure", schema::nullable(PressureStats::json_schema())),
+            ("persistence_daemon_running", schema::boolean()),
+            ("disk", schema::nullable(DiskStats::json_schema())),
+            ("disks", schema::array(DeviceStats::json_schema())),
+            ("interfaces", schema::array(InterfaceStats::json_schema())),
+            ("netfs", schema::array(NetFsStats::json_schema())),
+            ("aggregate", AggregateGPUStats::json_schema()),
+            ("gpus", schema::array(GPUStats::json_schema())),
+            ("processes", schema::array(ProcessStats::json_schema())),
+            ("filesystems", schema::array(FsStats::json_schema())),
+            ("numa_nodes", schema::array(NumaNode::json_schema())),
+            ("accounting", schema::array(AccountingStats::json_schema())),
+            ("warnings", schema::array(schema::string())),
+        ])
+    }
+}
+
+/// The NVML queries of one GPU, made on its own thread.
+struct GpuQuery {
+    gpu: GPUStats,
+    accounting_enabled: bool,
+    accounting: Vec<AccountingStats>,
+    process_accounting: Vec<(u32, NvmlAccountingStats)>,
+}
+
+impl GpuQuery {
+    /// Accounting is only queried along with processes, since only the process table shows it.
+    fn collect(nvml: &Nvml, idx: u32, accounting: bool) -> Result<Self, BmonError> {
+        debug!("querying GPU {} stats", idx);
+        let device = nvml.device_by_index(idx)?;
+        let gpu = GPUStats::from_device(&device);
+        if !accounting {
+            return Ok(Self {
+                gpu,
+                accounting_enabled: false,
+                accounting: vec![],
+                process_accounting: vec![],
+            });
+        }
+        Ok(Self {
+            accounting_enabled: device.is_accounting_enabled().unwrap_or(false),
+            accounting: AccountingStats::from_nvml_device(&device),
+            process_accounting: running_process_accounting(&device, &gpu.processes),
+            gpu,
+        })
+    }
+}
+
+/// Host stats that don't depend on the sample window, so can be collected
+/// while the GPUs are queried.
+#[derive(Default)]
+struct HostStats {
+    numa_nodes: Vec<NumaNode>,
+    cpu_model: String,
+    cpu_temp: Option<f32>,
+    filesystems: Vec<FsStats>,
+    shm: Option<FsStats>,
+    pods: Vec<Pod>,
+}
+
+impl HostStats {
+    fn collect(options: &CollectOptions) -> Self {
+        let mut host = Self::default();
+        if options.cpu {
+            host.numa_nodes = get_numa_topology();
+            host.cpu_model = get_cpu_model();
+            host.cpu_temp = get_cpu_temp();
+        }
+        if options.io {
+            let fs_paths = options.fs_paths.clone().unwrap_or_else(default_fs_paths);
+            host.filesystems = get_fs_stats(&fs_paths);
+            host.shm = get_shm_stats();
+        }
+        let processes = options.processes || options.all_processes;
+        if processes && std::env::var_os("KUBERNETES_SERVICE_HOST").is_some() {
+            // one API request for all the pods, rather than one per process
+            host.pods = get_pods();
+        }
+        host
+    }
+}
+
+// CPU or memory usage (%) above which non-GPU processes are collected with `all_processes`
+const BUSY_PROCESS_THRESHOLD: f32 = 5.0;
+
+#[cfg(test)]
+impl Machine {
+    /// An otherwise empty machine with `gpus`, for testing diagnoses and rendering.
+    pub(crate) fn with_gpus(gpus: Vec<GPUStats>) -> Self {
+        let mut cpu = process::parse_meminfo("");
+        cpu.num_cpus = 8;
+        Self {
+            gpus,
+            processes: vec![],
+            all_processes: false,
+            accounting: vec![],
+            accounting_enabled: false,
+            driver: DriverStats {
+                cuda_version: "12.2".to_string(),
+                cuda_version_int: 12020,
+                driver_version: "535.104.05".to_string(),
+                nvml_version: "12.535.104.05".to_string(),
+                errors: vec![],
+            },
+            system: SystemInfo {
+                kernel: "5.15.0".to_string(),
+                os: "Ubuntu 22.04".to_string(),
+                uptime_secs: 0,
+            },
+            cpu_model: "AMD EPYC 7763".to_string(),
+            cpu_temp: None,
+            cpu_utilization: None,
+            event_rates: None,
+            cpu,
+            numa_nodes: vec![],
+            load_average: (0.0, 0.0, 0.0),
+            disk: None,
+            devices: vec![],
+            filesystems: vec![],
+            shm: None,
+            interfaces: vec![],
+            netfs: vec![],
+            pressure: None,
+            swap_in_rate: None,
+            persistence_daemon_running: false,
+            warnings: vec![],
+        }
+    }
+
+    /// Empties what `collect` would leave out with `options`, e.g. the disks without `io`.
+    pub(crate) fn skip_uncollected(&mut self, options: &CollectOptions) {
+        if !(options.processes || options.all_processes) {
+            self.processes.clear();
+            self.accounting.clear();
+        }
+        if !options.cpu {
+            self.cpu_model.clear();
+            self.cpu_temp = None;
+            self.cpu_utilization = None;
+            self.event_rates = None;
+            self.load_average = (0.0, 0.0, 0.0);
+            self.pressure = None;
+            self.swap_in_rate = None;
+            // from /proc/stat, like the CPU utilization
+            self.disk = None;
+        }
+        if !(options.cpu || options.processes || options.all_processes) {
+            self.cpu = process::parse_meminfo("");
+        }
+        if !options.io {
+            self.devices.clear();
+            self.filesystems.clear();
+            self.shm = None;
+            self.interfaces.clear();
+            self.netfs.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_lost_gpus_or_host_stats_make_a_snapshot_incomplete() {
+        let mut machine = Machine::with_gpus(vec![]);
+        machine.warnings = vec![Warning::partial(
+            "GPU 1: failed to query fan speed".to_string(),
+        )];
+        assert!(!machine.is_incomplete());
+        machine.warnings.push(Warning::new(
+            WarningKind::Gpu,
+            "GPU 2: failed to open device: Unknown Error".to_string(),
+        ));
+        assert!(machine.is_incomplete());
+        // the JSON keeps only the messages
+        assert_eq!(
+            Json::from(machine.warnings.clone()).to_string(),
+            r#"["GPU 1: failed to query fan speed","GPU 2: failed to open device: Unknown Error"]"#
+        );
+    }
+}
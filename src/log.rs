@@ -0,0 +1,144 @@
+//! Levelled logging to stderr, or to a logger set with `set_logger`, e.g. for a
+//! library user to collect bmon's messages with their own.
+
+use clap::ValueEnum;
+use std::fmt::Arguments;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::RwLock;
+
+/// Log levels, from least to most verbose.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+/// Receives each enabled message, with its level.
+pub type Logger = Box<dyn Fn(Level, &Arguments) + Send + Sync>;
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Warn as u8);
+static LOGGER: RwLock<Option<Logger>> = RwLock::new(None);
+
+/// Reads the log level from the `BMON_LOG` environment variable (e.g. `BMON_LOG=debug`).
+/// Unset or unrecognised values keep the default level of `warn`.
+pub fn init() {
+    let level = std::env::var("BMON_LOG")
+        .ok()
+        .and_then(|level| Level::from_str(&level, false).ok());
+    if let Some(level) = level {
+        set_level(level);
+    }
+}
+
+/// Logs everything up to and including `level`, e.g. from `--log-level`.
+pub fn set_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as u8 <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Sends every enabled message to `logger` instead of stderr, e.g.
+/// `set_logger(Box::new(|level, message| my_log(level.name(), message.to_string())))`.
+pub fn set_logger(logger: Logger) {
+    *LOGGER.write().unwrap_or_else(|e| e.into_inner()) = Some(logger);
+}
+
+/// Where the macros below send their messages, once they're known to be enabled.
+pub fn log(level: Level, message: &Arguments) {
+    match &*LOGGER.read().unwrap_or_else(|e| e.into_inner()) {
+        Some(logger) => logger(level, message),
+        None => eprintln!("[{}] {}", level.name(), message),
+    }
+}
+
+/// Logs at error level, which is always enabled. Unlike the others it isn't
+/// re-exported here, where it would share a name with `crate::error`.
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Error) {
+            $crate::log::log($crate::log::Level::Error, &format_args!($($arg)*));
+        }
+    };
+}
+
+/// Logs at info level.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Info) {
+            $crate::log::log($crate::log::Level::Info, &format_args!($($arg)*));
+        }
+    };
+}
+pub use crate::info;
+
+/// Logs at debug level.
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Debug) {
+            $crate::log::log($crate::log::Level::Debug, &format_args!($($arg)*));
+        }
+    };
+}
+pub use crate::debug;
+
+/// Logs at warn level, which is enabled by default.
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Warn) {
+            $crate::log::log($crate::log::Level::Warn, &format_args!($($arg)*));
+        }
+    };
+}
+pub use crate::warning;
+
+/// Logs at trace level, for every query.
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::Level::Trace) {
+            $crate::log::log($crate::log::Level::Trace, &format_args!($($arg)*));
+        }
+    };
+}
+pub use crate::trace;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn sends_messages_to_the_logger() {
+        let messages = Arc::new(Mutex::new(vec![]));
+        let received = messages.clone();
+        set_logger(Box::new(move |level, message| {
+            received.lock().unwrap().push((level, message.to_string()));
+        }));
+        warning!("GPU {} is hot", 3);
+        assert!(messages
+            .lock()
+            .unwrap()
+            .contains(&(Level::Warn, "GPU 3 is hot".to_string())));
+    }
+}
@@ -1,183 +1,783 @@
-use clap::Parser;
-use nvml_wrapper::Nvml;
-use tabled::{
-    settings::object::{Columns, Rows},
-    settings::{Extract, Modify, Panel, Style, Width},
-    Table,
-};
-
-mod disk;
-mod gpu;
-mod process;
-use disk::get_io_stats;
-use gpu::{get_driver_stats, GPUStats};
-use process::{get_cpu_stats, ProcessStats};
-
-struct Machine {
-    gpus: Vec<GPUStats>,
-    processes: Vec<ProcessStats>,
-    cuda_version: String,
-    driver_version: String,
-    num_cpus: String,
-    ram_capacity: String,
-    iowait: String,
-    steal: String,
-    idle: String,
-}
-
-impl Machine {
-    fn new() -> Self {
-        let nvml = Nvml::init().unwrap();
-
-        let (cuda_version, driver_version) = get_driver_stats(&nvml);
-
-        let mut gpus: Vec<GPUStats> = vec![];
-        let num_gpus = nvml.device_count().unwrap();
-        for i in 0..num_gpus {
-            let device = nvml.device_by_index(i).unwrap();
-            let gpu = GPUStats::from_nvml_device(device);
-            gpus.push(gpu);
-        }
-        let gpu_process_pids = gpus
-            .iter()
-            .flat_map(|gpu| gpu.processes.clone())
-            .collect::<Vec<u32>>();
+use bmon::baseline::Baseline;
+use bmon::diagnostics::{diagnose, parse_pct, parse_rate, Severity, Thresholds, REQUIRED_STATS};
+use bmon::export::{CsvRenderer, Format, InfluxRenderer, JsonRenderer, PrometheusRenderer};
+use bmon::history::{session_energy_summary, session_total_wh, GpuSession, GpuSessionPeak};
+use bmon::process::Signal;
+use bmon::render::{session_peaks_table, DisplayOptions, Renderer, TableRenderer};
+use bmon::{check, clocks, log, tui, BmonError, CollectOptions, Collector, Machine, ProcessStats};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
-        let processes = gpu_process_pids
-            .iter()
-            .map(|pid| ProcessStats::from_pid(*pid))
-            .collect::<Vec<ProcessStats>>();
-
-        let (num_cpus, ram_capacity) = get_cpu_stats();
-        let (iowait, steal, idle) = get_io_stats();
-
-        Self {
-            gpus,
-            processes,
-            cuda_version,
-            driver_version,
-            num_cpus,
-            ram_capacity,
-            iowait,
-            steal,
-            idle,
-        }
-    }
-
-    fn display_gpu_stats(&self, verbose: bool) {
-        let mut table = Table::new(&self.gpus);
-
-        // set process col width to be exactly 10 characters
-        let process_col_width = { 10 };
-        table.with(
-            Modify::new(Columns::new(10..11))
-                .with(Width::truncate(process_col_width).suffix("..."))
-                .with(Width::increase(process_col_width)),
-        );
+mod config;
+mod daemon;
+
+const PKG_AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
+const PKG_DESC: &str = env!("CARGO_PKG_DESCRIPTION");
+const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Parser)]
+#[command(author=PKG_AUTHORS, version=PKG_VERSION, about=PKG_DESC)]
+struct Args {
+    /// Without a subcommand, bmon prints one snapshot and exits.
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Displays all possible stats, equivalent to -bc
+    #[arg(short, long, default_value = "false")]
+    all: bool,
+
+    /// Whether to display bottleneck diagnosis, with the likely bottleneck of each busy GPU. Defaults to false.
+    #[arg(short, long, alias = "detect-bottleneck", default_value = "false")]
+    bottleneck: bool,
+
+    /// Whether to exit with code 2 if the diagnosis finds an issue at --fail-level or above, or 1 if collecting stats fails or a GPU or the host stats couldn't be collected, e.g. for `bmon --exit-on-issue && sbatch job.sh`. The diagnosis runs even without --bottleneck. Defaults to false.
+    #[arg(long, default_value = "false", conflicts_with_all = ["tui", "daemon"])]
+    exit_on_issue: bool,
+
+    /// Lowest severity that makes --exit-on-issue fail. Defaults to warn.
+    #[arg(long, value_enum, default_value = "warn", requires = "exit_on_issue")]
+    fail_level: FailLevel,
+
+    /// Whether to print nothing, e.g. to only use the exit code of --exit-on-issue. Defaults to false.
+    #[arg(short, long, default_value = "false")]
+    quiet: bool,
+
+    /// Whether to display CPU stats. Defaults to false.
+    #[arg(short, long, default_value = "false")]
+    cpu: bool,
+
+    /// Whether to display extra information. Defaults to false.
+    #[arg(short, long, default_value = "false")]
+    verbose: bool,
+
+    /// Only display processes that have been running for at least this many minutes. Defaults to 0.
+    #[arg(long, default_value = "0", value_name = "MINUTES")]
+    min_runtime: u64,
+
+    /// Comma-separated indices of the only GPUs to display, e.g. `0,1`, along with their processes.
+    #[arg(
+        long,
+        value_name = "INDICES",
+        value_delimiter = ',',
+        conflicts_with = "exclude_gpus"
+    )]
+    gpus: Option<Vec<u32>>,
+
+    /// Comma-separated indices of GPUs to hide, e.g. `0` for a display adapter, along with processes that only run on them.
+    #[arg(long, value_name = "INDICES", value_delimiter = ',')]
+    exclude_gpus: Vec<u32>,
+
+    /// Hide GPUs with utilization below this from the GPU table, unless they meet --min-mem-util. Other sections still include them.
+    #[arg(long, value_name = "PCT", value_parser = clap::value_parser!(u32).range(0..=100))]
+    min_gpu_util: Option<u32>,
+
+    /// Hide GPUs with memory utilization below this from the GPU table, unless they meet --min-gpu-util. Other sections still include them.
+    #[arg(long, value_name = "PCT", value_parser = clap::value_parser!(u32).range(0..=100))]
+    min_mem_util: Option<u32>,
+
+    /// Comma-separated PIDs to display even if they aren't using a GPU, e.g. a data loader. They are marked with `*` in the Source column.
+    #[arg(long, value_name = "PIDS", value_delimiter = ',', num_args = 1)]
+    pids: Vec<u32>,
+
+    /// Comma-separated users whose processes are hidden, e.g. `root,jupyter`. Can also be repeated.
+    // one value per flag, so that a following `watch` isn't taken as a user
+    #[arg(long, value_name = "USERS", value_delimiter = ',', num_args = 1)]
+    exclude_users: Vec<String>,
+
+    /// Whether to display full column contents instead of truncating them. Defaults to false.
+    #[arg(long, default_value = "false")]
+    no_truncate: bool,
+
+    /// Whether to use the full terminal width instead of fixed column widths. Defaults to false.
+    #[arg(long, default_value = "false")]
+    wide: bool,
+
+    /// Whether to show the change in GPU utilization, temperature, and memory since the previous refresh instead of absolute values, for watch mode. Defaults to false.
+    #[arg(long, default_value = "false")]
+    delta: bool,
+
+    /// Whether to print one summary line of all GPUs instead of the GPU table, e.g. one line per node for a fleet dashboard. Defaults to false.
+    #[arg(long, default_value = "false", conflicts_with_all = ["tui", "delta"])]
+    aggregate: bool,
+
+    /// Whether to show a bar of each GPU's utilization instead of the GPU table, sized to the terminal width. Defaults to false.
+    #[arg(long, default_value = "false", conflicts_with_all = ["tui", "delta", "aggregate"])]
+    graph: bool,
+
+    /// Whether to print the energy each GPU consumed when watch mode ends, through --count or Ctrl-C. Defaults to false.
+    #[arg(long, default_value = "false")]
+    energy: bool,
+
+    /// Electricity price in $/kWh, to also print what the energy of the watch session cost. Requires --energy.
+    #[arg(long, value_name = "USD_PER_KWH", requires = "energy", value_parser = parse_price)]
+    energy_price: Option<f64>,
+
+    /// Whether to print tables in markdown format, e.g. for GitHub issues. Defaults to false.
+    #[arg(long, default_value = "false")]
+    markdown: bool,
+
+    /// Whether to display stats for completed processes from NVML accounting mode. Defaults to false.
+    #[arg(long, default_value = "false")]
+    accounting: bool,
+
+    /// Whether to also display non-GPU processes using more than 5% CPU or memory. Defaults to false.
+    #[arg(long, default_value = "false")]
+    all_processes: bool,
+
+    /// Whether to hide GPUs that are driving a display, along with processes that only run on them.
+    /// Note that this silently removes those GPUs from all output, including JSON. Defaults to false.
+    #[arg(long, default_value = "false")]
+    ignore_display_gpus: bool,
+
+    /// Whether to display the number of GPU memory pages retired due to ECC errors. Defaults to false.
+    #[arg(long, default_value = "false")]
+    retired_pages: bool,
+
+    /// Whether to display the GPU slowdown and shutdown temperatures. Always shown in verbose mode. Defaults to false.
+    #[arg(long, default_value = "false")]
+    thermal_limits: bool,
 
-        if !verbose {
-            // only display the first 6 columns in non-verbose mode
-            table.with(Extract::segment(0.., 0..6));
+    /// Whether to display read/write throughput, IOPS, and utilization of each physical disk. Defaults to false.
+    #[arg(long, default_value = "false")]
+    disk: bool,
+
+    /// Whether to also include loop, ram, and device-mapper devices and partitions in --disk. Defaults to false.
+    #[arg(long, default_value = "false", requires = "disk")]
+    all_disks: bool,
+
+    /// Whether to display receive and transmit throughput of each network interface that is up. Defaults to false.
+    #[arg(long, default_value = "false")]
+    net: bool,
+
+    /// Whether to also include virtual interfaces such as docker0 and veth pairs in --net. Defaults to false.
+    #[arg(long, default_value = "false", requires = "net")]
+    all_interfaces: bool,
+
+    /// Whether to display read throughput and RPC latency of NFS and Lustre mounts, if there are any. Defaults to false.
+    #[arg(long, default_value = "false")]
+    netfs: bool,
+
+    /// Whether to display usage of the filesystems behind --fs-paths. Defaults to false.
+    #[arg(long, default_value = "false")]
+    fs: bool,
+
+    /// Comma-separated paths whose filesystems are checked. Defaults to /, /tmp, /dev/shm and the current directory.
+    #[arg(long, value_name = "PATHS", value_delimiter = ',')]
+    fs_paths: Option<Vec<PathBuf>>,
+
+    /// Milliseconds to average CPU, disk, and network rates over. The window overlaps with the GPU queries, so usually adds less than this to the runtime. Defaults to 250.
+    #[arg(long, default_value = "250", value_name = "MS")]
+    sample_ms: u64,
+
+    /// Whether to display the NUMA topology, which node each GPU is attached to, and processes pinned to the wrong node. Defaults to false.
+    #[arg(long, default_value = "false")]
+    numa: bool,
+
+    /// Context switches per second per core above which the bottleneck diagnosis suggests fewer dataloader workers. Defaults to 10000.
+    #[arg(long, default_value = "10000", value_name = "RATE", value_parser = parse_rate)]
+    ctxt_threshold: f32,
+
+    /// GPU memory usage (%) above which the bottleneck diagnosis warns that the GPU may run out of memory. Defaults to 95.
+    // the old name is kept for existing scripts
+    #[arg(long, alias = "memory-threshold", default_value = "95", value_name = "PCT", value_parser = parse_pct)]
+    warn_mem_pct: f32,
+
+    /// GPU temperature (°C) at or above which the bottleneck diagnosis warns, e.g. 85 for boxes that intentionally run hot. Defaults to 10°C below each GPU's slowdown temperature.
+    #[arg(long, value_name = "CELSIUS", value_parser = clap::value_parser!(u32).range(1..=150))]
+    warn_temp: Option<u32>,
+
+    /// IO wait or IO pressure (%) above which the bottleneck diagnosis blames low GPU utilization on slow storage. Defaults to 10.
+    // the alias is the input pipeline diagnosis's old flag for the same limit
+    #[arg(long, alias = "starved-io-threshold", default_value = "10", value_name = "PCT", value_parser = parse_pct)]
+    warn_iowait: f32,
+
+    /// GPU utilization (%) below which the bottleneck diagnosis considers a GPU with processes underutilized, and checks whether the input pipeline is starving it. Defaults to 40.
+    // the alias is the input pipeline diagnosis's old flag for the same limit
+    #[arg(long, alias = "starved-util-threshold", default_value = "40", value_name = "PCT", value_parser = clap::value_parser!(u32).range(0..=100))]
+    low_util: u32,
+
+    /// Print all stats as JSON instead of tables, same as `--format json`. Defaults to false.
+    #[arg(long, default_value = "false", conflicts_with = "format")]
+    json: bool,
+
+    /// Whether to print the JSON Schema (draft 7) of the --json output and exit, with the diagnostics if --bottleneck or --all is given. Defaults to false.
+    #[arg(long, default_value = "false")]
+    json_schema: bool,
+
+    /// Output format. Defaults to table.
+    #[arg(long, value_enum, default_value = "table")]
+    format: Format,
+
+    /// Launch an interactive terminal UI that refreshes every second. Defaults to false.
+    #[arg(long, default_value = "false", conflicts_with_all = ["json", "format", "daemon"])]
+    tui: bool,
+
+    /// Run in the background, appending JSON snapshots to --log-file. Defaults to false.
+    #[arg(long, default_value = "false", requires = "log_file")]
+    daemon: bool,
+
+    /// File that daemon mode appends snapshots to.
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+
+    /// Seconds between snapshots in daemon mode, more than 0. Defaults to 5.
+    #[arg(long, default_value = "5", value_name = "SECONDS", value_parser = parse_interval)]
+    interval: f32,
+
+    /// Stop a running bmon daemon. Defaults to false.
+    #[arg(long, default_value = "false", conflicts_with = "daemon")]
+    stop: bool,
+
+    /// Print the supported memory and SM clocks of each GPU instead of the usual tables, e.g. before `nvidia-smi --lock-gpu-clocks`. Defaults to false.
+    #[arg(long, alias = "list-clocks", default_value = "false", conflicts_with_all = ["tui", "daemon"])]
+    supported_clocks: bool,
+
+    /// Write each GPU's clocks, temperature and compute capability to this JSON file, e.g. before a driver update, for --compare-baseline.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["tui", "daemon", "compare_baseline"])]
+    export_baseline: Option<PathBuf>,
+
+    /// Compare the GPUs against a file from --export-baseline, printing any deviations and exiting with 2 if there are some.
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["tui", "daemon"])]
+    compare_baseline: Option<PathBuf>,
+
+    /// Send --signal to this process and exit, e.g. to stop a runaway job from a script.
+    #[arg(long, value_name = "PID", conflicts_with_all = ["tui", "daemon"])]
+    kill_pid: Option<u32>,
+
+    /// Signal sent by --kill-pid. Defaults to term.
+    #[arg(long, value_enum, default_value = "term", requires = "kill_pid")]
+    signal: Signal,
+
+    /// Config file with defaults for any of these flags, e.g. `verbose = true`. Flags on the command line take precedence. Defaults to ~/.config/bmon/config.toml.
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Whether to print the effective configuration, merged from the config file and command line, and exit. Defaults to false.
+    #[arg(long, default_value = "false")]
+    dump_config: bool,
+
+    /// The most verbose messages to log to stderr, e.g. debug to see each GPU queried and the queries that failed. Defaults to warn, or to the BMON_LOG environment variable if it's set.
+    #[arg(long, value_enum, value_name = "LEVEL")]
+    log_level: Option<log::Level>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum FailLevel {
+    Warn,
+    Crit,
+}
+
+impl FailLevel {
+    fn severity(self) -> Severity {
+        match self {
+            FailLevel::Warn => Severity::Warn,
+            FailLevel::Crit => Severity::Crit,
         }
+    }
+}
 
-        // set name width to be exactly 15 characters
-        // other columns have fixed width already
-        let name_col_width = { 15 };
-        table.with(
-            Modify::new(Columns::new(1..2))
-                .with(Width::truncate(name_col_width).suffix("..."))
-                .with(Width::increase(name_col_width)),
-        );
+// exit codes of --exit-on-issue
+const EXIT_COLLECTION_FAILED: i32 = 1;
+const EXIT_ISSUE_FOUND: i32 = 2;
 
-        table.with(Panel::header(format!(
-            "Driver Version: {}  CUDA Version: {}",
-            self.driver_version, self.cuda_version
-        )));
+#[derive(Subcommand)]
+enum Command {
+    /// Print a fresh snapshot every --interval seconds, until interrupted or --count snapshots have been printed.
+    Watch {
+        /// Seconds between snapshots, more than 0. Defaults to 2.
+        #[arg(long, default_value = "2", value_name = "SECONDS", value_parser = parse_interval)]
+        interval: f32,
 
-        table.with(Style::re_structured_text());
-        println!("\nGPU Usage:");
-        println!("{}", table);
+        /// Number of snapshots to print before exiting, at least 1. Defaults to running until interrupted.
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+        count: Option<u32>,
+    },
+    /// Check that NVML works and every GPU field bmon needs can be read, exiting with 1 if any check fails.
+    #[command(alias = "check")]
+    CheckNvml,
+}
+
+/// Parses the seconds between snapshots, which `Duration::from_secs_f32` would
+/// panic on if negative or not finite, and which would be a busy loop if 0.
+fn parse_interval(value: &str) -> Result<f32, String> {
+    let secs = value
+        .parse::<f32>()
+        .map_err(|_| format!("`{}` isn't a number", value))?;
+    if !secs.is_finite() || secs <= 0.0 {
+        return Err(format!("{} is not a positive number of seconds", secs));
+    }
+    Ok(secs)
+}
+
+/// Parses the --energy-price, which would print a meaningless cost if negative or not finite.
+fn parse_price(value: &str) -> Result<f64, String> {
+    let price = value
+        .parse::<f64>()
+        .map_err(|_| format!("`{}` isn't a number", value))?;
+    if !price.is_finite() || price < 0.0 {
+        return Err(format!("{} is not a price of 0 or more", price));
     }
+    Ok(price)
+}
 
-    fn display_cpu_stats(&self, verbose: bool) {
-        let mut table = Table::new(&self.processes);
-        let truncate_width = if verbose { 75 } else { 20 };
-        table.with(Modify::new(Rows::new(0..)).with(Width::truncate(truncate_width).suffix("...")));
+/// A collector of the machine stats in `options`, for the GPUs chosen on the command line.
+fn collector(args: &Args, options: CollectOptions) -> Collector {
+    try_collector(args, options).unwrap_or_else(|e| exit_collection_failed(e))
+}
 
-        table.with(Panel::header(format!(
-            "Num CPUs: {}  RAM Capacity: {}  IO Wait: {}  Steal: {}  Idle: {}",
-            self.num_cpus, self.ram_capacity, self.iowait, self.steal, self.idle
-        )));
+/// Like `collector`, but returns the error rather than exiting, e.g. for the
+/// daemon to log it and retry.
+fn try_collector(args: &Args, options: CollectOptions) -> Result<Collector, BmonError> {
+    let all_processes = args.all_processes && options.processes;
+    let mut options = options
+        .all_processes(all_processes)
+        .exclude_gpus(args.exclude_gpus.iter().copied())
+        .pids(args.pids.iter().copied())
+        .sample_window(Duration::from_millis(args.sample_ms));
+    if let Some(gpus) = &args.gpus {
+        options = options.gpus(gpus.iter().copied());
+    }
+    if let Some(fs_paths) = &args.fs_paths {
+        options = options.fs_paths(fs_paths);
+    }
+    Collector::new(options)
+}
 
-        // set PID col to be min 7 characters
-        // we cannot set with the rest because the truncation messes up the header
-        table.with(Modify::new(Columns::new(0..1)).with(Width::increase(7)));
+/// Collects a snapshot, applying any process filters from the command line.
+fn collect(args: &Args, collector: &Collector) -> Machine {
+    try_collect(args, collector).unwrap_or_else(|e| exit_collection_failed(e))
+}
 
-        // set fixed col widths (except for the PID col)
-        let col_widths = if !verbose {
-            vec![8, 20, 10, 22]
+/// Like `collect`, but returns the error rather than exiting, e.g. for the TUI
+/// to restore the terminal first.
+fn try_collect(args: &Args, collector: &Collector) -> Result<Machine, BmonError> {
+    let mut machine = collector.collect()?;
+    machine.processes.retain(|process| {
+        process.manual || process.elapsed_secs >= args.min_runtime.saturating_mul(60)
+    });
+    machine
+        .processes
+        .retain(|process| !args.exclude_users.contains(&process.user));
+    if args.ignore_display_gpus {
+        machine.retain_gpus(|gpu| gpu.display != "Active");
+    }
+    Ok(machine)
+}
+
+fn exit_collection_failed(e: BmonError) -> ! {
+    bmon::error!("failed to collect stats: {}", e);
+    std::process::exit(EXIT_COLLECTION_FAILED);
+}
+
+/// Parses the command line on top of the defaults from the config file.
+fn parse_args() -> (Args, ArgMatches) {
+    let cli = std::env::args_os().collect::<Vec<_>>();
+    let cli_matches = Args::command().get_matches_from(&cli);
+
+    let explicit = cli_matches.get_one::<PathBuf>("config");
+    let mut config_args = vec![];
+    if let Some(path) = explicit.cloned().or_else(config::default_path) {
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => match config::parse(&contents) {
+                Ok(entries) => {
+                    config_args = config::to_args(&Args::command(), &entries, |id| {
+                        cli_matches.value_source(id) == Some(ValueSource::CommandLine)
+                    });
+                }
+                Err((line, reason)) => {
+                    eprintln!(
+                        "invalid config file {}:{}: {}",
+                        path.display(),
+                        line,
+                        reason
+                    );
+                    std::process::exit(1);
+                }
+            },
+            // the default config file is optional
+            Err(e) if explicit.is_some() || e.kind() != io::ErrorKind::NotFound => {
+                eprintln!("failed to read config file {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+            Err(_) => {}
+        }
+    }
+
+    // the config goes first, so it stays before any subcommand
+    let argv = cli[..1]
+        .iter()
+        .cloned()
+        .chain(config_args.into_iter().map(Into::into))
+        .chain(cli[1..].iter().cloned());
+    let matches = Args::command().get_matches_from(argv);
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    (args, matches)
+}
+
+fn main() {
+    log::init();
+    let (args, matches) = parse_args();
+    if let Some(level) = args.log_level {
+        log::set_level(level);
+    }
+
+    if args.dump_config {
+        println!("{}", config::dump(&Args::command(), &matches));
+        return;
+    }
+
+    if args.json_schema {
+        println!("{}", json_renderer(&args).json_schema());
+        return;
+    }
+
+    if let Some(Command::CheckNvml) = args.command {
+        let checks = check::check_nvml();
+        for check in &checks {
+            println!("{}", check.format());
+        }
+        let passed = checks.iter().all(check::Check::passed);
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    if args.supported_clocks {
+        let gpus = clocks::query_supported_clocks().unwrap_or_else(|e| {
+            eprintln!("failed to query supported clocks: {}", e);
+            std::process::exit(1);
+        });
+        if output_format(&args) == Format::Json {
+            println!("{}", clocks::supported_clocks_json(&gpus));
         } else {
-            vec![8, 20, 10, 75]
-        };
-        for (i, width) in col_widths.iter().enumerate() {
-            table.with(
-                Modify::new(Columns::new(i + 1..i + 2))
-                    .with(Width::truncate(*width).suffix("..."))
-                    .with(Width::increase(*width)),
+            for gpu in gpus {
+                println!("{}", gpu.format());
+            }
+        }
+        return;
+    }
+
+    if let Some(path) = &args.export_baseline {
+        let machine = collect(&args, &collector(&args, CollectOptions::GPUS_ONLY));
+        let baseline = Baseline::from_machine(&machine);
+        if let Err(e) = std::fs::write(path, baseline.to_json().to_string()) {
+            eprintln!("failed to write baseline {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+        println!(
+            "Saved a baseline of {} GPUs to {}",
+            baseline.gpus.len(),
+            path.display()
+        );
+        return;
+    }
+
+    if let Some(path) = &args.compare_baseline {
+        let saved = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| Baseline::parse(&contents))
+            .unwrap_or_else(|e| {
+                eprintln!("failed to read baseline {}: {}", path.display(), e);
+                std::process::exit(1);
+            });
+        let machine = collect(&args, &collector(&args, CollectOptions::GPUS_ONLY));
+        let current = Baseline::from_machine(&machine);
+        if current.driver_version != saved.driver_version {
+            println!(
+                "Driver {} (baseline {})",
+                current.driver_version, saved.driver_version
             );
         }
+        let deviations = saved.compare(&current);
+        if deviations.is_empty() {
+            println!("No deviations from the baseline");
+            return;
+        }
+        for deviation in &deviations {
+            println!("{}", deviation);
+        }
+        std::process::exit(EXIT_ISSUE_FOUND);
+    }
+
+    if let Some(pid) = args.kill_pid {
+        let result = ProcessStats::from_pid(pid)
+            .ok_or(BmonError::NoSuchProcess { pid })
+            .and_then(|process| process.send_signal(args.signal));
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.stop {
+        if let Err(e) = daemon::stop() {
+            eprintln!("failed to stop bmon daemon: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args.daemon {
+        let log_file = args.log_file.as_ref().unwrap();
+        // NVML can't be initialised until the daemon has forked, and is retried
+        // on the next snapshot if it fails
+        let cached = OnceCell::new();
+        if let Err(e) = daemon::start(log_file, Duration::from_secs_f32(args.interval), || {
+            if cached.get().is_none() {
+                let _ = cached.set(try_collector(&args, CollectOptions::ALL)?);
+            }
+            let machine = try_collect(&args, cached.get().unwrap())?;
+            Ok(json_renderer(&args).to_json(&machine))
+        }) {
+            eprintln!("failed to start bmon daemon: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let options = DisplayOptions {
+        verbose: args.verbose,
+        truncate: !args.no_truncate,
+        markdown: args.markdown,
+        retired_pages: args.retired_pages,
+        thermal_limits: args.thermal_limits,
+        // the terminal width is meaningless when piping, so keep fixed widths
+        wide: args.wide && std::io::stdout().is_terminal(),
+        min_gpu_util: args.min_gpu_util,
+        min_mem_util: args.min_mem_util,
+    };
+
+    if args.tui {
+        let collector = collector(&args, tui::COLLECT_OPTIONS);
+        match tui::run(|| try_collect(&args, &collector), options) {
+            Ok(()) => {}
+            Err(BmonError::Terminal(e)) => {
+                eprintln!("failed to run TUI: {}", e);
+                std::process::exit(1);
+            }
+            Err(e) => exit_collection_failed(e),
+        }
+        return;
+    }
+
+    if let Some(Command::Watch { interval, count }) = args.command {
+        watch(&args, options, interval, count);
+        return;
+    }
+
+    let mut renderer = renderer(&args, options);
+    let mut collect_options = if args.quiet {
+        CollectOptions::GPUS_ONLY
+    } else {
+        renderer.collect_options()
+    };
+    if args.exit_on_issue {
+        collect_options = collect_options.union(REQUIRED_STATS);
+    }
+    let machine = collect(&args, &collector(&args, collect_options));
+    if !args.quiet {
+        if let Err(e) = renderer.render(&machine, &mut io::stdout().lock()) {
+            eprintln!("failed to write output: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if args.exit_on_issue {
+        // a GPU that couldn't be read may well be the issue
+        if machine.is_incomplete() {
+            bmon::error!("a GPU or the host stats couldn't be collected, see the warnings");
+            std::process::exit(EXIT_COLLECTION_FAILED);
+        }
+        let findings = diagnose(&machine, &thresholds(&args));
+        let fail_level = args.fail_level.severity();
+        if findings
+            .iter()
+            .any(|finding| finding.severity >= fail_level)
+        {
+            std::process::exit(EXIT_ISSUE_FOUND);
+        }
+    }
+}
 
-        table.with(Style::re_structured_text());
-        println!("\nCPU Usage:");
-        println!("{}", table);
+/// Prints a snapshot every `interval` seconds. Tables clear the screen in
+/// between, while the other formats append one snapshot after another.
+fn watch(args: &Args, options: DisplayOptions, interval: f32, count: Option<u32>) {
+    let format = output_format(args);
+    let mut renderer: Box<dyn Renderer> = if format == Format::Table {
+        let mut renderer = table_renderer(args, options);
+        renderer.history = Some(HashMap::new());
+        renderer.memory_trends = Some(HashMap::new());
+        Box::new(renderer)
+    } else {
+        renderer(args, options)
+    };
+    let mut peaks: Vec<GpuSessionPeak> = vec![];
+    let mut sessions: Vec<GpuSession> = vec![];
+    let mut remaining = count;
+    if args.energy {
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+        unsafe {
+            libc::signal(
+                libc::SIGINT,
+                on_sigint as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            )
+        };
     }
+    let collector = collector(args, renderer.collect_options());
+    let mut previous: Option<(Machine, Instant)> = None;
+    loop {
+        let machine = collect(args, &collector);
+        let now = Instant::now();
+        let delta = previous
+            .as_ref()
+            .map(|(previous, taken)| machine.diff(previous, now.duration_since(*taken)));
+        for gpu in &machine.gpus {
+            let peak = match peaks.iter().position(|peak| peak.idx == gpu.idx) {
+                Some(i) => &mut peaks[i],
+                None => {
+                    peaks.push(GpuSessionPeak::new(gpu.idx));
+                    peaks.last_mut().unwrap()
+                }
+            };
+            peak.update(gpu);
+            let session = match sessions.iter().position(|session| session.idx == gpu.idx) {
+                Some(i) => &mut sessions[i],
+                None => {
+                    sessions.push(GpuSession::new(gpu.idx));
+                    sessions.last_mut().unwrap()
+                }
+            };
+            if let Some(delta) = &delta {
+                if let Some(rates) = delta.gpus.iter().find(|rates| rates.idx == gpu.idx) {
+                    peak.add_rates(rates, delta.elapsed);
+                    session.add(gpu, rates, delta.elapsed);
+                }
+            }
+        }
+        if format == Format::Table {
+            print!("\x1b[H\x1b[2J");
+        }
+        if let Err(e) = renderer.render(&machine, &mut io::stdout().lock()) {
+            eprintln!("failed to write output: {}", e);
+            std::process::exit(1);
+        }
+        previous = Some((machine, now));
+        if let Some(n) = &mut remaining {
+            *n -= 1;
+            if *n == 0 {
+                if format == Format::Table {
+                    println!("\nSession Peaks:");
+                    println!("{}", session_peaks_table(&peaks, options));
+                    if args.energy {
+                        print_session_energy(&sessions, args.energy_price);
+                    }
+                }
+                return;
+            }
+        }
 
-    fn display_bottleneck_diagnostics(&self) {
-        println!("\nBottleneck diagnosis:");
-        for gpu in &self.gpus {
-            if gpu.throttling.is_empty() {
-                continue;
+        // sleep in short steps so that Ctrl-C is handled promptly
+        let wake_at = Instant::now() + Duration::from_secs_f32(interval);
+        while Instant::now() < wake_at && !INTERRUPTED.load(Ordering::Relaxed) {
+            thread::sleep(INTERRUPT_POLL.min(wake_at.saturating_duration_since(Instant::now())));
+        }
+        if INTERRUPTED.load(Ordering::Relaxed) {
+            if format == Format::Table {
+                print_session_energy(&sessions, args.energy_price);
             }
-            println!("GPU {} is throttling due to: {:?}", gpu.idx, gpu.throttling);
+            return;
         }
     }
 }
 
-const PKG_AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
-const PKG_DESC: &str = env!("CARGO_PKG_DESCRIPTION");
-const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
+// set by the SIGINT handler that --energy installs in watch mode
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
-#[derive(Parser)]
-#[command(author=PKG_AUTHORS, version=PKG_VERSION, about=PKG_DESC)]
-struct Args {
-    /// Displays all possible stats, equivalent to -bc
-    #[arg(short, long, default_value = "false")]
-    all: bool,
+const INTERRUPT_POLL: Duration = Duration::from_millis(100);
 
-    /// Whether to display bottleneck diagnosis. Defaults to false.
-    #[arg(short, long, default_value = "false")]
-    bottleneck: bool,
+extern "C" fn on_sigint(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
 
-    /// Whether to display CPU stats. Defaults to false.
-    #[arg(short, long, default_value = "false")]
-    cpu: bool,
+/// Prints the energy each GPU consumed over the watch session, for --energy, and its cost
+/// at `price` ($/kWh).
+fn print_session_energy(sessions: &[GpuSession], price: Option<f64>) {
+    println!("\n{}", session_energy_summary(sessions));
+    if let Some(price) = price {
+        let total_wh = session_total_wh(sessions);
+        println!(
+            "Session Cost: ${:.4} ({:.1} Wh at ${}/kWh)",
+            total_wh / 1000.0 * price,
+            total_wh,
+            price
+        );
+    }
+}
 
-    /// Whether to display extra information. Defaults to false.
-    #[arg(short, long, default_value = "false")]
-    verbose: bool,
+/// --json is shorthand for --format json.
+fn output_format(args: &Args) -> Format {
+    if args.json {
+        Format::Json
+    } else {
+        args.format
+    }
 }
 
-fn main() {
-    let args: Args = Args::parse();
-    let machine = Machine::new();
+/// The renderer for the --format selected on the command line.
+fn renderer(args: &Args, options: DisplayOptions) -> Box<dyn Renderer> {
+    match output_format(args) {
+        Format::Table => Box::new(table_renderer(args, options)),
+        Format::Json => Box::new(json_renderer(args)),
+        Format::Csv => Box::<CsvRenderer>::default(),
+        Format::Prometheus => Box::new(PrometheusRenderer),
+        Format::Influx => Box::new(InfluxRenderer),
+    }
+}
 
-    machine.display_gpu_stats(args.verbose);
+fn thresholds(args: &Args) -> Thresholds {
+    Thresholds {
+        temp: args.warn_temp,
+        memory_pct: args.warn_mem_pct,
+        iowait: args.warn_iowait,
+        low_util: args.low_util,
+        ctxt: args.ctxt_threshold,
+    }
+}
 
-    if args.cpu || args.all {
-        machine.display_cpu_stats(args.verbose);
+/// Includes the diagnosis when the bottleneck section is selected.
+fn json_renderer(args: &Args) -> JsonRenderer {
+    JsonRenderer {
+        diagnostics: args.bottleneck || args.all,
+        thresholds: thresholds(args),
     }
+}
 
-    if args.bottleneck || args.all {
-        machine.display_bottleneck_diagnostics();
+/// The sections selected on the command line.
+fn table_renderer(args: &Args, options: DisplayOptions) -> TableRenderer {
+    TableRenderer {
+        options,
+        cpu: args.cpu || args.all,
+        accounting: args.accounting,
+        numa: args.numa,
+        disk: args.disk,
+        all_disks: args.all_disks,
+        net: args.net,
+        all_interfaces: args.all_interfaces,
+        netfs: args.netfs,
+        fs: args.fs,
+        bottleneck: args.bottleneck || args.all,
+        aggregate: args.aggregate,
+        graph: args.graph,
+        thresholds: thresholds(args),
+        history: None,
+        memory_trends: None,
+        delta: args.delta,
+        previous: None,
     }
 }
@@ -0,0 +1,311 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+#[cfg(feature = "render")]
+use tabled::Tabled;
+
+use crate::json::Json;
+use crate::schema;
+
+const LUSTRE_LLITE_DIR: &str = "/proc/fs/lustre/llite";
+
+/// Read throughput and RPC latency of one network filesystem mount, averaged over a sample window.
+#[cfg_attr(feature = "render", derive(Tabled))]
+#[cfg_attr(feature = "render", tabled(rename_all = "PascalCase"))]
+pub struct NetFsStats {
+    #[cfg_attr(feature = "render", tabled(rename = "Mount"))]
+    pub mount: String,
+    #[cfg_attr(feature = "render", tabled(rename = "Type"))]
+    pub fstype: String,
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "Read", display_with("Self::display_read", self))
+    )]
+    pub read_bytes_per_sec: f32,
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "Ops", display_with("Self::display_ops", self))
+    )]
+    pub ops_per_sec: f32,
+    // None if no RPCs completed during the window, or for Lustre
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "RPC Latency", display_with("Self::display_latency", self))
+    )]
+    pub avg_rpc_latency_ms: Option<f32>,
+}
+
+impl NetFsStats {
+    #[cfg(feature = "render")]
+    fn display_read(&self) -> String {
+        format!("{:.1}MB/s", self.read_bytes_per_sec / 1e6)
+    }
+
+    #[cfg(feature = "render")]
+    fn display_ops(&self) -> String {
+        format!("{:.0}/s", self.ops_per_sec)
+    }
+
+    #[cfg(feature = "render")]
+    fn display_latency(&self) -> String {
+        match self.avg_rpc_latency_ms {
+            Some(latency) => format!("{:.1}ms", latency),
+            None => "-".to_string(),
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("mount", (&self.mount).into()),
+            ("fstype", (&self.fstype).into()),
+            ("read_bytes_per_sec", self.read_bytes_per_sec.into()),
+            ("ops_per_sec", self.ops_per_sec.into()),
+            ("avg_rpc_latency_ms", self.avg_rpc_latency_ms.into()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        schema::object(vec![
+            ("mount", schema::string()),
+            ("fstype", schema::string()),
+            ("read_bytes_per_sec", schema::number()),
+            ("ops_per_sec", schema::number()),
+            ("avg_rpc_latency_ms", schema::nullable(schema::number())),
+        ])
+    }
+}
+
+/// Cumulative counters for one network filesystem mount.
+pub struct NetFsCounters {
+    mount: String,
+    fstype: String,
+    read_bytes: u64,
+    ops: u64,
+    rtt: Duration, // summed over all ops
+}
+
+/// Returns the counters of every NFS and Lustre mount, or nothing if there are none.
+pub fn read_netfs() -> Vec<NetFsCounters> {
+    let mut counters = fs::read_to_string("/proc/self/mountstats")
+        .map(|mountstats| parse_mountstats(&mountstats))
+        .unwrap_or_default();
+    counters.extend(read_lustre(Path::new(LUSTRE_LLITE_DIR)));
+    counters
+}
+
+/// Parses the NFS stanzas of /proc/self/mountstats, which look like
+/// ```text
+/// device nfs01:/datasets mounted on /mnt/datasets with fstype nfs4 statvers=1.1
+///     ...
+///     bytes:  <normal read> <normal write> <direct read> <direct write> <server read> ...
+///     ...
+///     per-op statistics
+///             READ: <ops> <trans> <timeouts> <bytes sent> <bytes recv> <queue ms> <rtt ms> <execute ms>
+/// ```
+/// Other filesystems only have the `device` line and are skipped.
+fn parse_mountstats(mountstats: &str) -> Vec<NetFsCounters> {
+    let mut mounts: Vec<NetFsCounters> = vec![];
+    // whether the current stanza is NFS, and if so whether its per-op section was reached
+    let mut in_nfs = false;
+    let mut in_per_op = false;
+    for line in mountstats.lines() {
+        let line = line.trim();
+        if let Some(device) = line.strip_prefix("device ") {
+            // <device> mounted on <mount> with fstype <fstype>
+            let words = device.split_whitespace().collect::<Vec<&str>>();
+            in_nfs = words.get(6).is_some_and(|fstype| fstype.starts_with("nfs"));
+            in_per_op = false;
+            if in_nfs {
+                mounts.push(NetFsCounters {
+                    mount: words[3].to_string(),
+                    fstype: words[6].to_string(),
+                    read_bytes: 0,
+                    ops: 0,
+                    rtt: Duration::ZERO,
+                });
+            }
+            continue;
+        }
+        let Some(mount) = mounts.last_mut().filter(|_| in_nfs) else {
+            continue;
+        };
+        if line == "per-op statistics" {
+            in_per_op = true;
+        } else if let Some(bytes) = line.strip_prefix("bytes:") {
+            // bytes actually read from the server, unlike normal read which includes page cache hits
+            mount.read_bytes = bytes
+                .split_whitespace()
+                .nth(4)
+                .and_then(|b| b.parse().ok())
+                .unwrap_or(0);
+        } else if in_per_op {
+            let Some((_, counters)) = line.split_once(':') else {
+                continue;
+            };
+            let fields = counters
+                .split_whitespace()
+                .map(|field| field.parse::<u64>().unwrap_or(0))
+                .collect::<Vec<u64>>();
+            if fields.len() >= 8 {
+                mount.ops += fields[0];
+                mount.rtt += Duration::from_millis(fields[6]);
+            }
+        }
+    }
+    mounts
+}
+
+/// Reads the client stats of each Lustre filesystem, e.g. llite/scratch-ffff8c1e/stats.
+/// Lustre doesn't expose per-mount RPC latency there, so only bytes and ops are counted.
+fn read_lustre(llite: &Path) -> Vec<NetFsCounters> {
+    let Ok(entries) = fs::read_dir(llite) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let stats = fs::read_to_string(entry.path().join("stats")).ok()?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // the instance suffix is a kernel address, the prefix is the filesystem name
+            let fsname = name
+                .rsplit_once('-')
+                .map_or(name.as_str(), |(fsname, _)| fsname);
+            Some(parse_lustre_stats(fsname, &stats))
+        })
+        .collect()
+}
+
+/// Parses lines like "read_bytes 3880 samples [bytes] 1 4194304 16252928000",
+/// where the last field is the sum.
+fn parse_lustre_stats(fsname: &str, stats: &str) -> NetFsCounters {
+    let mut counters = NetFsCounters {
+        mount: fsname.to_string(),
+        fstype: "lustre".to_string(),
+        read_bytes: 0,
+        ops: 0,
+        rtt: Duration::ZERO,
+    };
+    for line in stats.lines() {
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+        let samples = words.get(1).and_then(|s| s.parse::<u64>().ok());
+        match (words.first(), samples) {
+            (Some(&"snapshot_time"), _) | (_, None) => {}
+            (Some(&"read_bytes"), Some(samples)) => {
+                counters.ops += samples;
+                counters.read_bytes = words.last().and_then(|s| s.parse().ok()).unwrap_or(0);
+            }
+            (_, Some(samples)) => counters.ops += samples,
+        }
+    }
+    counters
+}
+
+/// Computes per-mount rates from two sets of counters taken `window` apart.
+/// Mounts that appeared in between are skipped.
+pub fn get_netfs_stats(
+    before: &[NetFsCounters],
+    after: &[NetFsCounters],
+    window: Duration,
+) -> Vec<NetFsStats> {
+    let secs = window.as_secs_f32();
+    if secs == 0.0 {
+        return vec![];
+    }
+    after
+        .iter()
+        .filter_map(|after| {
+            let before = before
+                .iter()
+                .find(|before| before.mount == after.mount && before.fstype == after.fstype)?;
+            let ops = after.ops.saturating_sub(before.ops);
+            let rtt = after.rtt.saturating_sub(before.rtt);
+            let avg_rpc_latency_ms =
+                (ops > 0 && after.fstype != "lustre").then(|| rtt.as_millis() as f32 / ops as f32);
+            Some(NetFsStats {
+                mount: after.mount.clone(),
+                fstype: after.fstype.clone(),
+                read_bytes_per_sec: after.read_bytes.saturating_sub(before.read_bytes) as f32
+                    / secs,
+                ops_per_sec: ops as f32 / secs,
+                avg_rpc_latency_ms,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // recorded 1s apart on a node reading a dataset over NFS (truncated)
+    const MOUNTSTATS_BEFORE: &str = "\
+device proc mounted on /proc with fstype proc
+device nfs01:/datasets mounted on /mnt/datasets with fstype nfs4 statvers=1.1
+\topts:\trw,vers=4.2,rsize=1048576,wsize=1048576
+\tage:\t86400
+\tevents:\t1 2 3
+\tbytes:\t52428800000 1048576 0 0 41943040000 1048576 10240000 256
+\tRPC iostats version: 1.1  p/v: 100003/4 (nfs)
+\txprt:\ttcp 0 1 0 0 0 0 0 0 0 0 0 0 0
+\tper-op statistics
+\t        NULL: 1 1 0 44 24 0 0 0 0
+\t        READ: 40000 40000 0 6400000 41948160000 2000 80000 84000 0
+\t       WRITE: 10 10 0 1049856 1440 0 50 52 0
+\t     GETATTR: 9000 9000 0 1260000 2160000 90 9000 9200 0
+device tmpfs mounted on /dev/shm with fstype tmpfs
+";
+    const MOUNTSTATS_AFTER: &str = "\
+device proc mounted on /proc with fstype proc
+device nfs01:/datasets mounted on /mnt/datasets with fstype nfs4 statvers=1.1
+\topts:\trw,vers=4.2,rsize=1048576,wsize=1048576
+\tage:\t86401
+\tevents:\t1 2 3
+\tbytes:\t52638515200 1048576 0 0 42152755200 1048576 10291200 256
+\tRPC iostats version: 1.1  p/v: 100003/4 (nfs)
+\txprt:\ttcp 0 1 0 0 0 0 0 0 0 0 0 0 0
+\tper-op statistics
+\t        NULL: 1 1 0 44 24 0 0 0 0
+\t        READ: 40200 40200 0 6432000 42157875200 2100 90000 94500 0
+\t       WRITE: 10 10 0 1049856 1440 0 50 52 0
+\t     GETATTR: 9000 9000 0 1260000 2160000 90 9000 9200 0
+device tmpfs mounted on /dev/shm with fstype tmpfs
+";
+
+    #[test]
+    fn computes_nfs_rates_from_recorded_snapshots() {
+        let before = parse_mountstats(MOUNTSTATS_BEFORE);
+        let after = parse_mountstats(MOUNTSTATS_AFTER);
+        assert_eq!(after.len(), 1);
+
+        let mounts = get_netfs_stats(&before, &after, Duration::from_secs(1));
+        let nfs = &mounts[0];
+        assert_eq!(nfs.mount, "/mnt/datasets");
+        assert_eq!(nfs.fstype, "nfs4");
+        // 200 reads of 1MiB, with 10000ms of RTT between them
+        assert_eq!(nfs.read_bytes_per_sec, 209715200.0);
+        assert_eq!(nfs.ops_per_sec, 200.0);
+        assert_eq!(nfs.avg_rpc_latency_ms, Some(50.0));
+        #[cfg(feature = "render")]
+        assert_eq!(nfs.display_latency(), "50.0ms");
+    }
+
+    #[test]
+    fn parses_lustre_stats() {
+        let stats = "\
+snapshot_time             1697100000.123456 secs.usecs
+read_bytes                3880 samples [bytes] 1 4194304 16252928000
+write_bytes               12 samples [bytes] 4096 1048576 6291456
+open                      420 samples [regs]
+";
+        let counters = parse_lustre_stats("scratch", stats);
+        assert_eq!(counters.read_bytes, 16252928000);
+        assert_eq!(counters.ops, 4312);
+    }
+
+    #[test]
+    fn local_filesystems_are_ignored() {
+        let mountstats = "device proc mounted on /proc with fstype proc\n\
+                          device /dev/nvme0n1p1 mounted on / with fstype ext4\n";
+        assert!(parse_mountstats(mountstats).is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+use std::fs;
+use std::path::Path;
+#[cfg(feature = "render")]
+use tabled::Tabled;
+
+use crate::json::Json;
+use crate::schema;
+
+#[cfg_attr(feature = "render", derive(Tabled))]
+#[cfg_attr(feature = "render", tabled(rename_all = "PascalCase"))]
+pub struct NumaNode {
+    #[cfg_attr(feature = "render", tabled(rename = "Node"))]
+    pub id: u32,
+    // e.g. 0-15,32-47
+    #[cfg_attr(feature = "render", tabled(rename = "CPUs"))]
+    pub cpulist: String,
+    #[cfg_attr(feature = "render", tabled(display_with("Self::display_memory", self)))]
+    pub memory: (u64, u64), // (free, total) in bytes
+    // GPUs whose PCIe bus is attached to this node
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "GPUs", display_with("Self::display_gpus", self))
+    )]
+    pub gpus: Vec<u32>,
+}
+
+impl NumaNode {
+    #[cfg(feature = "render")]
+    fn display_memory(&self) -> String {
+        let (free, total) = self.memory;
+        format!(
+            "{:.1}GB free / {:.1}GB",
+            free as f32 / 1024.0 / 1024.0 / 1024.0,
+            total as f32 / 1024.0 / 1024.0 / 1024.0
+        )
+    }
+
+    #[cfg(feature = "render")]
+    fn display_gpus(&self) -> String {
+        if self.gpus.is_empty() {
+            return "-".to_string();
+        }
+        self.gpus
+            .iter()
+            .map(|idx| idx.to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("id", self.id.into()),
+            ("cpulist", (&self.cpulist).into()),
+            ("memory_free_bytes", self.memory.0.into()),
+            ("memory_total_bytes", self.memory.1.into()),
+            ("gpus", self.gpus.clone().into()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        schema::object(vec![
+            ("id", schema::integer()),
+            ("cpulist", schema::string()),
+            ("memory_free_bytes", schema::integer()),
+            ("memory_total_bytes", schema::integer()),
+            ("gpus", schema::array(schema::integer())),
+        ])
+    }
+}
+
+/// Returns the NUMA nodes with their CPUs and memory usage, sorted by id.
+/// Machines without NUMA support in the kernel have no nodes.
+pub fn get_numa_topology() -> Vec<NumaNode> {
+    let Ok(entries) = fs::read_dir("/sys/devices/system/node") else {
+        return vec![];
+    };
+    let mut nodes = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let id = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("node")?
+                .parse::<u32>()
+                .ok()?;
+            let meminfo = fs::read_to_string(entry.path().join("meminfo")).ok()?;
+            let cpulist = fs::read_to_string(entry.path().join("cpulist")).unwrap_or_default();
+            Some(NumaNode {
+                id,
+                cpulist: cpulist.trim().to_string(),
+                memory: parse_node_meminfo(&meminfo),
+                gpus: vec![],
+            })
+        })
+        .collect::<Vec<NumaNode>>();
+    nodes.sort_by_key(|node| node.id);
+    nodes
+}
+
+/// Parses (free, total) bytes from a per-node meminfo,
+/// e.g. "Node 0 MemTotal:       263921528 kB".
+fn parse_node_meminfo(meminfo: &str) -> (u64, u64) {
+    let field = |name: &str| -> u64 {
+        meminfo
+            .lines()
+            .find_map(|line| {
+                let mut words = line.split_whitespace().skip(2);
+                if words.next()? != name {
+                    return None;
+                }
+                words.next()?.parse::<u64>().ok()
+            })
+            .unwrap_or(0)
+            * 1024
+    };
+    (field("MemFree:"), field("MemTotal:"))
+}
+
+/// Expands a kernel CPU list like `0-3,8,10-11` into the CPU ids.
+/// Malformed ranges are skipped.
+pub fn parse_cpulist(cpulist: &str) -> Vec<u32> {
+    cpulist
+        .trim()
+        .split(',')
+        .filter_map(|range| match range.split_once('-') {
+            Some((start, end)) => {
+                Some((start.parse::<u32>().ok()?..=end.parse::<u32>().ok()?).collect())
+            }
+            None => Some(vec![range.parse::<u32>().ok()?]),
+        })
+        .flatten()
+        .collect()
+}
+
+/// Returns the NUMA node a PCI device is attached to, given its NVML bus id.
+/// None if the device isn't local to any node (sysfs reports -1).
+pub fn pci_numa_node(bus_id: &str) -> Option<u32> {
+    let path = Path::new("/sys/bus/pci/devices")
+        .join(sysfs_pci_address(bus_id))
+        .join("numa_node");
+    fs::read_to_string(path).ok()?.trim().parse::<u32>().ok()
+}
+
+/// Converts NVML's bus id (e.g. "00000000:3B:00.0") to the
+/// sysfs address format (e.g. "0000:3b:00.0").
+fn sysfs_pci_address(bus_id: &str) -> String {
+    let bus_id = bus_id.to_lowercase();
+    // NVML pads the domain to 8 hex digits, sysfs uses 4
+    match bus_id.split_once(':') {
+        Some((domain, rest)) if domain.len() > 4 => {
+            format!("{}:{}", &domain[domain.len() - 4..], rest)
+        }
+        _ => bus_id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_node_meminfo() {
+        let meminfo = "Node 1 MemTotal:       263921528 kB\n\
+                       Node 1 MemFree:        101234560 kB\n\
+                       Node 1 MemUsed:        162686968 kB\n";
+        assert_eq!(
+            parse_node_meminfo(meminfo),
+            (101234560 * 1024, 263921528 * 1024)
+        );
+        assert_eq!(parse_node_meminfo(""), (0, 0));
+    }
+
+    #[test]
+    fn parses_cpulists() {
+        assert_eq!(parse_cpulist("0-3,8,10-11\n"), vec![0, 1, 2, 3, 8, 10, 11]);
+        assert_eq!(parse_cpulist("5"), vec![5]);
+        assert!(parse_cpulist("").is_empty());
+    }
+
+    #[test]
+    fn converts_nvml_bus_ids_to_sysfs_addresses() {
+        assert_eq!(sysfs_pci_address("00000000:3B:00.0"), "0000:3b:00.0");
+        assert_eq!(sysfs_pci_address("0000:af:00.0"), "0000:af:00.0");
+    }
+}
@@ -0,0 +1,72 @@
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+
+// enough to overlap the NVML queries of an 8 GPU node and a few `ps` spawns each
+const MAX_THREADS: usize = 16;
+
+/// Maps `f` over `items` on scoped threads, keeping their order. An item whose
+/// `f` panics gives the panic message instead, so that it only loses itself.
+pub fn map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<Result<R, String>> {
+    if items.is_empty() {
+        return vec![];
+    }
+    let chunk_size = items.len().div_ceil(MAX_THREADS);
+    let f = &f;
+    thread::scope(|scope| {
+        let handles = items
+            .chunks(chunk_size)
+            .map(|chunk| {
+                let handle = scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|item| {
+                            panic::catch_unwind(AssertUnwindSafe(|| f(item))).map_err(panic_message)
+                        })
+                        .collect::<Vec<Result<R, String>>>()
+                });
+                (chunk.len(), handle)
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .flat_map(|(len, handle)| {
+                // nothing outside catch_unwind should panic, but keep the results aligned if it does
+                handle.join().unwrap_or_else(|payload| {
+                    let message = panic_message(payload);
+                    (0..len).map(|_| Err(message.clone())).collect()
+                })
+            })
+            .collect()
+    })
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "panicked".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_order_and_isolates_panics() {
+        let items = (0..40).collect::<Vec<u32>>();
+        let results = map(&items, |i| {
+            if *i == 7 {
+                panic!("bad item {}", i);
+            }
+            i * 2
+        });
+        assert_eq!(results.len(), 40);
+        assert_eq!(results[6], Ok(12));
+        assert_eq!(results[7], Err("bad item 7".to_string()));
+        assert_eq!(results[39], Ok(78));
+    }
+}
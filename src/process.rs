@@ -1,71 +1,1235 @@
+use clap::ValueEnum;
+use std::fs;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+#[cfg(feature = "render")]
+use std::path::Path;
 use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(feature = "render")]
 use tabled::Tabled;
 
-#[derive(Tabled)]
-#[tabled(rename_all = "PascalCase")]
+#[cfg(feature = "render")]
+use crate::accounting::display_pct;
+use crate::cgroup::{get_cgroup_limits, CgroupLimits};
+use crate::error::BmonError;
+use crate::json::Json;
+use crate::log::debug;
+use crate::schema;
+use crate::units::Bytes;
+
+/// Signals that can be sent to a process from bmon.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Signal {
+    /// SIGTERM, asking the process to exit
+    Term,
+    /// SIGKILL, for a process that ignores SIGTERM
+    Kill,
+}
+
+impl Signal {
+    pub fn name(self) -> &'static str {
+        match self {
+            Signal::Term => "SIGTERM",
+            Signal::Kill => "SIGKILL",
+        }
+    }
+
+    fn number(self) -> libc::c_int {
+        match self {
+            Signal::Term => libc::SIGTERM,
+            Signal::Kill => libc::SIGKILL,
+        }
+    }
+}
+
+/// Cumulative counters of a process, which `ProcessStats::rates_since` turns into rates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProcessCounters {
+    pub started: Duration,            // since boot, which tells a reused pid apart
+    pub cpu_time: Duration,           // user and system
+    pub io_bytes: Option<(u64, u64)>, // (read, write), None if /proc/<pid>/io is unreadable
+    pub read_at: Instant,             // for io_rates over the sample window
+}
+
+/// A process's rates between two snapshots, each None when unknown, e.g. because
+/// the pid was reused by another process in between.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProcessRates {
+    pub pid: u32,
+    pub cpu_pct: Option<f32>, // of one core, like ps, but over the interval
+    pub io_rates: Option<(f64, f64)>, // (read, write) in bytes/s
+}
+
+#[cfg_attr(feature = "render", derive(Tabled))]
+#[cfg_attr(feature = "render", tabled(rename_all = "PascalCase"))]
 pub struct ProcessStats {
-    pid: u32,
-    user: String,
-    utilizations: String,
-    elapsed: String,
-    command: String,
+    pub pid: u32,
+    // indices of the GPUs the process is running on
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "GPUS", display_with("Self::display_gpu_indices", self))
+    )]
+    pub gpu_indices: Vec<u32>,
+    // single letter state from /proc/<pid>/stat, e.g. R (running) or D (uninterruptible sleep)
+    pub state: char,
+    pub user: String,
+    // as ps prints them, e.g. "CPU 12.3% RAM 0.5%", see cpu_pct and mem_pct for the numbers
+    pub utilizations: String,
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "IO (R/W)", display_with("Self::display_io_rates", self))
+    )]
+    pub io_rates: Option<(f32, f32)>, // (read, write) in bytes/s, None if /proc/<pid>/io is unreadable
+    pub elapsed: String,
+    // lifetime GPU stats, only available if NVML accounting mode is enabled
+    #[cfg_attr(
+        feature = "render",
+        tabled(
+            rename = "AvgSM",
+            display_with("Self::display_avg_sm_utilization", self)
+        )
+    )]
+    pub avg_sm_utilization: Option<u32>,
+    #[cfg_attr(
+        feature = "render",
+        tabled(
+            rename = "PeakVRAM",
+            display_with("Self::display_peak_gpu_memory", self)
+        )
+    )]
+    pub peak_gpu_memory: Option<Bytes>,
+    pub command: String,
+    // only shown in verbose mode, where the pod replaces it in Kubernetes
+    #[cfg_attr(
+        feature = "render",
+        tabled(
+            rename = "Container",
+            display_with("Self::display_container_name", self)
+        )
+    )]
+    pub container_name: Option<String>,
+    // only shown in verbose mode, as its last two components
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "Cwd", display_with("Self::display_working_dir", self))
+    )]
+    pub working_dir: Option<String>,
+    // only shown in verbose mode, e.g. "0-7,32-39", or "all" if not pinned
+    #[cfg_attr(feature = "render", tabled(rename = "Affinity"))]
+    pub cpu_affinity: String,
+    // only shown with --all-processes
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "GPU", display_with("Self::display_on_gpu", self))
+    )]
+    pub on_gpu: bool,
+    // whether the process was added with --pids rather than found on a GPU,
+    // only shown if there are any
+    #[cfg_attr(
+        feature = "render",
+        tabled(rename = "Source", display_with("Self::display_manual", self))
+    )]
+    pub manual: bool,
+
+    // only looked up when running inside Kubernetes
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub k8s_pod: Option<String>,
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub k8s_namespace: Option<String>,
+    // cumulative counters for io_rates and for rates between snapshots, None if
+    // /proc/<pid>/stat is unreadable
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub counters: Option<ProcessCounters>,
+    // whether the process was in D state at both ends of the sample window
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub blocked: bool,
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub elapsed_secs: u64,
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub cpu_pct: f32,
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub mem_pct: f32,
+    // process group, shared by the ranks of a job launched together (e.g. by torchrun)
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub pgid: Option<u32>,
+    // LOCAL_WORLD_SIZE from the environment, the number of GPUs a torchrun job
+    // expects on this node. Only readable for other users' processes as root
+    #[cfg_attr(feature = "render", tabled(skip))]
+    pub local_world_size: Option<u32>,
 }
 
 impl ProcessStats {
-    pub fn from_pid(pid: u32) -> Self {
+    /// Returns None if the process has already exited, or can't be read.
+    pub fn from_pid(pid: u32) -> Option<Self> {
+        Self::try_from_pid(pid).unwrap_or_else(|e| {
+            debug!("failed to read process {}: {}", pid, e);
+            None
+        })
+    }
+
+    /// Like `from_pid`, but with the reason when `ps` fails or its output can't be parsed.
+    pub fn try_from_pid(pid: u32) -> Result<Option<Self>, String> {
         let ps = Command::new("ps")
             .arg("-p")
             .arg(pid.to_string())
             .arg("-o")
-            .arg("pid=,user=,%cpu=,%mem=,etime=,command=")
+            .arg("pid=,user=,%cpu=,%mem=,etime=,etimes=,command=")
             .output()
-            .expect("failed to execute ps command");
+            .map_err(|e| format!("failed to execute ps: {}", e))?;
 
-        let ps_output = String::from_utf8(ps.stdout).unwrap();
+        let ps_output = String::from_utf8_lossy(&ps.stdout);
+        if ps_output.trim().is_empty() {
+            return Ok(None);
+        }
 
-        let user = ps_output.split_whitespace().nth(1).unwrap().to_string();
-        let cpu_utilization = ps_output.split_whitespace().nth(2).unwrap().to_string();
-        let memory_utilization = ps_output.split_whitespace().nth(3).unwrap().to_string();
+        let word = |i: usize| -> Result<String, String> {
+            ps_output
+                .split_whitespace()
+                .nth(i)
+                .map(str::to_string)
+                .ok_or_else(|| format!("unexpected ps output `{}`", ps_output.trim()))
+        };
+        let user = word(1)?;
+        let cpu_utilization = word(2)?;
+        let memory_utilization = word(3)?;
 
         let utilizations = format!("CPU {}% RAM {}%", cpu_utilization, memory_utilization);
 
-        let elapsed = ps_output.split_whitespace().nth(4).unwrap().to_string();
-        // command is everything from the 5th word onwards
+        let elapsed = word(4)?;
+        let elapsed_secs = word(5)?
+            .parse::<u64>()
+            .map_err(|e| format!("unexpected elapsed time in ps output: {}", e))?;
+        // command is everything from the 6th word onwards
         let mut command = String::new();
         for (i, word) in ps_output.split_whitespace().enumerate() {
-            if i < 5 {
+            if i < 6 {
                 continue;
             }
             command.push_str(word);
             command.push(' ');
         }
 
-        Self {
+        Ok(Some(Self {
             pid,
+            gpu_indices: vec![],
+            state: read_process_state(pid).unwrap_or('?'),
             user,
             utilizations,
+            io_rates: None,
             elapsed,
+            avg_sm_utilization: None,
+            peak_gpu_memory: None,
             command,
+            // looked up for all the processes at once, see `Machine::collect`
+            container_name: None,
+            // unreadable for other users' processes unless running as root
+            working_dir: fs::read_link(format!("/proc/{}/cwd", pid))
+                .ok()
+                .map(|cwd| cwd.display().to_string()),
+            cpu_affinity: read_cpu_affinity(pid).unwrap_or_else(|| "N/A".to_string()),
+            on_gpu: true,
+            manual: false,
+            k8s_pod: None,
+            k8s_namespace: None,
+            counters: read_process_counters(pid),
+            blocked: false,
+            elapsed_secs,
+            cpu_pct: cpu_utilization.parse().unwrap_or(0.0),
+            mem_pct: memory_utilization.parse().unwrap_or(0.0),
+            pgid: read_process_group(pid),
+            local_world_size: read_environ_var(pid, "LOCAL_WORLD_SIZE")
+                .and_then(|size| size.parse().ok()),
+        }))
+    }
+
+    /// A placeholder row for a process given with --pids that has exited, or never existed.
+    pub fn exited(pid: u32) -> Self {
+        Self {
+            pid,
+            gpu_indices: vec![],
+            state: 'X',
+            user: "-".to_string(),
+            utilizations: "-".to_string(),
+            io_rates: None,
+            elapsed: "-".to_string(),
+            avg_sm_utilization: None,
+            peak_gpu_memory: None,
+            command: "(exited)".to_string(),
+            container_name: None,
+            working_dir: None,
+            cpu_affinity: "N/A".to_string(),
+            on_gpu: false,
+            manual: true,
+            k8s_pod: None,
+            k8s_namespace: None,
+            counters: None,
+            blocked: false,
+            elapsed_secs: 0,
+            cpu_pct: 0.0,
+            mem_pct: 0.0,
+            pgid: None,
+            local_world_size: None,
+        }
+    }
+
+    /// Whether this is a placeholder for a process that has exited.
+    pub fn has_exited(&self) -> bool {
+        self.state == 'X'
+    }
+
+    /// Sends `signal` to the process, checking first that the user owns it or is root
+    /// so that the error names the owner rather than being a bare EPERM.
+    pub fn send_signal(&self, signal: Signal) -> Result<(), BmonError> {
+        let owner = fs::metadata(format!("/proc/{}", self.pid))
+            .map_err(|_| BmonError::NoSuchProcess { pid: self.pid })?
+            .uid();
+        // SAFETY: geteuid has no memory safety requirements and can't fail
+        let euid = unsafe { libc::geteuid() };
+        if !may_signal(euid, owner) {
+            return Err(BmonError::NotPermitted {
+                pid: self.pid,
+                user: self.user.clone(),
+            });
+        }
+        // SAFETY: kill has no memory safety requirements
+        if unsafe { libc::kill(self.pid as libc::pid_t, signal.number()) } != 0 {
+            return Err(BmonError::Signal {
+                pid: self.pid,
+                signal: signal.name(),
+                source: io::Error::last_os_error(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Updates the state, and computes io_rates from the change in IO counters since
+    /// the process was first read, so it should be called after some time has passed.
+    pub fn end_sample_window(&mut self) {
+        if let Some(state) = read_process_state(self.pid) {
+            self.blocked = self.state == 'D' && state == 'D';
+            self.state = state;
+        }
+        let Some(ProcessCounters {
+            io_bytes: Some((read_before, write_before)),
+            read_at,
+            ..
+        }) = self.counters
+        else {
+            return;
+        };
+        let secs = read_at.elapsed().as_secs_f32();
+        if let (Some((read, write)), true) = (read_process_io(self.pid), secs > 0.0) {
+            self.io_rates = Some((
+                read.saturating_sub(read_before) as f32 / secs,
+                write.saturating_sub(write_before) as f32 / secs,
+            ));
+        }
+    }
+
+    /// The rates since `previous`, a snapshot of the same pid taken `elapsed` earlier.
+    /// They're unknown if the pid now belongs to a different process.
+    pub fn rates_since(&self, previous: &ProcessStats, elapsed: Duration) -> ProcessRates {
+        let secs = elapsed.as_secs_f64();
+        let counters = match (previous.counters, self.counters) {
+            (Some(previous), Some(current))
+                if previous.started == current.started && secs > 0.0 =>
+            {
+                Some((previous, current))
+            }
+            _ => None,
+        };
+        let cpu_pct = counters.and_then(|(previous, current)| {
+            let cpu_time = current.cpu_time.checked_sub(previous.cpu_time)?;
+            Some((100.0 * cpu_time.as_secs_f64() / secs) as f32)
+        });
+        let io_rates = counters.and_then(|(previous, current)| {
+            let (read_before, write_before) = previous.io_bytes?;
+            let (read, write) = current.io_bytes?;
+            Some((
+                read.checked_sub(read_before)? as f64 / secs,
+                write.checked_sub(write_before)? as f64 / secs,
+            ))
+        });
+        ProcessRates {
+            pid: self.pid,
+            cpu_pct,
+            io_rates,
+        }
+    }
+
+    #[cfg(feature = "render")]
+    fn display_io_rates(&self) -> String {
+        match self.io_rates {
+            Some((read, write)) => format!("{:.1}/{:.1}MB/s", read / 1e6, write / 1e6),
+            // other users' processes can only be read by root
+            None => "?".to_string(),
+        }
+    }
+
+    #[cfg(feature = "render")]
+    fn display_on_gpu(&self) -> String {
+        if self.on_gpu {
+            "GPU".to_string()
+        } else {
+            "".to_string()
+        }
+    }
+
+    #[cfg(feature = "render")]
+    fn display_manual(&self) -> String {
+        if self.manual {
+            "*".to_string()
+        } else {
+            "".to_string()
         }
     }
+
+    #[cfg(feature = "render")]
+    fn display_gpu_indices(&self) -> String {
+        if self.gpu_indices.is_empty() {
+            return "-".to_string();
+        }
+        self.gpu_indices
+            .iter()
+            .map(|idx| idx.to_string())
+            .collect::<Vec<String>>()
+            .join(",")
+    }
+
+    /// The pod as `namespace/name` in Kubernetes, otherwise the Docker container name.
+    #[cfg(feature = "render")]
+    fn display_container_name(&self) -> String {
+        if let (Some(namespace), Some(pod)) = (&self.k8s_namespace, &self.k8s_pod) {
+            return format!("{}/{}", namespace, pod);
+        }
+        self.container_name
+            .clone()
+            .unwrap_or_else(|| "-".to_string())
+    }
+
+    /// e.g. `experiments/run_42` for `/home/user/experiments/run_42`
+    #[cfg(feature = "render")]
+    fn display_working_dir(&self) -> String {
+        let Some(working_dir) = &self.working_dir else {
+            return "-".to_string();
+        };
+        let components = Path::new(working_dir)
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .filter(|component| component != "/")
+            .collect::<Vec<_>>();
+        if components.is_empty() {
+            return "/".to_string();
+        }
+        components[components.len().saturating_sub(2)..].join("/")
+    }
+
+    #[cfg(feature = "render")]
+    fn display_avg_sm_utilization(&self) -> String {
+        display_pct(self.avg_sm_utilization)
+    }
+
+    #[cfg(feature = "render")]
+    fn display_peak_gpu_memory(&self) -> String {
+        match self.peak_gpu_memory {
+            Some(bytes) => bytes.gib(),
+            None => "N/A".to_string(),
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("pid", self.pid.into()),
+            ("gpu_indices", self.gpu_indices.clone().into()),
+            ("state", self.state.to_string().into()),
+            ("blocked", self.blocked.into()),
+            ("user", (&self.user).into()),
+            // kept as ps prints it for existing consumers, alongside the numbers
+            ("utilizations", (&self.utilizations).into()),
+            ("cpu_pct", self.cpu_pct.into()),
+            ("mem_pct", self.mem_pct.into()),
+            (
+                "io_read_bytes_per_sec",
+                self.io_rates.map(|(read, _)| read).into(),
+            ),
+            (
+                "io_write_bytes_per_sec",
+                self.io_rates.map(|(_, write)| write).into(),
+            ),
+            ("elapsed", (&self.elapsed).into()),
+            ("elapsed_secs", self.elapsed_secs.into()),
+            ("avg_sm_utilization", self.avg_sm_utilization.into()),
+            ("peak_gpu_memory_bytes", self.peak_gpu_memory.into()),
+            ("command", self.command.trim_end().into()),
+            ("pgid", self.pgid.into()),
+            ("local_world_size", self.local_world_size.into()),
+            ("container_name", self.container_name.clone().into()),
+            ("working_dir", self.working_dir.clone().into()),
+            ("cpu_affinity", (&self.cpu_affinity).into()),
+            ("k8s_pod", self.k8s_pod.clone().into()),
+            ("k8s_namespace", self.k8s_namespace.clone().into()),
+            ("on_gpu", self.on_gpu.into()),
+            ("manual", self.manual.into()),
+            ("exited", self.has_exited().into()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        let optional_string = || schema::nullable(schema::string());
+        schema::object(vec![
+            ("pid", schema::integer()),
+            ("gpu_indices", schema::array(schema::integer())),
+            ("state", schema::string()),
+            ("blocked", schema::boolean()),
+            ("user", schema::string()),
+            ("utilizations", schema::string()),
+            ("cpu_pct", schema::number()),
+            ("mem_pct", schema::number()),
+            ("io_read_bytes_per_sec", schema::nullable(schema::number())),
+            ("io_write_bytes_per_sec", schema::nullable(schema::number())),
+            ("elapsed", schema::string()),
+            ("elapsed_secs", schema::integer()),
+            ("avg_sm_utilization", schema::nullable(schema::integer())),
+            ("peak_gpu_memory_bytes", schema::nullable(schema::integer())),
+            ("command", schema::string()),
+            ("pgid", schema::nullable(schema::integer())),
+            ("local_world_size", schema::nullable(schema::integer())),
+            ("container_name", optional_string()),
+            ("working_dir", optional_string()),
+            ("cpu_affinity", schema::string()),
+            ("k8s_pod", optional_string()),
+            ("k8s_namespace", optional_string()),
+            ("on_gpu", schema::boolean()),
+            ("manual", schema::boolean()),
+            ("exited", schema::boolean()),
+        ])
+    }
+}
+
+/// Whether a user may signal a process, ignoring capabilities other than root's.
+fn may_signal(euid: u32, owner: u32) -> bool {
+    euid == 0 || euid == owner
+}
+
+/// CPU count and memory usage, with any container limits.
+/// Memory is in kiB, as reported by /proc/meminfo.
+pub struct CpuStats {
+    pub num_cpus: u32,
+    pub ram_total_kib: u64,
+    pub ram_used_kib: u64,
+    pub ram_available_kib: u64,
+    pub ram_buffers_kib: u64,
+    pub ram_cached_kib: u64, // including reclaimable slab
+    pub swap_total_kib: u64,
+    pub swap_used_kib: u64,
+    pub hugepages: Hugepages,
+    // transparent hugepage mode, e.g. "madvise"
+    pub thp: String,
+    pub cgroup: CgroupLimits,
+}
+
+/// Statically configured hugepages of the default size.
+#[cfg_attr(feature = "render", derive(Tabled))]
+#[cfg_attr(feature = "render", tabled(rename_all = "PascalCase"))]
+pub struct Hugepages {
+    #[cfg_attr(feature = "render", tabled(display_with("Self::display_size", self)))]
+    pub size_kib: u64,
+    pub total: u64,
+    pub free: u64,
+}
+
+impl Hugepages {
+    #[cfg(feature = "render")]
+    fn display_size(&self) -> String {
+        Bytes::from_kib(self.size_kib).human()
+    }
+}
+
+impl CpuStats {
+    /// e.g. `Num CPUs: 16 (of 128)  RAM: 41G/64G (limit)  Swap: none`
+    pub fn format_header(&self) -> String {
+        format!(
+            "Num CPUs: {}  RAM: {}  Swap: {}",
+            self.display_num_cpus(),
+            self.display_ram(),
+            self.display_swap()
+        )
+    }
+
+    /// The cgroup CPU limit if there is one, e.g. `16 (of 128)`, otherwise the host count.
+    fn display_num_cpus(&self) -> String {
+        match self.cgroup.cpus {
+            Some(cpus) if cpus < self.num_cpus as f32 => format!("{} (of {})", cpus, self.num_cpus),
+            _ => self.num_cpus.to_string(),
+        }
+    }
+
+    /// Usage against the cgroup memory limit if there is one, e.g. `41G/64G (limit)`,
+    /// otherwise the host breakdown, e.g. `210G used / 503G (290G available, 12G buff/cache)`.
+    pub fn display_ram(&self) -> String {
+        match (self.cgroup.memory, self.cgroup.memory_used) {
+            (Some(limit), Some(used)) if limit < self.ram_total_kib * 1024 => {
+                format!("{}/{} (limit)", Bytes(used).human(), Bytes(limit).human())
+            }
+            _ => format!(
+                "{} used / {} ({} available, {} buff/cache)",
+                Bytes::from_kib(self.ram_used_kib).human(),
+                Bytes::from_kib(self.ram_total_kib).human(),
+                Bytes::from_kib(self.ram_available_kib).human(),
+                Bytes::from_kib(self.ram_buffers_kib + self.ram_cached_kib).human()
+            ),
+        }
+    }
+
+    /// e.g. `3.2G/16G`, or `none` if there is no swap.
+    pub fn display_swap(&self) -> String {
+        if self.swap_total_kib == 0 {
+            return "none".to_string();
+        }
+        format!(
+            "{}/{}",
+            Bytes::from_kib(self.swap_used_kib).human(),
+            Bytes::from_kib(self.swap_total_kib).human()
+        )
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("num_cpus", self.num_cpus.into()),
+            (
+                "memory",
+                Json::object(vec![
+                    ("ram_total_kib", self.ram_total_kib.into()),
+                    ("ram_used_kib", self.ram_used_kib.into()),
+                    ("ram_available_kib", self.ram_available_kib.into()),
+                    ("ram_buffers_kib", self.ram_buffers_kib.into()),
+                    ("ram_cached_kib", self.ram_cached_kib.into()),
+                    ("swap_total_kib", self.swap_total_kib.into()),
+                    ("swap_used_kib", self.swap_used_kib.into()),
+                    ("hugepages_total", self.hugepages.total.into()),
+                    ("hugepages_free", self.hugepages.free.into()),
+                    ("hugepage_size_kib", self.hugepages.size_kib.into()),
+                    ("thp", (&self.thp).into()),
+                ]),
+            ),
+            ("cgroup", self.cgroup.to_json()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        let memory = [
+            "ram_total_kib",
+            "ram_used_kib",
+            "ram_available_kib",
+            "ram_buffers_kib",
+            "ram_cached_kib",
+            "swap_total_kib",
+            "swap_used_kib",
+            "hugepages_total",
+            "hugepages_free",
+            "hugepage_size_kib",
+        ]
+        .into_iter()
+        .map(|field| (field, schema::integer()))
+        .chain([("thp", schema::string())])
+        .collect();
+        schema::object(vec![
+            ("num_cpus", schema::integer()),
+            ("memory", schema::object(memory)),
+            ("cgroup", CgroupLimits::json_schema()),
+        ])
+    }
+}
+
+pub fn get_cpu_stats() -> Result<CpuStats, BmonError> {
+    let meminfo = fs::read_to_string("/proc/meminfo").map_err(|source| BmonError::Read {
+        path: "/proc/meminfo",
+        source,
+    })?;
+    let mut stats = parse_meminfo(&meminfo);
+    stats.num_cpus = get_num_cpus();
+    stats.thp = fs::read_to_string("/sys/kernel/mm/transparent_hugepage/enabled")
+        .map(|enabled| parse_thp_mode(&enabled))
+        .unwrap_or_else(|_| "N/A".to_string());
+    stats.cgroup = get_cgroup_limits();
+    Ok(stats)
+}
+
+/// Returns the number of CPUs bmon may run on, the same as `nproc`.
+fn get_num_cpus() -> u32 {
+    // SAFETY: cpu_set_t is plain data and is filled in by sched_getaffinity
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+    if unsafe { libc::sched_getaffinity(0, size, &mut set) } == 0 {
+        unsafe { libc::CPU_COUNT(&set) as u32 }
+    } else {
+        // unlike nproc, this also respects cgroup quotas, so is only a fallback
+        thread::available_parallelism().map_or(1, |n| n.get() as u32)
+    }
 }
 
-pub fn get_cpu_stats() -> (String, String) {
-    let nproc = Command::new("nproc")
-        .output()
-        .expect("failed to execute nproc command");
-    let num_cpus = String::from_utf8(nproc.stdout)
-        .unwrap()
-        .strip_suffix('\n')
-        .unwrap()
-        .to_string();
+/// The selected mode is in brackets, e.g. "always [madvise] never".
+fn parse_thp_mode(enabled: &str) -> String {
+    enabled
+        .split_whitespace()
+        .find_map(|mode| mode.strip_prefix('[')?.strip_suffix(']'))
+        .unwrap_or("N/A")
+        .to_string()
+}
+
+/// Computes used memory the same way as `free`: whatever is neither free
+/// nor reclaimable buffers/cache. Missing fields are treated as 0.
+pub(crate) fn parse_meminfo(meminfo: &str) -> CpuStats {
+    // values are in kiB, e.g. "MemTotal:       527988292 kB"
+    // hugepage counts have no unit, e.g. "HugePages_Total:       0"
+    let optional_field = |name: &str| -> Option<u64> {
+        let line = meminfo
+            .lines()
+            .find(|line| line.split(':').next() == Some(name))?;
+        line.split_whitespace().nth(1)?.parse::<u64>().ok()
+    };
+    let field = |name: &str| optional_field(name).unwrap_or(0);
+
+    let total = field("MemTotal");
+    let free = field("MemFree");
+    let buffers = field("Buffers");
+    let cached = field("Cached") + field("SReclaimable");
+    let swap_total = field("SwapTotal");
+    CpuStats {
+        num_cpus: 0,
+        ram_total_kib: total,
+        ram_used_kib: total.saturating_sub(free + buffers + cached),
+        // kernels before 3.14 don't report MemAvailable, so estimate it like old `free`
+        ram_available_kib: optional_field("MemAvailable").unwrap_or(free + buffers + cached),
+        ram_buffers_kib: buffers,
+        ram_cached_kib: cached,
+        swap_total_kib: swap_total,
+        swap_used_kib: swap_total.saturating_sub(field("SwapFree")),
+        hugepages: Hugepages {
+            size_kib: field("Hugepagesize"),
+            total: field("HugePages_Total"),
+            free: field("HugePages_Free"),
+        },
+        thp: "N/A".to_string(),
+        cgroup: CgroupLimits::default(),
+    }
+}
+
+/// Returns the number of pages swapped in since boot, None if /proc/vmstat is unreadable.
+pub fn read_pswpin() -> Option<u64> {
+    parse_pswpin(&fs::read_to_string("/proc/vmstat").ok()?)
+}
+
+fn parse_pswpin(vmstat: &str) -> Option<u64> {
+    vmstat
+        .lines()
+        .find(|line| line.starts_with("pswpin "))?
+        .split_whitespace()
+        .nth(1)?
+        .parse::<u64>()
+        .ok()
+}
+
+/// Returns the state of the process, or None if it has exited.
+fn read_process_state(pid: u32) -> Option<char> {
+    parse_process_state(&fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)
+}
+
+/// The state follows the command name, which is in parentheses and may itself
+/// contain spaces or parentheses, e.g. "4242 (python (worker)) D 4100 ...".
+fn parse_process_state(stat: &str) -> Option<char> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.trim_start().chars().next()
+}
+
+/// Returns the CPU time and start time of the process, and its IO counters
+/// if readable, or None if it has exited.
+fn read_process_counters(pid: u32) -> Option<ProcessCounters> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // SAFETY: sysconf has no memory safety requirements
+    let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as u64;
+    let (started, cpu_time) = parse_process_times(&stat, ticks_per_sec)?;
+    Some(ProcessCounters {
+        started,
+        cpu_time,
+        io_bytes: read_process_io(pid),
+        read_at: Instant::now(),
+    })
+}
+
+/// The start time (field 22 in proc(5)) and the user plus system time (14 and 15),
+/// which are counted in clock ticks, e.g. "4242 (python) S 4100 ... 1200 300 ... 51230 ...".
+fn parse_process_times(stat: &str, ticks_per_sec: u64) -> Option<(Duration, Duration)> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    let fields = rest.split_whitespace().collect::<Vec<&str>>();
+    let field = |n: usize| -> Option<u64> { fields.get(n - 3)?.parse().ok() };
+    let hz = ticks_per_sec.max(1);
+    // in two parts, since ticks in nanoseconds would overflow after a few CPU-weeks
+    let ticks = |ticks: u64| {
+        Duration::from_secs(ticks / hz) + Duration::from_nanos(ticks % hz * 1_000_000_000 / hz)
+    };
+    Some((ticks(field(22)?), ticks(field(14)? + field(15)?)))
+}
+
+/// Returns the process group id, or None if the process has exited.
+fn read_process_group(pid: u32) -> Option<u32> {
+    parse_process_group(&fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?)
+}
+
+/// The process group is the third field after the command name, e.g. 4100 in
+/// "4242 (python) S 4100 4100 4100 0 -1 ...", following the state and parent pid.
+fn parse_process_group(stat: &str) -> Option<u32> {
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_whitespace().nth(2)?.parse().ok()
+}
+
+/// Returns the value of an environment variable of the process, or None if it
+/// isn't set or /proc/<pid>/environ can't be read.
+fn read_environ_var(pid: u32, name: &str) -> Option<String> {
+    let environ = fs::read(format!("/proc/{}/environ", pid)).ok()?;
+    parse_environ_var(&String::from_utf8_lossy(&environ), name)
+}
+
+/// Finds `name` in an environ file, which is NUL-separated `NAME=value` pairs.
+fn parse_environ_var(environ: &str, name: &str) -> Option<String> {
+    environ.split('\0').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        (key == name).then(|| value.to_string())
+    })
+}
+
+/// Returns the CPUs the process may run on, or "all" if it may run on every online CPU.
+fn read_cpu_affinity(pid: u32) -> Option<String> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let allowed = parse_cpus_allowed_list(&status)?;
+    let online = fs::read_to_string("/sys/devices/system/cpu/online").ok();
+    if online.is_some_and(|online| online.trim() == allowed) {
+        return Some("all".to_string());
+    }
+    Some(allowed)
+}
+
+/// Finds the `Cpus_allowed_list` line, e.g. `0-7,32-39`.
+fn parse_cpus_allowed_list(status: &str) -> Option<String> {
+    let allowed = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Cpus_allowed_list:"))?;
+    Some(allowed.trim().to_string())
+}
+
+/// Returns the bytes the process has read from and written to storage, or None
+/// if /proc/<pid>/io can't be read (e.g. another user's process).
+fn read_process_io(pid: u32) -> Option<(u64, u64)> {
+    parse_process_io(&fs::read_to_string(format!("/proc/{}/io", pid)).ok()?)
+}
+
+/// Parses the read_bytes and write_bytes lines, which unlike rchar and wchar
+/// only count IO that actually reached the block layer.
+fn parse_process_io(io: &str) -> Option<(u64, u64)> {
+    let field = |name: &str| -> Option<u64> {
+        io.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))?
+            .trim()
+            .parse::<u64>()
+            .ok()
+    };
+    Some((field("read_bytes")?, field("write_bytes")?))
+}
+
+/// Returns the PIDs of all processes using more than `min_pct` percent of
+/// CPU or memory, by scanning /proc. CPU usage is averaged over the process
+/// lifetime, the same as `ps`. Fails if /proc or /proc/uptime can't be read.
+pub fn get_busy_pids(min_pct: f32, ram_total_kib: u64) -> Result<Vec<u32>, String> {
+    // SAFETY: sysconf has no memory safety requirements
+    let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as f32;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as f32;
+
+    let uptime = fs::read_to_string("/proc/uptime")
+        .map_err(|e| format!("failed to read /proc/uptime: {}", e))?;
+    let uptime = parse_uptime(&uptime)
+        .ok_or_else(|| format!("unexpected /proc/uptime `{}`", uptime.trim()))?;
 
-    let free = Command::new("free")
-        .arg("-h")
-        .output()
-        .expect("failed to execute free command");
-    let free_output = String::from_utf8(free.stdout).unwrap();
-    let ram_capacity = free_output.split_whitespace().nth(7).unwrap().to_string();
+    let mut pids = fs::read_dir("/proc")
+        .map_err(|e| format!("failed to read /proc: {}", e))?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| {
+            // the process may exit while we're scanning, so skip it if we can't read it
+            let stat = match fs::read_to_string(format!("/proc/{}/stat", pid)) {
+                Ok(stat) => stat,
+                Err(_) => return false,
+            };
+            // the command name is in parentheses and may contain spaces,
+            // so start parsing after the last ')'. fields[0] is the state (field 3 in proc(5))
+            let fields = match stat.rfind(')') {
+                Some(i) => stat[i + 1..].split_whitespace().collect::<Vec<&str>>(),
+                None => return false,
+            };
+            let field = |n: usize| -> f32 {
+                fields
+                    .get(n - 3)
+                    .and_then(|f| f.parse().ok())
+                    .unwrap_or(0.0)
+            };
 
-    (num_cpus, ram_capacity)
+            let cpu_secs = (field(14) + field(15)) / ticks_per_sec;
+            let elapsed_secs = uptime - field(22) / ticks_per_sec;
+            let cpu_pct = if elapsed_secs > 0.0 {
+                100.0 * cpu_secs / elapsed_secs
+            } else {
+                0.0
+            };
+            let mem_pct = 100.0 * field(24) * page_size / 1024.0 / ram_total_kib as f32;
+
+            cpu_pct > min_pct || mem_pct > min_pct
+        })
+        .collect::<Vec<u32>>();
+    pids.sort();
+    Ok(pids)
+}
+
+/// The seconds since boot, the first of the two numbers in /proc/uptime.
+fn parse_uptime(uptime: &str) -> Option<f32> {
+    uptime.split_whitespace().next()?.parse().ok()
+}
+
+/// Returns the 1, 5, and 15 minute load averages from /proc/loadavg, or None
+/// if it can't be read.
+pub fn get_load_average() -> Option<(f32, f32, f32)> {
+    parse_load_average(&fs::read_to_string("/proc/loadavg").ok()?)
+}
+
+/// e.g. "0.52 0.58 0.59 1/1139 123456", where the rest are process counts.
+fn parse_load_average(loadavg: &str) -> Option<(f32, f32, f32)> {
+    let mut loads = loadavg.split_whitespace().map(|load| load.parse().ok());
+    Some((loads.next()??, loads.next()??, loads.next()??))
+}
+
+/// Returns the CPU model name and clock speed, e.g. `AMD EPYC 7763 64-Core @ 2.45GHz (boost 3.50)`.
+/// Frequencies are omitted when they aren't exposed (e.g. in some VMs).
+pub fn get_cpu_model() -> String {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    let model = cpuinfo
+        .lines()
+        .find(|line| line.starts_with("model name"))
+        .and_then(|line| line.split(':').nth(1))
+        .map(shorten_cpu_model)
+        .unwrap_or_else(|| "Unknown CPU".to_string());
+
+    // prefer cpufreq, fall back to the (less accurate) cpuinfo MHz
+    let mut freqs_mhz = cpufreq_khz("scaling_cur_freq")
+        .iter()
+        .map(|khz| *khz as f32 / 1000.0)
+        .collect::<Vec<f32>>();
+    if freqs_mhz.is_empty() {
+        freqs_mhz = cpuinfo
+            .lines()
+            .filter(|line| line.starts_with("cpu MHz"))
+            .filter_map(|line| line.split(':').nth(1)?.trim().parse::<f32>().ok())
+            .collect();
+    }
+    if freqs_mhz.is_empty() {
+        return model;
+    }
+
+    let min = freqs_mhz.iter().cloned().fold(f32::INFINITY, f32::min) / 1000.0;
+    let max = freqs_mhz.iter().cloned().fold(0.0, f32::max) / 1000.0;
+    let avg = freqs_mhz.iter().sum::<f32>() / freqs_mhz.len() as f32 / 1000.0;
+    // cores can idle at very different clocks, so show the spread if it's large
+    let freq = if max > 1.5 * min {
+        format!("{:.2}/{:.2}/{:.2}GHz (min/avg/max)", min, avg, max)
+    } else {
+        format!("{:.2}GHz", avg)
+    };
+
+    let boost = cpufreq_khz("cpuinfo_max_freq")
+        .iter()
+        .max()
+        .map(|khz| format!(" (boost {:.2})", *khz as f32 / 1000.0 / 1000.0))
+        .unwrap_or_default();
+
+    format!("{} @ {}{}", model, freq, boost)
+}
+
+/// Reads the given cpufreq file (in kHz) for every CPU that exposes it.
+fn cpufreq_khz(file: &str) -> Vec<u64> {
+    let Ok(entries) = fs::read_dir("/sys/devices/system/cpu") else {
+        return vec![];
+    };
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name();
+            let name = name.to_str()?;
+            // only cpuN directories, not cpufreq, cpuidle etc.
+            if !name.starts_with("cpu") || !name[3..].chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let path = entry.path().join("cpufreq").join(file);
+            fs::read_to_string(path).ok()?.trim().parse::<u64>().ok()
+        })
+        .collect()
+}
+
+/// Removes the marketing noise from CPU model names,
+/// e.g. "Intel(R) Xeon(R) Gold 6248 CPU @ 2.50GHz" -> "Intel Xeon Gold 6248".
+fn shorten_cpu_model(model: &str) -> String {
+    let model = model.split(" @ ").next().unwrap_or(model);
+    model
+        .replace("(R)", "")
+        .replace("(TM)", "")
+        .split_whitespace()
+        .filter(|word| *word != "CPU" && *word != "Processor")
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_process_state() {
+        let stat = "4242 (python (worker)) D 4100 4242 4100 0 -1 4194560 51230";
+        assert_eq!(parse_process_state(stat), Some('D'));
+        assert_eq!(parse_process_state("1 (systemd) S 0 1 1"), Some('S'));
+        assert_eq!(parse_process_state(""), None);
+    }
+
+    #[test]
+    fn parses_process_times() {
+        let stat = "4242 (python (worker)) R 4100 4242 4100 0 -1 4194560 51230 0 12 0 \
+                    1200 300 0 0 20 0 9 0 51230 8589934592 1048576";
+        assert_eq!(
+            parse_process_times(stat, 100),
+            Some((Duration::from_millis(512_300), Duration::from_secs(15)))
+        );
+        assert_eq!(parse_process_times("4242 (python) S 4100", 100), None);
+
+        // a month on 128 cores, which is too many nanoseconds for a u64
+        let ticks: u64 = 100 * 3600 * 24 * 30 * 128;
+        let stat = format!(
+            "1 (python) R{} {} 50{} 51230",
+            " 0".repeat(10),
+            ticks,
+            " 0".repeat(6)
+        );
+        assert_eq!(
+            parse_process_times(&stat, 100),
+            Some((
+                Duration::from_millis(512_300),
+                Duration::from_millis(ticks * 10 + 500)
+            ))
+        );
+    }
+
+    #[test]
+    fn computes_rates_unless_the_pid_was_reused() {
+        let at = |started: u64, cpu_secs: u64, read: u64| {
+            let mut process = ProcessStats::exited(4242);
+            process.counters = Some(ProcessCounters {
+                started: Duration::from_secs(started),
+                cpu_time: Duration::from_secs(cpu_secs),
+                io_bytes: Some((read, 0)),
+                read_at: Instant::now(),
+            });
+            process
+        };
+        let elapsed = Duration::from_secs(10);
+        let rates = at(100, 25, 3000).rates_since(&at(100, 20, 1000), elapsed);
+        assert_eq!(rates.cpu_pct, Some(50.0));
+        assert_eq!(rates.io_rates, Some((200.0, 0.0)));
+
+        // a new process started since, whose counters began again from 0
+        let rates = at(105, 1, 0).rates_since(&at(100, 20, 1000), elapsed);
+        assert_eq!((rates.cpu_pct, rates.io_rates), (None, None));
+
+        let rates = ProcessStats::exited(4242).rates_since(&at(100, 20, 1000), elapsed);
+        assert_eq!((rates.cpu_pct, rates.io_rates), (None, None));
+    }
+
+    #[test]
+    fn parses_uptime_load_average_and_swap_ins() {
+        assert_eq!(parse_uptime("350735.47 234388.90\n"), Some(350735.47));
+        assert_eq!(parse_uptime(""), None);
+        assert_eq!(
+            parse_load_average("0.52 0.58 0.59 1/1139 123456\n"),
+            Some((0.52, 0.58, 0.59))
+        );
+        assert_eq!(parse_load_average("0.52 0.58"), None);
+        assert_eq!(parse_load_average("0.52 a 0.59"), None);
+        assert_eq!(
+            parse_pswpin("pgpgout 1024\npswpin 42\npswpout 7\n"),
+            Some(42)
+        );
+        assert_eq!(parse_pswpin("pgpgout 1024\n"), None);
+    }
+
+    #[test]
+    fn serializes_usage_as_text_and_numbers() {
+        let mut process = ProcessStats::exited(4242);
+        process.utilizations = "CPU 12.5% RAM 0.5%".to_string();
+        process.cpu_pct = 12.5;
+        let json = process.to_json();
+        assert_eq!(json["utilizations"].as_str(), Some("CPU 12.5% RAM 0.5%"));
+        assert_eq!(json["cpu_pct"], Json::from(12.5f32));
+    }
+
+    #[test]
+    fn parses_process_group() {
+        let stat = "4242 (python (worker)) S 4100 4099 4099 0 -1 4194560 51230";
+        assert_eq!(parse_process_group(stat), Some(4099));
+        assert_eq!(parse_process_group("4242 (python) S"), None);
+    }
+
+    #[test]
+    fn parses_environ_vars() {
+        let environ = "PATH=/usr/bin\0LOCAL_RANK=1\0LOCAL_WORLD_SIZE=8\0EMPTY=\0";
+        assert_eq!(
+            parse_environ_var(environ, "LOCAL_WORLD_SIZE"),
+            Some("8".to_string())
+        );
+        assert_eq!(parse_environ_var(environ, "EMPTY"), Some("".to_string()));
+        assert_eq!(parse_environ_var(environ, "WORLD_SIZE"), None);
+    }
+
+    #[test]
+    fn parses_cpus_allowed_list() {
+        let status = "Name:\tpython\n\
+                      Cpus_allowed:\tff,000000ff\n\
+                      Cpus_allowed_list:\t0-7,32-39\n\
+                      Mems_allowed_list:\t0\n";
+        assert_eq!(
+            parse_cpus_allowed_list(status),
+            Some("0-7,32-39".to_string())
+        );
+        assert_eq!(parse_cpus_allowed_list("Name:\tpython\n"), None);
+    }
+
+    #[test]
+    fn parses_process_io() {
+        let io = "rchar: 84123049314\n\
+                  wchar: 1203948\n\
+                  syscr: 20312\n\
+                  syscw: 1402\n\
+                  read_bytes: 52428800000\n\
+                  write_bytes: 409600\n\
+                  cancelled_write_bytes: 0\n";
+        assert_eq!(parse_process_io(io), Some((52428800000, 409600)));
+        assert_eq!(parse_process_io(""), None);
+    }
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    // captured from an Ubuntu 22.04 training node (truncated)
+    const UBUNTU_MEMINFO: &str = "\
+MemTotal:       527988292 kB
+MemFree:         12359412 kB
+MemAvailable:   304218764 kB
+Buffers:          2912108 kB
+Cached:         282113968 kB
+SwapCached:          1236 kB
+Active:         146563488 kB
+Inactive:       303910216 kB
+Shmem:            1220540 kB
+SReclaimable:     9583432 kB
+SUnreclaim:      10677252 kB
+SwapTotal:        8388604 kB
+SwapFree:         8011260 kB
+HugePages_Total:       0
+Hugepagesize:       2048 kB
+";
+
+    // captured from an Alpine container, which has no swap
+    const ALPINE_MEMINFO: &str = "\
+MemTotal:        2030640 kB
+MemFree:          806032 kB
+MemAvailable:    1583584 kB
+Buffers:           35028 kB
+Cached:           815872 kB
+SwapCached:            0 kB
+SReclaimable:      54124 kB
+SwapTotal:             0 kB
+SwapFree:              0 kB
+";
+
+    // captured from a CentOS 6 (2.6.32) node, which predates MemAvailable
+    const CENTOS6_MEMINFO: &str = "\
+MemTotal:       65938028 kB
+MemFree:        20112996 kB
+Buffers:          411952 kB
+Cached:         38500784 kB
+SwapCached:            0 kB
+SwapTotal:      33554428 kB
+SwapFree:       33554428 kB
+";
+
+    #[test]
+    fn parses_ubuntu_meminfo() {
+        let stats = parse_meminfo(UBUNTU_MEMINFO);
+        assert_eq!(stats.ram_total_kib, 527988292);
+        assert_eq!(stats.ram_available_kib, 304218764);
+        assert_eq!(stats.ram_buffers_kib, 2912108);
+        assert_eq!(stats.ram_cached_kib, 282113968 + 9583432);
+        assert_eq!(
+            stats.ram_used_kib,
+            527988292 - 12359412 - 2912108 - 282113968 - 9583432
+        );
+        assert_eq!(stats.swap_used_kib, 8388604 - 8011260);
+        assert_eq!(
+            stats.display_ram(),
+            "211G used / 504G (290G available, 281G buff/cache)"
+        );
+        assert_eq!(stats.display_swap(), "368M/8.0G");
+        assert_eq!(stats.hugepages.total, 0);
+        assert_eq!(stats.hugepages.size_kib, 2048);
+    }
+
+    #[test]
+    fn parses_thp_mode() {
+        assert_eq!(parse_thp_mode("always [madvise] never\n"), "madvise");
+        assert_eq!(parse_thp_mode("[always] madvise never\n"), "always");
+        assert_eq!(parse_thp_mode(""), "N/A");
+    }
+
+    #[test]
+    fn parses_meminfo_without_swap() {
+        let stats = parse_meminfo(ALPINE_MEMINFO);
+        assert_eq!(stats.swap_total_kib, 0);
+        assert_eq!(stats.display_swap(), "none");
+        assert_eq!(
+            stats.display_ram(),
+            "312M used / 1.9G (1.5G available, 884M buff/cache)"
+        );
+    }
+
+    #[test]
+    fn estimates_available_on_old_kernels() {
+        let stats = parse_meminfo(CENTOS6_MEMINFO);
+        assert_eq!(stats.ram_available_kib, 20112996 + 411952 + 38500784);
+        assert_eq!(stats.swap_used_kib, 0);
+    }
+
+    #[test]
+    fn shows_cgroup_limits_in_the_header() {
+        let mut stats = parse_meminfo(UBUNTU_MEMINFO);
+        stats.num_cpus = 128;
+        stats.cgroup = CgroupLimits {
+            cpus: Some(16.0),
+            memory: Some(64 * GIB),
+            memory_used: Some(41 * GIB),
+        };
+        assert_eq!(
+            stats.format_header(),
+            "Num CPUs: 16 (of 128)  RAM: 41G/64G (limit)  Swap: 368M/8.0G"
+        );
+    }
+
+    #[test]
+    fn only_root_or_the_owner_may_signal() {
+        assert!(may_signal(1000, 1000));
+        assert!(may_signal(0, 1000));
+        assert!(!may_signal(1001, 1000));
+    }
 }
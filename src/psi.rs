@@ -0,0 +1,120 @@
+use std::fs;
+use std::path::Path;
+
+use crate::json::Json;
+use crate::schema;
+
+/// Share of the last 10s (%) that some or all non-idle tasks were stalled on a resource.
+#[derive(Clone, Copy, Default)]
+pub struct Pressure {
+    pub some: f32,
+    pub full: f32, // always 0 for cpu before Linux 5.13
+}
+
+impl Pressure {
+    fn to_json(self) -> Json {
+        Json::object(vec![
+            ("some_avg10", self.some.into()),
+            ("full_avg10", self.full.into()),
+        ])
+    }
+
+    fn json_schema() -> Json {
+        schema::object(vec![
+            ("some_avg10", schema::number()),
+            ("full_avg10", schema::number()),
+        ])
+    }
+}
+
+/// Pressure Stall Information, see Documentation/accounting/psi.rst.
+pub struct PressureStats {
+    pub cpu: Pressure,
+    pub memory: Pressure,
+    pub io: Pressure,
+}
+
+impl PressureStats {
+    /// e.g. `PSI (some/full): CPU 12.3/0.0% Mem 0.0/0.0% IO 41.2/38.9%`
+    pub fn format_header(&self) -> String {
+        let format = |pressure: Pressure| format!("{:.1}/{:.1}%", pressure.some, pressure.full);
+        format!(
+            "PSI (some/full): CPU {} Mem {} IO {}",
+            format(self.cpu),
+            format(self.memory),
+            format(self.io)
+        )
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("cpu", self.cpu.to_json()),
+            ("memory", self.memory.to_json()),
+            ("io", self.io.to_json()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        schema::object(vec![
+            ("cpu", Pressure::json_schema()),
+            ("memory", Pressure::json_schema()),
+            ("io", Pressure::json_schema()),
+        ])
+    }
+}
+
+/// Returns None on kernels without PSI, or with it disabled (psi=0), where the
+/// files are missing or fail to read.
+pub fn get_pressure() -> Option<PressureStats> {
+    let root = Path::new("/proc/pressure");
+    let read = |resource: &str| parse_pressure(&fs::read_to_string(root.join(resource)).ok()?);
+    Some(PressureStats {
+        cpu: read("cpu")?,
+        memory: read("memory")?,
+        io: read("io")?,
+    })
+}
+
+/// Parses e.g.
+/// ```text
+/// some avg10=41.20 avg60=30.11 avg300=12.58 total=1804372513
+/// full avg10=38.90 avg60=28.04 avg300=11.91 total=1690233069
+/// ```
+/// The full line is missing for cpu on older kernels.
+fn parse_pressure(pressure: &str) -> Option<Pressure> {
+    let avg10 = |kind: &str| -> Option<f32> {
+        pressure
+            .lines()
+            .find(|line| line.starts_with(kind))?
+            .split_whitespace()
+            .find_map(|field| field.strip_prefix("avg10="))?
+            .parse::<f32>()
+            .ok()
+    };
+    Some(Pressure {
+        some: avg10("some")?,
+        full: avg10("full").unwrap_or(0.0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pressure() {
+        let io = "some avg10=41.20 avg60=30.11 avg300=12.58 total=1804372513\n\
+                  full avg10=38.90 avg60=28.04 avg300=11.91 total=1690233069\n";
+        let pressure = parse_pressure(io).unwrap();
+        assert_eq!(pressure.some, 41.2);
+        assert_eq!(pressure.full, 38.9);
+
+        // cpu has no full line before 5.13
+        let cpu = "some avg10=12.30 avg60=8.00 avg300=3.10 total=99123\n";
+        let pressure = parse_pressure(cpu).unwrap();
+        assert_eq!(pressure.some, 12.3);
+        assert_eq!(pressure.full, 0.0);
+
+        assert!(parse_pressure("").is_none());
+    }
+}
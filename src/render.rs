@@ -0,0 +1,733 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::Instant;
+use tabled::{
+    builder::Builder,
+    settings::object::{Cell, Columns, Rows},
+    settings::{peaker::PriorityMax, Concat, Disable, Extract, Modify, Panel, Style, Width},
+    Table,
+};
+
+use crate::color::{self, Color};
+use crate::delta::{Delta, Snapshot};
+use crate::diagnostics::{diagnose, summarize, Thresholds, REQUIRED_STATS};
+use crate::gpu::GPUStats;
+use crate::history::{GpuSessionPeak, MemoryTrend, UtilizationHistory, HISTORY_SIZE};
+use crate::numa::parse_cpulist;
+use crate::{CollectOptions, Machine};
+
+pub mod graph;
+
+// number of GPU table columns shown in non-verbose mode
+const N_DEFAULT_GPU_COLS: usize = 7;
+
+/// Options shared by everything that renders tables.
+#[derive(Clone, Copy)]
+pub struct DisplayOptions {
+    pub verbose: bool,
+    pub truncate: bool,
+    pub markdown: bool,
+    pub retired_pages: bool,
+    pub thermal_limits: bool,
+    // use the full terminal width instead of fixed column widths
+    pub wide: bool,
+    // GPUs below both utilizations (%) are hidden from the GPU table
+    pub min_gpu_util: Option<u32>,
+    pub min_mem_util: Option<u32>,
+}
+
+impl DisplayOptions {
+    /// Whether `gpu` meets either of the minimum utilizations, if any are set.
+    fn shows_gpu(&self, gpu: &GPUStats) -> bool {
+        let (gpu_utilization, memory_utilization) = gpu.utilizations;
+        match (self.min_gpu_util, self.min_mem_util) {
+            (None, None) => true,
+            (min_gpu, min_mem) => {
+                min_gpu.is_some_and(|min| gpu_utilization >= min)
+                    || min_mem.is_some_and(|min| memory_utilization >= min)
+            }
+        }
+    }
+
+    /// Whether columns should be truncated to their fixed widths.
+    fn fixed_width(&self) -> bool {
+        self.truncate && !self.wide
+    }
+}
+
+/// Presents a snapshot of the machine, e.g. as printed tables or in the TUI.
+pub trait Renderer {
+    fn render(&mut self, machine: &Machine, writer: &mut dyn Write) -> io::Result<()>;
+
+    /// The stats that `render` shows, so that the rest can be left uncollected.
+    fn collect_options(&self) -> CollectOptions {
+        CollectOptions::ALL
+    }
+}
+
+/// Prints the selected sections as tables, once.
+pub struct TableRenderer {
+    pub options: DisplayOptions,
+    pub cpu: bool,
+    pub accounting: bool,
+    pub numa: bool,
+    pub disk: bool,
+    pub all_disks: bool,
+    pub net: bool,
+    pub all_interfaces: bool,
+    pub netfs: bool,
+    pub fs: bool,
+    pub bottleneck: bool,
+    // one summary line instead of the GPU table
+    pub aggregate: bool,
+    // utilization bars instead of the GPU table
+    pub graph: bool,
+    pub thresholds: Thresholds,
+    // recent utilization of each GPU by index, only kept in watch mode
+    pub history: Option<HashMap<u32, UtilizationHistory>>,
+    // recent memory usage of each GPU by index, only kept in watch mode
+    pub memory_trends: Option<HashMap<u32, MemoryTrend>>,
+    // show GPU readings as the change since the previous render
+    pub delta: bool,
+    pub previous: Option<Snapshot>,
+}
+
+impl Renderer for TableRenderer {
+    fn render(&mut self, machine: &Machine, writer: &mut dyn Write) -> io::Result<()> {
+        if let Some(history) = &mut self.history {
+            for gpu in &machine.gpus {
+                history
+                    .entry(gpu.idx)
+                    .or_insert_with(|| UtilizationHistory::new(HISTORY_SIZE))
+                    .push(gpu.utilizations.0);
+            }
+        }
+
+        if self.aggregate {
+            writeln!(writer, "\n{}", machine.aggregate_gpu_stats().format())?;
+        } else if self.graph {
+            writeln!(writer, "\nGPU Utilization:")?;
+            let (cols, _) = terminal_size();
+            writeln!(
+                writer,
+                "{}",
+                graph::utilization_graph(machine, self.options, cols)
+            )?;
+        } else {
+            self.render_gpu_table(machine, writer)?;
+        }
+
+        if self.cpu {
+            writeln!(writer, "\nCPU Usage:")?;
+            writeln!(writer, "{}", cpu_table(machine, self.options))?;
+            if self.options.verbose {
+                writeln!(writer, "\nHugepages:")?;
+                writeln!(writer, "{}", hugepages_table(machine, self.options))?;
+            }
+            if self.options.verbose && !machine.accounting_enabled {
+                writeln!(writer, "Hint: enable accounting mode with `nvidia-smi --accounting-mode=1` for lifetime GPU stats per process.")?;
+            }
+        }
+
+        if self.accounting {
+            writeln!(writer, "\nCompleted Processes (GPU Accounting):")?;
+            writeln!(writer, "{}", accounting_table(machine, self.options))?;
+        }
+
+        if self.disk {
+            writeln!(writer, "\nDisk Usage:")?;
+            writeln!(
+                writer,
+                "{}",
+                disk_table(machine, self.options, self.all_disks)
+            )?;
+        }
+
+        if self.net {
+            writeln!(writer, "\nNetwork Usage:")?;
+            writeln!(
+                writer,
+                "{}",
+                net_table(machine, self.options, self.all_interfaces)
+            )?;
+        }
+
+        // most machines have no network filesystems, so skip the section entirely
+        if self.netfs && !machine.netfs.is_empty() {
+            writeln!(writer, "\nNetwork Filesystems:")?;
+            writeln!(writer, "{}", netfs_table(machine, self.options))?;
+        }
+
+        if self.fs {
+            writeln!(writer, "\nFilesystem Usage:")?;
+            writeln!(writer, "{}", fs_table(machine, self.options))?;
+        }
+
+        if self.numa {
+            writeln!(writer, "\nNUMA Topology:")?;
+            writeln!(writer, "{}", numa_table(machine, self.options))?;
+        }
+
+        if self.bottleneck {
+            writeln!(writer, "\nBottleneck diagnosis:")?;
+            let findings = diagnose(machine, &self.thresholds);
+            for finding in &findings {
+                let label = format!("[{}]", finding.severity.label());
+                writeln!(
+                    writer,
+                    "{} {}",
+                    color::paint(&label, finding.severity.color()),
+                    finding.message
+                )?;
+                for detail in &finding.details {
+                    writeln!(writer, "  {}", detail)?;
+                }
+            }
+            writeln!(writer, "{}", summarize(&findings, machine.gpus.len()))?;
+            let hints = machine.detect_bottleneck(&self.thresholds, &findings);
+            if !hints.is_empty() {
+                writeln!(writer, "\nLikely bottlenecks:")?;
+                for hint in &hints {
+                    writeln!(writer, "{}", hint.format())?;
+                }
+            }
+        }
+
+        // last, so that they aren't scrolled out of view by the tables
+        if !machine.warnings.is_empty() {
+            writeln!(writer, "\nWarnings:")?;
+            for warning in &machine.warnings {
+                writeln!(writer, "{}", color::paint(&warning.message, Color::Yellow))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn collect_options(&self) -> CollectOptions {
+        let options = CollectOptions::GPUS_ONLY
+            // the NUMA table flags processes pinned away from their GPU
+            .processes(self.cpu || self.accounting || self.numa)
+            .cpu(self.cpu || self.numa)
+            .io(self.disk || self.net || self.netfs || self.fs);
+        if self.bottleneck {
+            options.union(REQUIRED_STATS)
+        } else {
+            options
+        }
+    }
+}
+
+impl TableRenderer {
+    /// The GPU table, with the delta, history and memory trends of watch mode.
+    fn render_gpu_table(&mut self, machine: &Machine, writer: &mut dyn Write) -> io::Result<()> {
+        let delta = if self.delta {
+            let current = Snapshot::from_machine(machine);
+            let delta = self
+                .previous
+                .as_ref()
+                .map(|previous| Delta::from_snapshots(previous, &current));
+            self.previous = Some(current);
+            match delta {
+                Some(_) => writeln!(writer, "\nGPU Usage (change since last refresh):")?,
+                None => writeln!(writer, "\nGPU Usage (first):")?,
+            }
+            delta
+        } else {
+            writeln!(writer, "\nGPU Usage:")?;
+            None
+        };
+        writeln!(
+            writer,
+            "{}",
+            gpu_table(machine, self.options, self.history.as_ref(), delta.as_ref())
+        )?;
+
+        if let Some(trends) = &mut self.memory_trends {
+            let now = Instant::now();
+            for gpu in &machine.gpus {
+                let trend = trends
+                    .entry(gpu.idx)
+                    .or_insert_with(|| MemoryTrend::new(HISTORY_SIZE));
+                trend.push(now, gpu.memory.0 .0);
+                if let Some(rate) = trend.leak_rate() {
+                    writeln!(
+                        writer,
+                        "Note: possible memory leak on GPU {} (+{:.0}MB/min)",
+                        gpu.idx,
+                        rate / 1024.0 / 1024.0
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `history` adds a sparkline of recent utilization to the verbose view, and
+/// `delta` replaces the temperature, utilization, and memory with their changes.
+pub fn gpu_table(
+    machine: &Machine,
+    options: DisplayOptions,
+    history: Option<&HashMap<u32, UtilizationHistory>>,
+    delta: Option<&Delta>,
+) -> String {
+    // filter a local list so that the other sections still see every GPU
+    let gpus = machine
+        .gpus
+        .iter()
+        .filter(|gpu| options.shows_gpu(gpu))
+        .collect::<Vec<&GPUStats>>();
+    let n_hidden = machine.gpus.len() - gpus.len();
+    let mut table = Table::new(&gpus);
+
+    if let Some(delta) = delta {
+        // the temp, utilization, and memory columns are 2, 4, and 5, after the header row
+        for (row, gpu) in gpus.iter().enumerate() {
+            if let Some(gpu_delta) = delta.gpu(gpu.idx) {
+                table.with(Modify::new(Cell::new(row + 1, 2)).with(gpu_delta.display_temp()));
+                table.with(
+                    Modify::new(Cell::new(row + 1, 4)).with(gpu_delta.display_utilizations()),
+                );
+                table.with(Modify::new(Cell::new(row + 1, 5)).with(gpu_delta.display_memory()));
+            }
+        }
+    }
+
+    // set process col width to be exactly 10 characters
+    // the process col is always the last one
+    let process_col_width = { 10 };
+    let process_col = table.count_columns() - 1;
+    set_col_width(
+        &mut table,
+        process_col,
+        process_col_width,
+        options.fixed_width(),
+    );
+
+    // optional columns come straight after the default ones, in this order:
+    // retired pages, thermal limits
+    let optional_cols = [
+        options.retired_pages,
+        options.thermal_limits || options.verbose,
+    ];
+    // disable from the right so the remaining indices stay valid
+    for (i, shown) in optional_cols.iter().enumerate().rev() {
+        if !shown {
+            table.with(Disable::column(Columns::single(N_DEFAULT_GPU_COLS + i)));
+        }
+    }
+
+    if !options.verbose {
+        // only display the default (and requested optional) columns in non-verbose mode
+        let n_shown = optional_cols.iter().filter(|shown| **shown).count();
+        table.with(Extract::segment(0.., 0..N_DEFAULT_GPU_COLS + n_shown));
+    }
+
+    // set name width to be exactly 15 characters
+    // other columns have fixed width already
+    let name_col_width = { 15 };
+    set_col_width(&mut table, 1, name_col_width, options.fixed_width());
+
+    if let Some(history) = history.filter(|_| options.verbose) {
+        let mut sparklines = Builder::default();
+        sparklines.set_header(["History"]);
+        for gpu in &gpus {
+            let sparkline = history
+                .get(&gpu.idx)
+                .map(UtilizationHistory::sparkline)
+                .unwrap_or_default();
+            sparklines.push_record([sparkline]);
+        }
+        table.with(Concat::horizontal(sparklines.build()));
+    }
+
+    // the NVML version explains missing fields on older drivers, but is
+    // otherwise noise, so only show it in verbose mode
+    let header = if options.verbose {
+        format!(
+            "Driver: {}  CUDA: {}  NVML: {}  Kernel: {}  OS: {}  {}",
+            machine.driver.driver_version,
+            machine.driver.cuda_version,
+            machine.driver.nvml_version,
+            machine.system.kernel,
+            machine.system.os,
+            machine.system.display_uptime()
+        )
+    } else {
+        format!(
+            "Driver Version: {}  CUDA Version: {}",
+            machine.driver.driver_version, machine.driver.cuda_version
+        )
+    };
+    if !options.markdown {
+        table.with(Panel::header(header.clone()));
+    }
+
+    fit_to_terminal(&mut table, options);
+    let table = table_to_string(&mut table, Some(&header), options.markdown);
+    if n_hidden > 0 {
+        format!(
+            "{}\n({} of {} GPUs hidden, below thresholds)",
+            table,
+            n_hidden,
+            machine.gpus.len()
+        )
+    } else {
+        table
+    }
+}
+
+pub fn cpu_table(machine: &Machine, options: DisplayOptions) -> String {
+    let mut table = Table::new(&machine.processes);
+    // the source column (from --pids) is always the last, and the GPU marker
+    // column is only useful when non-GPU processes are shown. The verbose-only
+    // container, working directory, and affinity columns are just before them
+    let source_col = table.count_columns() - 1;
+    let gpu_marker_col = source_col - 1;
+    if !machine.processes.iter().any(|process| process.manual) {
+        table.with(Disable::column(Columns::single(source_col)));
+    }
+    if !machine.all_processes {
+        table.with(Disable::column(Columns::single(gpu_marker_col)));
+    }
+    if !options.verbose {
+        // disable from the right so the remaining indices stay valid
+        for col in (gpu_marker_col - 3..gpu_marker_col).rev() {
+            table.with(Disable::column(Columns::single(col)));
+        }
+    }
+    if options.fixed_width() {
+        let truncate_width = if options.verbose { 75 } else { 20 };
+        table.with(Modify::new(Rows::new(0..)).with(Width::truncate(truncate_width).suffix("...")));
+    }
+
+    let header = format!(
+        "CPU: {}  Util: {}  Temp: {}  {}  shm: {}  Load: {}  {}",
+        machine.cpu_model,
+        cpu_utilization(machine),
+        cpu_temp(machine),
+        machine.cpu.format_header(),
+        shm_usage(machine),
+        load_average(machine),
+        io_stats(machine)
+    );
+    let header = if options.verbose {
+        let header = format!("{}  {}", header, event_rates(machine));
+        match &machine.pressure {
+            Some(pressure) => format!("{}  {}", header, pressure.format_header()),
+            None => header,
+        }
+    } else {
+        header
+    };
+    if !options.markdown {
+        table.with(Panel::header(header.clone()));
+    }
+
+    // set PID col to be min 7 characters
+    // we cannot set with the rest because the truncation messes up the header
+    table.with(Modify::new(Columns::new(0..1)).with(Width::increase(7)));
+
+    // set fixed col widths (except for the PID col)
+    let col_widths = if !options.verbose {
+        vec![6, 5, 8, 20, 14, 10, 6, 9, 22]
+    } else {
+        vec![6, 5, 8, 20, 14, 10, 6, 9, 75, 20, 20, 12]
+    };
+    for (i, width) in col_widths.iter().enumerate() {
+        set_col_width(&mut table, i + 1, *width, options.fixed_width());
+    }
+
+    fit_to_terminal(&mut table, options);
+    table_to_string(&mut table, Some(&header), options.markdown)
+}
+
+pub fn accounting_table(machine: &Machine, options: DisplayOptions) -> String {
+    if machine.accounting.is_empty() {
+        return "None found. Enable accounting mode with `nvidia-smi --accounting-mode=1`."
+            .to_string();
+    }
+    let mut table = Table::new(&machine.accounting);
+    table_to_string(&mut table, None, options.markdown)
+}
+
+pub fn hugepages_table(machine: &Machine, options: DisplayOptions) -> String {
+    let cpu = &machine.cpu;
+    if cpu.hugepages.total == 0 {
+        return format!("No hugepages configured (THP: {})", cpu.thp);
+    }
+    let mut table = Table::new([&cpu.hugepages]);
+    let header = format!("THP: {}", cpu.thp);
+    if !options.markdown {
+        table.with(Panel::header(header.clone()));
+    }
+    table_to_string(&mut table, Some(&header), options.markdown)
+}
+
+pub fn disk_table(machine: &Machine, options: DisplayOptions, all_disks: bool) -> String {
+    let devices = machine
+        .devices
+        .iter()
+        .filter(|device| all_disks || device.physical)
+        .collect::<Vec<_>>();
+    if devices.is_empty() {
+        return "No disks found.".to_string();
+    }
+    let mut table = Table::new(devices);
+    table_to_string(&mut table, None, options.markdown)
+}
+
+pub fn net_table(machine: &Machine, options: DisplayOptions, all_interfaces: bool) -> String {
+    let interfaces = machine
+        .interfaces
+        .iter()
+        .filter(|interface| all_interfaces || interface.physical)
+        .collect::<Vec<_>>();
+    if interfaces.is_empty() {
+        return "No network interfaces found.".to_string();
+    }
+    let mut table = Table::new(interfaces);
+    table_to_string(&mut table, None, options.markdown)
+}
+
+pub fn session_peaks_table(peaks: &[GpuSessionPeak], options: DisplayOptions) -> String {
+    let mut table = Table::new(peaks);
+    table_to_string(&mut table, None, options.markdown)
+}
+
+pub fn netfs_table(machine: &Machine, options: DisplayOptions) -> String {
+    let mut table = Table::new(&machine.netfs);
+    table_to_string(&mut table, None, options.markdown)
+}
+
+pub fn fs_table(machine: &Machine, options: DisplayOptions) -> String {
+    if machine.filesystems.is_empty() {
+        return "No filesystems found.".to_string();
+    }
+    let mut table = Table::new(&machine.filesystems);
+    table_to_string(&mut table, None, options.markdown)
+}
+
+pub fn numa_table(machine: &Machine, options: DisplayOptions) -> String {
+    // a breakdown is pointless with a single node (or no NUMA support)
+    if machine.numa_nodes.len() <= 1 {
+        return "1 NUMA node".to_string();
+    }
+    let mut table = Table::new(&machine.numa_nodes);
+    let mut lines = vec![table_to_string(&mut table, None, options.markdown)];
+    lines.extend(numa_affinity_mismatches(machine));
+    lines.join("\n")
+}
+
+/// Processes pinned to CPUs outside the NUMA node of a GPU they use, which pay
+/// for a cross-socket hop on every host-to-device copy. Unpinned processes are
+/// left to the scheduler and aren't reported.
+fn numa_affinity_mismatches(machine: &Machine) -> Vec<String> {
+    let mut lines = vec![];
+    for process in &machine.processes {
+        if matches!(process.cpu_affinity.as_str(), "all" | "N/A") {
+            continue;
+        }
+        let allowed = parse_cpulist(&process.cpu_affinity);
+        for idx in &process.gpu_indices {
+            let Some(node) = machine
+                .numa_nodes
+                .iter()
+                .find(|node| node.gpus.contains(idx))
+            else {
+                continue;
+            };
+            let node_cpus = parse_cpulist(&node.cpulist);
+            if !allowed.iter().all(|cpu| node_cpus.contains(cpu)) {
+                lines.push(format!(
+                    "Process {} uses GPU {} on NUMA node {} (CPUs {}) but is pinned to CPUs {}, try `numactl --cpunodebind={}`",
+                    process.pid, idx, node.id, node.cpulist, process.cpu_affinity, node.id
+                ));
+            }
+        }
+    }
+    lines
+}
+
+fn cpu_utilization(machine: &Machine) -> String {
+    match machine.cpu_utilization {
+        Some(utilization) => format!("{:.0}%", utilization),
+        None => "N/A".to_string(),
+    }
+}
+
+fn io_stats(machine: &Machine) -> String {
+    match &machine.disk {
+        Some(disk) => disk.format_header(),
+        None => "IO Wait: N/A  Steal: N/A  Idle: N/A".to_string(),
+    }
+}
+
+fn event_rates(machine: &Machine) -> String {
+    match machine.event_rates {
+        Some((ctxt, intr)) => format!(
+            "Ctx Switches: {}/s  Interrupts: {}/s",
+            format_rate(ctxt),
+            format_rate(intr)
+        ),
+        None => "Ctx Switches: N/A  Interrupts: N/A".to_string(),
+    }
+}
+
+/// e.g. 950, 12.3k, 1.2M
+fn format_rate(rate: f32) -> String {
+    if rate >= 1_000_000.0 {
+        format!("{:.1}M", rate / 1_000_000.0)
+    } else if rate >= 1000.0 {
+        format!("{:.1}k", rate / 1000.0)
+    } else {
+        format!("{:.0}", rate)
+    }
+}
+
+fn shm_usage(machine: &Machine) -> String {
+    match &machine.shm {
+        Some(shm) => shm.display_usage(),
+        None => "N/A".to_string(),
+    }
+}
+
+fn cpu_temp(machine: &Machine) -> String {
+    match machine.cpu_temp {
+        Some(temp) => format!("{:.0}°C", temp),
+        None => "N/A".to_string(),
+    }
+}
+
+/// Load averages colored relative to the number of cores:
+/// green below the core count, yellow below twice the core count, red beyond.
+fn load_average(machine: &Machine) -> String {
+    let cores = machine.num_cpus();
+    let (one, five, fifteen) = machine.load_average;
+    [one, five, fifteen]
+        .iter()
+        .map(|load| {
+            let color = if *load < cores {
+                Color::Green
+            } else if *load < 2.0 * cores {
+                Color::Yellow
+            } else {
+                Color::Red
+            };
+            color::paint(&format!("{:.1}", load), color)
+        })
+        .collect::<Vec<String>>()
+        .join(" / ")
+}
+
+/// In wide mode, stretches the table to the terminal width, or shrinks
+/// the widest columns first if it doesn't fit.
+fn fit_to_terminal(table: &mut Table, options: DisplayOptions) {
+    if !options.wide {
+        return;
+    }
+    let (cols, _) = terminal_size();
+    if table.total_width() > cols {
+        table.with(
+            Width::truncate(cols)
+                .suffix("...")
+                .priority::<PriorityMax>(),
+        );
+    } else {
+        table.with(Width::increase(cols));
+    }
+}
+
+/// Returns the terminal size as (columns, rows), defaulting to 80x24.
+pub fn terminal_size() -> (usize, usize) {
+    // SAFETY: winsize is plain data and is filled in by the ioctl
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if result != 0 || size.ws_col == 0 || size.ws_row == 0 {
+        return (80, 24);
+    }
+    (size.ws_col as usize, size.ws_row as usize)
+}
+
+/// Renders the table in either RST or markdown style.
+/// Markdown has no panels, so the header is put above the table instead.
+fn table_to_string(table: &mut Table, header: Option<&str>, markdown: bool) -> String {
+    if markdown {
+        table.with(Style::markdown());
+        match header {
+            Some(header) => format!("{}\n\n{}", header, table),
+            None => table.to_string(),
+        }
+    } else {
+        table.with(Style::re_structured_text());
+        table.to_string()
+    }
+}
+
+/// Sets the column at `col` to be exactly `width` characters wide.
+/// If `truncate` is false, the column is only padded to be at least `width` wide.
+fn set_col_width(table: &mut Table, col: usize, width: usize, truncate: bool) {
+    if truncate {
+        table.with(
+            Modify::new(Columns::new(col..col + 1))
+                .with(Width::truncate(width).suffix("..."))
+                .with(Width::increase(width)),
+        );
+    } else {
+        table.with(Modify::new(Columns::new(col..col + 1)).with(Width::increase(width)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::mock::MockDevice;
+    use crate::ProcessStats;
+
+    // the tables are read by people and scraped by scripts, so guard their exact text
+    #[test]
+    fn tables_match_snapshots() {
+        let mut busy = MockDevice::new(0);
+        busy.utilization = Some((97, 30));
+        busy.memory = Some((40 * 1024 * 1024 * 1024, 80 * 1024 * 1024 * 1024));
+        busy.compute_processes = Some(vec![(1234, 1024)]);
+        let mut machine = Machine::with_gpus(vec![
+            GPUStats::from_device(&busy),
+            GPUStats::from_device(&MockDevice::unsupported(1)),
+        ]);
+        machine.processes = vec![ProcessStats::exited(1234)];
+        let options = DisplayOptions {
+            verbose: false,
+            truncate: true,
+            markdown: false,
+            retired_pages: false,
+            thermal_limits: false,
+            wide: false,
+            min_gpu_util: None,
+            min_mem_util: None,
+        };
+
+        let gpu_snapshot = [
+            "===== ================= ====== =========== ==================== ================= =====",
+            " Driver Version: 535.104.05  CUDA Version: 12.2                                        ",
+            "===== ================= ====== =========== ==================== ================= =====",
+            " Idx   Name              Temp   Power       Utilizations         Memory            Thr ",
+            " 0     NVIDIA A100-...   35°C    60W/400W   GPU  97% VRAM  30%   40.00GB/80.00GB   IDL ",
+            " 1     N/A                0°C     0W/  0W   GPU   0% VRAM   0%    0.00GB/0.00GB    -   ",
+            "===== ================= ====== =========== ==================== ================= =====",
+        ];
+        assert_eq!(
+            gpu_table(&machine, options, None, None),
+            gpu_snapshot.join("\n")
+        );
+        let cpu_snapshot = [
+            "==================== =========== ========== ============= ========================= =================== =============== =========== ============== =========================== ===========",
+            " CPU: AMD EPYC 7763  Util: N/A  Temp: N/A  Num CPUs: 8  RAM: 0M used / 0M (0M available, 0M buff/cache)  Swap: none  shm: N/A  Load: 0.0 / 0.0 / 0.0  IO Wait: N/A  Steal: N/A  Idle: N/A ",
+            "==================== =========== ========== ============= ========================= =================== =============== =========== ============== =========================== ===========",
+            " Pid                  GPUS        State      User          Utilizations              IO (R/W)            Elapsed         AvgSM       PeakVRAM       Command                     Source    ",
+            " 1234                 -           X          -             -                         ?                   -               N/A         N/A            (exited)                    *         ",
+            "==================== =========== ========== ============= ========================= =================== =============== =========== ============== =========================== ===========",
+        ];
+        assert_eq!(cpu_table(&machine, options), cpu_snapshot.join("\n"));
+    }
+}
@@ -0,0 +1,79 @@
+use super::DisplayOptions;
+use crate::Machine;
+
+// the bar never shrinks below this, even on a narrow terminal
+const MIN_BAR_WIDTH: usize = 10;
+
+/// A bar of `width` characters, filled in proportion to `value` (%), e.g. "███░░░░░░░" for 30.
+pub fn render_bar(value: u8, width: usize) -> String {
+    let filled = (value.min(100) as usize * width + 50) / 100;
+    format!("{}{}", "█".repeat(filled), "░".repeat(width - filled))
+}
+
+/// One line per GPU like "GPU 0 ███████░░░  73%", with the bar filling whatever
+/// of `cols` the label and percentage leave.
+pub fn utilization_graph(machine: &Machine, options: DisplayOptions, cols: usize) -> String {
+    let gpus = machine
+        .gpus
+        .iter()
+        .filter(|gpu| options.shows_gpu(gpu))
+        .collect::<Vec<_>>();
+    let label_width = gpus
+        .iter()
+        .map(|gpu| gpu.idx.to_string().len())
+        .max()
+        .unwrap_or(1);
+    // "GPU " + index + " " before the bar, and " 100%" after it
+    let bar_width = cols
+        .saturating_sub(4 + label_width + 1 + 5)
+        .max(MIN_BAR_WIDTH);
+    gpus.iter()
+        .map(|gpu| {
+            let utilization = gpu.utilizations.0.min(100) as u8;
+            format!(
+                "GPU {:<label_width$} {} {:>3}%",
+                gpu.idx,
+                render_bar(utilization, bar_width),
+                utilization,
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::mock::MockDevice;
+    use crate::GPUStats;
+
+    #[test]
+    fn bars_scale_to_the_terminal() {
+        assert_eq!(render_bar(0, 4), "░░░░");
+        assert_eq!(render_bar(30, 10), "███░░░░░░░");
+        assert_eq!(render_bar(100, 4), "████");
+        // rounded to the nearest character
+        assert_eq!(render_bar(74, 4), "███░");
+
+        let mut busy = MockDevice::new(0);
+        busy.utilization = Some((50, 0));
+        let machine = Machine::with_gpus(vec![
+            GPUStats::from_device(&busy),
+            GPUStats::from_device(&MockDevice::new(10)),
+        ]);
+        let options = DisplayOptions {
+            verbose: false,
+            truncate: true,
+            markdown: false,
+            retired_pages: false,
+            thermal_limits: false,
+            wide: false,
+            min_gpu_util: None,
+            min_mem_util: None,
+        };
+        assert_eq!(
+            utilization_graph(&machine, options, 32),
+            "GPU 0  ██████████░░░░░░░░░░  50%\nGPU 10 ░░░░░░░░░░░░░░░░░░░░   0%"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Building blocks for the JSON Schema (draft 7) of the `--json` output, which
+//! each `json_schema` sits next to the `to_json` it describes.
+//!
+//! The schemas are written by hand for the same reason the JSON is: bmon has no
+//! serde (or schemars) dependency. To keep them in step, the tests check the output
+//! of every JSON producer (`--json`, `supported-clocks --json` and baselines)
+//! against its schema with `violations`, so a field added to one but not the other fails.
+
+use crate::json::Json;
+
+pub const DRAFT: &str = "http://json-schema.org/draft-07/schema#";
+
+/// `schema` as a standalone document, with its draft and a title.
+pub fn document(title: &str, schema: Json) -> Json {
+    let mut fields = vec![
+        ("$schema".to_string(), DRAFT.into()),
+        ("title".to_string(), title.into()),
+    ];
+    if let Json::Object(schema) = schema {
+        fields.extend(schema);
+    }
+    Json::Object(fields)
+}
+
+pub fn integer() -> Json {
+    of_type("integer")
+}
+
+/// Integers are numbers too, so this also covers floats that happen to be whole.
+pub fn number() -> Json {
+    of_type("number")
+}
+
+pub fn string() -> Json {
+    of_type("string")
+}
+
+pub fn boolean() -> Json {
+    of_type("boolean")
+}
+
+/// A value that's null when the stat is unavailable, e.g. from an `Option`.
+pub fn nullable(schema: Json) -> Json {
+    match schema {
+        Json::Object(mut fields) => {
+            for (key, value) in &mut fields {
+                if let (true, Json::String(kind)) = (key == "type", &*value) {
+                    *value = vec![kind.clone(), "null".to_string()].into();
+                }
+            }
+            Json::Object(fields)
+        }
+        schema => schema,
+    }
+}
+
+pub fn array(items: Json) -> Json {
+    Json::object(vec![("type", "array".into()), ("items", items)])
+}
+
+/// An object with exactly these properties, all of which are always written.
+pub fn object(properties: Vec<(&str, Json)>) -> Json {
+    let required = properties
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .collect::<Vec<String>>();
+    Json::object(vec![
+        ("type", "object".into()),
+        ("properties", Json::object(properties)),
+        ("required", required.into()),
+        ("additionalProperties", false.into()),
+    ])
+}
+
+/// An `object` schema with more required properties.
+pub fn extend(schema: Json, properties: Vec<(&str, Json)>) -> Json {
+    let Json::Object(mut fields) = schema else {
+        return schema;
+    };
+    for (key, value) in &mut fields {
+        match (key.as_str(), value) {
+            ("properties", Json::Object(existing)) => existing.extend(
+                properties
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.clone())),
+            ),
+            ("required", Json::Array(required)) => {
+                required.extend(properties.iter().map(|(key, _)| (*key).into()))
+            }
+            _ => {}
+        }
+    }
+    Json::Object(fields)
+}
+
+/// An object keyed by something other than a fixed name, e.g. a PID.
+pub fn map(values: Json) -> Json {
+    Json::object(vec![
+        ("type", "object".into()),
+        ("additionalProperties", values),
+    ])
+}
+
+/// An object whose properties vary, e.g. the evidence of a finding.
+pub fn any_object() -> Json {
+    of_type("object")
+}
+
+fn of_type(kind: &str) -> Json {
+    Json::object(vec![("type", kind.into())])
+}
+
+/// Where `value` doesn't match `schema`, for checking the schemas against
+/// the output. Only understands the keywords above.
+#[cfg(test)]
+pub fn violations(schema: &Json, value: &Json, path: &str) -> Vec<String> {
+    let kinds = match &schema["type"] {
+        Json::String(kind) => vec![kind.as_str()],
+        Json::Array(kinds) => kinds.iter().filter_map(Json::as_str).collect(),
+        _ => vec![],
+    };
+    let matches = |kind: &str| {
+        matches!(
+            (kind, value),
+            ("null", Json::Null)
+                | ("boolean", Json::Bool(_))
+                | ("string", Json::String(_))
+                | ("integer", Json::Int(_))
+                | ("number", Json::Int(_) | Json::Float(_))
+                | ("array", Json::Array(_))
+                | ("object", Json::Object(_))
+        )
+    };
+    if !kinds.is_empty() && !kinds.iter().any(|kind| matches(kind)) {
+        return vec![format!(
+            "{}: expected {}, got {}",
+            path,
+            kinds.join(" or "),
+            value
+        )];
+    }
+    let mut violations = vec![];
+    match value {
+        Json::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                let path = format!("{}[{}]", path, i);
+                violations.extend(violations_of(&schema["items"], value, &path));
+            }
+        }
+        Json::Object(fields) => {
+            for key in schema["required"].as_array().unwrap_or_default() {
+                let key = key.as_str().unwrap_or_default();
+                if value.get(key).is_none() {
+                    violations.push(format!("{}: missing {}", path, key));
+                }
+            }
+            for (key, value) in fields {
+                let path = format!("{}.{}", path, key);
+                let property = schema["properties"]
+                    .get(key)
+                    .unwrap_or(&schema["additionalProperties"]);
+                match property {
+                    Json::Bool(false) => violations.push(format!("{}: unexpected", path)),
+                    property => violations.extend(violations_of(property, value, &path)),
+                }
+            }
+        }
+        _ => {}
+    }
+    violations
+}
+
+// a missing subschema allows anything
+#[cfg(test)]
+fn violations_of(schema: &Json, value: &Json, path: &str) -> Vec<String> {
+    if schema.is_null() {
+        return vec![];
+    }
+    violations(schema, value, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_values_against_schemas() {
+        let schema = object(vec![
+            ("idx", integer()),
+            ("temp", nullable(number())),
+            ("names", array(string())),
+            ("memory", map(integer())),
+        ]);
+        let valid = Json::parse(r#"{"idx":0,"temp":null,"names":["a"],"memory":{"12":3}}"#);
+        assert!(violations(&schema, &valid.unwrap(), "gpu").is_empty());
+
+        let invalid = Json::parse(r#"{"idx":0.5,"names":[1],"memory":{"12":"a"},"fan":1}"#);
+        assert_eq!(
+            violations(&schema, &invalid.unwrap(), "gpu"),
+            vec![
+                "gpu: missing temp",
+                "gpu.idx: expected integer, got 0.5",
+                "gpu.names[0]: expected string, got 1",
+                "gpu.memory.12: expected integer, got \"a\"",
+                "gpu.fan: unexpected",
+            ]
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::fs;
+use std::time::Duration;
+
+/// Aggregate CPU time counters from the `cpu` line of /proc/stat, in clock ticks since boot.
+#[derive(Clone, Copy, Default)]
+pub struct CpuTimes {
+    pub user: u64,
+    pub nice: u64,
+    pub system: u64,
+    pub idle: u64,
+    pub iowait: u64,
+    pub irq: u64,
+    pub softirq: u64,
+    pub steal: u64,
+}
+
+impl CpuTimes {
+    // guest time is already counted in user, so it's left out
+    pub fn total(&self) -> u64 {
+        self.user
+            + self.nice
+            + self.system
+            + self.idle
+            + self.iowait
+            + self.irq
+            + self.softirq
+            + self.steal
+    }
+
+    fn busy(&self) -> u64 {
+        self.total() - self.idle - self.iowait
+    }
+}
+
+/// A snapshot of the /proc/stat counters bmon uses.
+#[derive(Clone, Copy, Default)]
+pub struct StatSample {
+    pub cpu: CpuTimes,
+    pub context_switches: u64,
+    pub interrupts: u64,
+}
+
+/// Reads the current /proc/stat counters. They are since boot, so only the
+/// difference between two samples reflects current load.
+pub fn read_proc_stat() -> Option<StatSample> {
+    parse_proc_stat(&fs::read_to_string("/proc/stat").ok()?)
+}
+
+/// Context switches and interrupts per second between two samples taken `window` apart.
+pub fn event_rates(before: &StatSample, after: &StatSample, window: Duration) -> (f32, f32) {
+    let secs = window.as_secs_f32();
+    if secs == 0.0 {
+        return (0.0, 0.0);
+    }
+    (
+        after
+            .context_switches
+            .saturating_sub(before.context_switches) as f32
+            / secs,
+        after.interrupts.saturating_sub(before.interrupts) as f32 / secs,
+    )
+}
+
+/// Percentage of CPU time spent busy (i.e. not idle or waiting on IO) between two samples.
+pub fn cpu_utilization(before: &CpuTimes, after: &CpuTimes) -> f32 {
+    let total = after.total().saturating_sub(before.total());
+    if total == 0 {
+        return 0.0;
+    }
+    let busy = after.busy().saturating_sub(before.busy());
+    100.0 * busy as f32 / total as f32
+}
+
+pub fn parse_proc_stat(stat: &str) -> Option<StatSample> {
+    // the first number on each line is the total, e.g. "intr 114930548 113199788 3 0"
+    let counter = |name: &str| -> Option<u64> {
+        stat.lines()
+            .find(|line| line.split_whitespace().next() == Some(name))?
+            .split_whitespace()
+            .nth(1)?
+            .parse::<u64>()
+            .ok()
+    };
+    Some(StatSample {
+        cpu: parse_cpu_times(stat)?,
+        context_switches: counter("ctxt").unwrap_or(0),
+        interrupts: counter("intr").unwrap_or(0),
+    })
+}
+
+/// Parses the aggregate `cpu` line, e.g. "cpu  4705 356 584 3699176 23060 0 277 0 0 0".
+fn parse_cpu_times(stat: &str) -> Option<CpuTimes> {
+    let line = stat.lines().find(|line| line.starts_with("cpu "))?;
+    let fields = line
+        .split_whitespace()
+        .skip(1)
+        .map(|field| field.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    // older kernels have fewer columns, missing ones are 0
+    let field = |i: usize| fields.get(i).copied().unwrap_or(0);
+    Some(CpuTimes {
+        user: field(0),
+        nice: field(1),
+        system: field(2),
+        idle: field(3),
+        iowait: field(4),
+        irq: field(5),
+        softirq: field(6),
+        steal: field(7),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_aggregate_cpu_line() {
+        let stat = "cpu  4705 356 584 3699176 23060 0 277 12 0 0\n\
+                    cpu0 1393 280 287 924625 6733 0 71 3 0 0\n\
+                    intr 114930548 113199788 3 0 5 263 0 4\n\
+                    ctxt 187371832\n";
+        let times = parse_cpu_times(stat).unwrap();
+        assert_eq!(times.user, 4705);
+        assert_eq!(times.idle, 3699176);
+        assert_eq!(times.iowait, 23060);
+        assert_eq!(times.steal, 12);
+        assert!(parse_cpu_times("intr 1 2 3\n").is_none());
+
+        let sample = parse_proc_stat(stat).unwrap();
+        assert_eq!(sample.context_switches, 187371832);
+        assert_eq!(sample.interrupts, 114930548);
+    }
+
+    #[test]
+    fn event_rates_are_per_second() {
+        let before = StatSample {
+            context_switches: 1_000_000,
+            interrupts: 500_000,
+            ..Default::default()
+        };
+        let after = StatSample {
+            context_switches: 1_025_000,
+            interrupts: 502_500,
+            ..Default::default()
+        };
+        let window = Duration::from_millis(250);
+        assert_eq!(event_rates(&before, &after, window), (100_000.0, 10_000.0));
+    }
+
+    #[test]
+    fn utilization_is_computed_from_the_delta() {
+        let before = CpuTimes {
+            user: 1000,
+            idle: 9000,
+            ..Default::default()
+        };
+        // 87 of the 100 ticks in the window were busy
+        let after = CpuTimes {
+            user: 1080,
+            system: 7,
+            idle: 9010,
+            iowait: 3,
+            ..Default::default()
+        };
+        assert_eq!(cpu_utilization(&before, &after), 87.0);
+        assert_eq!(cpu_utilization(&before, &before), 0.0);
+    }
+}
@@ -0,0 +1,156 @@
+use std::ffi::CStr;
+use std::fs;
+
+use crate::json::Json;
+use crate::schema;
+
+const PERSISTENCE_DAEMON: &str = "nvidia-persistenced";
+
+/// Host details that are useful when triaging a node.
+pub struct SystemInfo {
+    pub kernel: String,
+    pub os: String,
+    pub uptime_secs: u64,
+}
+
+impl SystemInfo {
+    /// e.g. `up 12d 4h`, or `up 3h 12m` for the first day.
+    pub fn display_uptime(&self) -> String {
+        let days = self.uptime_secs / 86400;
+        let hours = self.uptime_secs % 86400 / 3600;
+        let minutes = self.uptime_secs % 3600 / 60;
+        if days > 0 {
+            format!("up {}d {}h", days, hours)
+        } else {
+            format!("up {}h {}m", hours, minutes)
+        }
+    }
+
+    pub fn to_json(&self) -> Json {
+        Json::object(vec![
+            ("kernel", (&self.kernel).into()),
+            ("os", (&self.os).into()),
+            ("uptime_secs", self.uptime_secs.into()),
+        ])
+    }
+
+    pub fn json_schema() -> Json {
+        schema::object(vec![
+            ("kernel", schema::string()),
+            ("os", schema::string()),
+            ("uptime_secs", schema::integer()),
+        ])
+    }
+}
+
+pub fn get_system_info() -> SystemInfo {
+    let os = fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|os_release| parse_pretty_name(&os_release))
+        .unwrap_or_else(|| "N/A".to_string());
+    let uptime_secs = fs::read_to_string("/proc/uptime")
+        .ok()
+        .and_then(|uptime| uptime.split_whitespace().next()?.parse::<f64>().ok())
+        .unwrap_or(0.0) as u64;
+
+    SystemInfo {
+        kernel: kernel_release(),
+        os,
+        uptime_secs,
+    }
+}
+
+/// The kernel release from uname(2), e.g. "5.15.0-91-generic".
+fn kernel_release() -> String {
+    // SAFETY: utsname is plain data and is filled in by uname
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    if unsafe { libc::uname(&mut uts) } != 0 {
+        return "N/A".to_string();
+    }
+    // SAFETY: uname NUL-terminates every field
+    let release = unsafe { CStr::from_ptr(uts.release.as_ptr()) };
+    release.to_string_lossy().into_owned()
+}
+
+/// Whether nvidia-persistenced is running, found by scanning /proc/*/cmdline.
+pub fn persistence_daemon_running() -> bool {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return false;
+    };
+    entries.filter_map(|entry| entry.ok()).any(|entry| {
+        fs::read(entry.path().join("cmdline"))
+            .is_ok_and(|cmdline| is_command(&cmdline, PERSISTENCE_DAEMON))
+    })
+}
+
+/// True if the program of a NUL-separated cmdline is `name`, with or without a path.
+fn is_command(cmdline: &[u8], name: &str) -> bool {
+    let program = cmdline.split(|byte| *byte == 0).next().unwrap_or_default();
+    let program = String::from_utf8_lossy(program);
+    program.rsplit('/').next() == Some(name)
+}
+
+/// Finds e.g. `PRETTY_NAME="Ubuntu 22.04.3 LTS"`, with or without quotes.
+fn parse_pretty_name(os_release: &str) -> Option<String> {
+    let value = os_release
+        .lines()
+        .find_map(|line| line.strip_prefix("PRETTY_NAME="))?;
+    Some(
+        value
+            .trim()
+            .trim_matches(|c| c == '"' || c == '\'')
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pretty_name() {
+        let os_release = "NAME=\"Ubuntu\"\n\
+                          VERSION_ID=\"22.04\"\n\
+                          PRETTY_NAME=\"Ubuntu 22.04.3 LTS\"\n\
+                          ID=ubuntu\n";
+        assert_eq!(
+            parse_pretty_name(os_release),
+            Some("Ubuntu 22.04.3 LTS".to_string())
+        );
+        assert_eq!(
+            parse_pretty_name("PRETTY_NAME=Alpine\n"),
+            Some("Alpine".to_string())
+        );
+        assert_eq!(parse_pretty_name("ID=debian\n"), None);
+    }
+
+    #[test]
+    fn matches_commands() {
+        assert!(is_command(
+            b"/usr/bin/nvidia-persistenced\0--user\0nvpd\0",
+            "nvidia-persistenced"
+        ));
+        assert!(is_command(b"nvidia-persistenced\0", "nvidia-persistenced"));
+        // e.g. someone viewing its man page
+        assert!(!is_command(
+            b"man\0nvidia-persistenced\0",
+            "nvidia-persistenced"
+        ));
+        // kernel threads have an empty cmdline
+        assert!(!is_command(b"", "nvidia-persistenced"));
+    }
+
+    #[test]
+    fn formats_uptime() {
+        let uptime = |uptime_secs| SystemInfo {
+            kernel: String::new(),
+            os: String::new(),
+            uptime_secs,
+        };
+        assert_eq!(
+            uptime(12 * 86400 + 4 * 3600 + 59).display_uptime(),
+            "up 12d 4h"
+        );
+        assert_eq!(uptime(3 * 3600 + 12 * 60).display_uptime(), "up 3h 12m");
+    }
+}
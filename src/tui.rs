@@ -0,0 +1,460 @@
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+use crate::process::{ProcessStats, Signal};
+use crate::render::{cpu_table, gpu_table, terminal_size, DisplayOptions, Renderer};
+use crate::{BmonError, CollectOptions, Machine};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The stats the TUI shows: the process table and its CPU header.
+pub const COLLECT_OPTIONS: CollectOptions = CollectOptions::GPUS_ONLY.processes(true).cpu(true);
+
+#[derive(Clone, Copy)]
+enum SortColumn {
+    Pid,
+    Cpu,
+    Memory,
+    Elapsed,
+}
+
+impl SortColumn {
+    fn next(self) -> Self {
+        match self {
+            SortColumn::Pid => SortColumn::Cpu,
+            SortColumn::Cpu => SortColumn::Memory,
+            SortColumn::Memory => SortColumn::Elapsed,
+            SortColumn::Elapsed => SortColumn::Pid,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            SortColumn::Pid => "PID",
+            SortColumn::Cpu => "CPU",
+            SortColumn::Memory => "RAM",
+            SortColumn::Elapsed => "Elapsed",
+        }
+    }
+
+    /// Sorts by PID ascending, or by usage/runtime descending.
+    fn sort(self, processes: &mut [ProcessStats]) {
+        match self {
+            SortColumn::Pid => processes.sort_by_key(|p| p.pid),
+            SortColumn::Cpu => processes.sort_by(|a, b| b.cpu_pct.total_cmp(&a.cpu_pct)),
+            SortColumn::Memory => processes.sort_by(|a, b| b.mem_pct.total_cmp(&a.mem_pct)),
+            SortColumn::Elapsed => processes.sort_by_key(|p| std::cmp::Reverse(p.elapsed_secs)),
+        }
+    }
+}
+
+enum Key {
+    Quit,
+    ToggleVerbose,
+    CycleSort,
+    Kill(Signal),
+    Confirm,
+    Up,
+    Down,
+    // a left click on a row of the screen, counting from 0 at the top
+    Click(usize),
+    // any other key, which cancels a pending signal
+    Other,
+}
+
+/// Draws the GPU and process tables full-screen, with a status bar at the bottom.
+pub struct TuiRenderer {
+    options: DisplayOptions,
+    sort: SortColumn,
+    selected: usize,
+    message: String,
+    // the (pid, signal) waiting for the user to confirm with y
+    pending_signal: Option<(u32, Signal)>,
+    // the screen row each process was last drawn on, None if it was scrolled out of view
+    process_rows: Vec<Option<usize>>,
+}
+
+impl Renderer for TuiRenderer {
+    fn render(&mut self, machine: &Machine, writer: &mut dyn Write) -> io::Result<()> {
+        let (cols, rows) = terminal_size();
+
+        let mut lines = gpu_table(machine, self.options, None, None)
+            .lines()
+            .map(String::from)
+            .collect::<Vec<String>>();
+        lines.push(String::new());
+
+        let process_lines = cpu_table(machine, self.options)
+            .lines()
+            .map(String::from)
+            .collect::<Vec<String>>();
+        let offset = lines.len();
+        self.process_rows = find_process_rows(&process_lines, &machine.processes)
+            .into_iter()
+            .map(|row| row.map(|row| offset + row))
+            .collect();
+        lines.extend(process_lines);
+        lines.extend(machine.warnings.iter().map(|w| format!("Warning: {}", w)));
+
+        // leave the last row for the status bar
+        lines.truncate(rows.saturating_sub(1));
+        for row in &mut self.process_rows {
+            *row = row.filter(|row| *row < lines.len());
+        }
+        if let Some(Some(row)) = self.process_rows.get(self.selected) {
+            lines[*row] = format!("\x1b[7m{}\x1b[0m", lines[*row]);
+        }
+
+        let status = format!(
+            " {}  refresh {:.1}s  sort: {}  {}  [q]uit [v]erbose [s]ort [k]ill [K] SIGKILL ↑↓/click select",
+            current_time(),
+            REFRESH_INTERVAL.as_secs_f32(),
+            self.sort.name(),
+            self.message
+        );
+        let status = format!("\x1b[7m{:<width$}\x1b[0m", status, width = cols);
+
+        // move to the top left and clear the screen
+        write!(writer, "\x1b[H\x1b[2J")?;
+        for line in lines {
+            write!(writer, "{}\r\n", line)?;
+        }
+        write!(writer, "\x1b[{};1H{}", rows, status)?;
+        writer.flush()
+    }
+
+    fn collect_options(&self) -> CollectOptions {
+        COLLECT_OPTIONS
+    }
+}
+
+impl TuiRenderer {
+    fn handle_key(&mut self, key: &Key, machine: &Machine) {
+        let n_processes = machine.processes.len();
+        if let Some((pid, signal)) = self.pending_signal.take() {
+            self.message = match key {
+                // by pid, as the selection may have moved with a refresh since
+                Key::Confirm => match machine.processes.iter().find(|p| p.pid == pid) {
+                    Some(process) => match process.send_signal(signal) {
+                        Ok(()) => format!("sent {} to {}", signal.name(), pid),
+                        Err(e) => e.to_string(),
+                    },
+                    None => format!("process {} has exited", pid),
+                },
+                _ => format!("not sending {} to {}", signal.name(), pid),
+            };
+            return;
+        }
+        match key {
+            Key::ToggleVerbose => self.options.verbose = !self.options.verbose,
+            Key::CycleSort => self.sort = self.sort.next(),
+            Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Down => {
+                if self.selected + 1 < n_processes {
+                    self.selected += 1;
+                }
+            }
+            Key::Kill(signal) => {
+                if let Some(process) = machine.processes.get(self.selected) {
+                    self.pending_signal = Some((process.pid, *signal));
+                    self.message = format!("send {} to {}? [y/N]", signal.name(), process.pid);
+                }
+            }
+            Key::Click(row) => {
+                if let Some(i) = self.process_rows.iter().position(|r| *r == Some(*row)) {
+                    self.selected = i;
+                }
+            }
+            Key::Confirm | Key::Other | Key::Quit => {}
+        }
+    }
+}
+
+/// Runs the TUI until the user quits, refreshing every second with `collect`, which
+/// should collect `COLLECT_OPTIONS`. A failed refresh ends it, with the terminal
+/// restored before the error is returned so that it can be printed.
+pub fn run(
+    collect: impl Fn() -> Result<Machine, BmonError>,
+    options: DisplayOptions,
+) -> Result<(), BmonError> {
+    let _terminal = RawTerminal::enter()?;
+    let mut renderer = TuiRenderer {
+        options,
+        sort: SortColumn::Pid,
+        selected: 0,
+        message: String::new(),
+        pending_signal: None,
+        process_rows: vec![],
+    };
+
+    let mut machine = collect()?;
+    let mut last_refresh = Instant::now();
+    loop {
+        renderer.sort.sort(&mut machine.processes);
+        renderer.selected = renderer
+            .selected
+            .min(machine.processes.len().saturating_sub(1));
+        renderer.render(&machine, &mut io::stdout().lock())?;
+
+        let timeout = REFRESH_INTERVAL.saturating_sub(last_refresh.elapsed());
+        for key in read_keys(timeout)? {
+            if let Key::Quit = key {
+                return Ok(());
+            }
+            renderer.handle_key(&key, &machine);
+        }
+
+        if last_refresh.elapsed() >= REFRESH_INTERVAL {
+            machine = collect()?;
+            last_refresh = Instant::now();
+        }
+    }
+}
+
+/// The line of `lines` (a rendered `cpu_table`) that each of `processes` starts on,
+/// found by its PID in the first column so that it doesn't depend on the table's
+/// style or on how many lines the header and each row take up.
+fn find_process_rows(lines: &[String], processes: &[ProcessStats]) -> Vec<Option<usize>> {
+    let mut next = 0;
+    processes
+        .iter()
+        .map(|process| {
+            let pid = process.pid.to_string();
+            // the rows are in the same order as the processes
+            let row = (next..lines.len()).find(|i| {
+                let first_cell = lines[*i]
+                    .split(|c: char| c == '|' || c.is_whitespace())
+                    .find(|cell| !cell.is_empty());
+                first_cell == Some(pid.as_str())
+            })?;
+            next = row + 1;
+            Some(row)
+        })
+        .collect()
+}
+
+/// Waits up to `timeout` for key presses or mouse events.
+fn read_keys(timeout: Duration) -> io::Result<Vec<Key>> {
+    let mut fds = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: fds is a valid pollfd for the duration of the call
+    let ready = unsafe { libc::poll(&mut fds, 1, timeout.as_millis() as libc::c_int) };
+    if ready <= 0 {
+        return Ok(vec![]);
+    }
+
+    // big enough for a burst of scroll wheel events
+    let mut buf = [0u8; 256];
+    let n = io::stdin().lock().read(&mut buf)?;
+    Ok(parse_keys(&buf[..n]))
+}
+
+/// Splits terminal input into keys, e.g. a scroll of the mouse wheel can arrive
+/// as several events in one read.
+fn parse_keys(mut input: &[u8]) -> Vec<Key> {
+    let mut keys = vec![];
+    while !input.is_empty() {
+        // an escape sequence ends with a byte in @ to ~, e.g. the A of \x1b[A
+        let len = if input.starts_with(b"\x1b[") {
+            input[2..]
+                .iter()
+                .position(|b| (0x40..=0x7e).contains(b))
+                .map_or(input.len(), |end| end + 3)
+        } else {
+            1
+        };
+        let (sequence, rest) = input.split_at(len);
+        input = rest;
+        let key = match sequence {
+            // Ctrl-C arrives as a byte because the terminal is in raw mode
+            b"q" | [3] => Key::Quit,
+            b"v" => Key::ToggleVerbose,
+            b"s" => Key::CycleSort,
+            b"k" => Key::Kill(Signal::Term),
+            b"K" => Key::Kill(Signal::Kill),
+            b"y" | b"Y" => Key::Confirm,
+            b"\x1b[A" => Key::Up,
+            b"\x1b[B" => Key::Down,
+            [0x1b, b'[', b'<', ..] => match parse_mouse(&sequence[3..]) {
+                Some(key) => key,
+                None => continue,
+            },
+            _ => Key::Other,
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+/// Parses an SGR mouse event without its \x1b[< prefix, e.g. `0;12;5M` for a left
+/// click at column 12 of row 5. Releases and other buttons are ignored.
+fn parse_mouse(event: &[u8]) -> Option<Key> {
+    let event = std::str::from_utf8(event).ok()?;
+    let fields = event.strip_suffix('M')?;
+    let mut fields = fields.split(';').map(|field| field.parse::<usize>().ok());
+    let (button, _col, row) = (fields.next()??, fields.next()??, fields.next()??);
+    match button {
+        0 => Some(Key::Click(row.checked_sub(1)?)),
+        64 => Some(Key::Up),
+        65 => Some(Key::Down),
+        _ => None,
+    }
+}
+
+/// Puts the terminal into raw mode on the alternate screen, restoring it on drop.
+struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        // SAFETY: termios is plain data and is fully initialised by tcgetattr
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // switch to the alternate screen, hide the cursor, and report mouse
+        // clicks and the wheel as SGR sequences
+        print!("\x1b[?1049h\x1b[?25l\x1b[?1000h\x1b[?1006h");
+        io::stdout().flush()?;
+        Ok(Self { original })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[?1006l\x1b[?1000l\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        // SAFETY: original was filled in by tcgetattr
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Local time as HH:MM:SS.
+fn current_time() -> String {
+    // SAFETY: tm is plain data and is filled in by localtime_r
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        libc::localtime_r(&now, &mut tm);
+    }
+    format!("{:02}:{:02}:{:02}", tm.tm_hour, tm.tm_min, tm.tm_sec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    fn options() -> DisplayOptions {
+        DisplayOptions {
+            verbose: false,
+            truncate: true,
+            markdown: false,
+            retired_pages: false,
+            thermal_limits: false,
+            wide: false,
+            min_gpu_util: None,
+            min_mem_util: None,
+        }
+    }
+
+    fn renderer() -> TuiRenderer {
+        TuiRenderer {
+            options: options(),
+            sort: SortColumn::Pid,
+            selected: 0,
+            message: String::new(),
+            pending_signal: None,
+            process_rows: vec![],
+        }
+    }
+
+    #[test]
+    fn finds_process_rows_in_any_table_style() {
+        let mut machine = Machine::with_gpus(vec![]);
+        machine.processes = vec![ProcessStats::exited(42), ProcessStats::exited(7)];
+        for markdown in [false, true] {
+            let options = DisplayOptions {
+                markdown,
+                ..options()
+            };
+            let lines = cpu_table(&machine, options)
+                .lines()
+                .map(String::from)
+                .collect::<Vec<String>>();
+            let rows = find_process_rows(&lines, &machine.processes);
+            for (process, row) in machine.processes.iter().zip(rows) {
+                let row = row.unwrap();
+                assert!(lines[row].contains(&process.pid.to_string()));
+                assert!(lines[row].contains("(exited)"));
+            }
+        }
+    }
+
+    #[test]
+    fn parses_keys_and_mouse_events() {
+        let keys = parse_keys(b"q\x1b[A\x1b[<0;12;5M\x1b[<0;12;5m\x1b[<64;3;3M\x1b[<65;3;3Mx");
+        let keys = keys
+            .iter()
+            .map(|key| match key {
+                Key::Quit => "quit".to_string(),
+                Key::Up => "up".to_string(),
+                Key::Down => "down".to_string(),
+                Key::Click(row) => format!("click {}", row),
+                Key::Other => "other".to_string(),
+                _ => "unexpected".to_string(),
+            })
+            .collect::<Vec<String>>();
+        // the release of the click is ignored
+        assert_eq!(keys, ["quit", "up", "click 4", "up", "down", "other"]);
+    }
+
+    #[test]
+    fn clicks_select_the_process_on_that_row() {
+        let mut machine = Machine::with_gpus(vec![]);
+        machine.processes = vec![ProcessStats::exited(42), ProcessStats::exited(7)];
+        let mut renderer = renderer();
+        renderer.render(&machine, &mut vec![]).unwrap();
+        let row = renderer.process_rows[1].unwrap();
+
+        renderer.handle_key(&Key::Click(row), &machine);
+        assert_eq!(renderer.selected, 1);
+        // the header isn't a process
+        renderer.handle_key(&Key::Click(0), &machine);
+        assert_eq!(renderer.selected, 1);
+    }
+
+    #[test]
+    fn signals_are_only_sent_once_confirmed() {
+        let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+        let mut machine = Machine::with_gpus(vec![]);
+        machine.processes = vec![ProcessStats::exited(child.id())];
+        let mut renderer = renderer();
+
+        renderer.handle_key(&Key::Kill(Signal::Kill), &machine);
+        assert_eq!(
+            renderer.message,
+            format!("send SIGKILL to {}? [y/N]", child.id())
+        );
+        renderer.handle_key(&Key::Other, &machine);
+        assert_eq!(
+            renderer.message,
+            format!("not sending SIGKILL to {}", child.id())
+        );
+        assert!(child.try_wait().unwrap().is_none());
+
+        renderer.handle_key(&Key::Kill(Signal::Kill), &machine);
+        renderer.handle_key(&Key::Confirm, &machine);
+        assert_eq!(renderer.message, format!("sent SIGKILL to {}", child.id()));
+        assert!(!child.wait().unwrap().success());
+    }
+}
@@ -0,0 +1,109 @@
+use std::iter::Sum;
+use std::ops::Add;
+
+use crate::json::Json;
+
+/// An amount of memory, e.g. of a GPU or held by a process.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bytes(pub u64);
+
+/// Power draw or a power limit, as NVML reports them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Milliwatts(pub u32);
+
+impl Bytes {
+    pub const GIB: Bytes = Bytes(1024 * 1024 * 1024);
+
+    /// From the KiB that /proc/meminfo and friends count in.
+    pub fn from_kib(kib: u64) -> Self {
+        Bytes(kib * 1024)
+    }
+
+    pub fn as_gib(self) -> f64 {
+        self.0 as f64 / Self::GIB.0 as f64
+    }
+
+    /// e.g. "79.50GB", for GPU memory (which is counted in GiB, like
+    /// `nvidia-smi` does), so that every GPU memory column lines up.
+    pub fn gib(self) -> String {
+        format!("{:.2}GB", self.as_gib())
+    }
+
+    /// Compactly like `free -h`, e.g. 503G, 3.2G, 512M.
+    pub fn human(self) -> String {
+        let gib = self.as_gib();
+        if gib >= 10.0 {
+            format!("{:.0}G", gib)
+        } else if gib >= 1.0 {
+            format!("{:.1}G", gib)
+        } else {
+            format!("{:.0}M", gib * 1024.0)
+        }
+    }
+}
+
+impl Milliwatts {
+    pub fn as_watts(self) -> f32 {
+        self.0 as f32 / 1000.0
+    }
+}
+
+impl Add for Bytes {
+    type Output = Bytes;
+
+    fn add(self, other: Bytes) -> Bytes {
+        Bytes(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sum for Bytes {
+    fn sum<I: Iterator<Item = Bytes>>(iter: I) -> Bytes {
+        iter.fold(Bytes(0), Add::add)
+    }
+}
+
+impl Add for Milliwatts {
+    type Output = Milliwatts;
+
+    fn add(self, other: Milliwatts) -> Milliwatts {
+        Milliwatts(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sum for Milliwatts {
+    fn sum<I: Iterator<Item = Milliwatts>>(iter: I) -> Milliwatts {
+        iter.fold(Milliwatts(0), Add::add)
+    }
+}
+
+// written as the plain number, under a key that names the unit (e.g. "memory_used_bytes")
+impl From<Bytes> for Json {
+    fn from(bytes: Bytes) -> Self {
+        bytes.0.into()
+    }
+}
+
+impl From<Milliwatts> for Json {
+    fn from(milliwatts: Milliwatts) -> Self {
+        milliwatts.0.into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_units() {
+        assert_eq!(Bytes(80 * Bytes::GIB.0).gib(), "80.00GB");
+        assert_eq!(Bytes(3 * Bytes::GIB.0 / 2).gib(), "1.50GB");
+        assert_eq!(Bytes::from_kib(503 * 1024 * 1024).human(), "503G");
+        assert_eq!(Bytes(3 * Bytes::GIB.0 / 2).human(), "1.5G");
+        assert_eq!(Bytes::from_kib(512 * 1024).human(), "512M");
+        assert_eq!(Milliwatts(399_500).as_watts(), 399.5);
+        assert_eq!(
+            [Bytes(1), Bytes(u64::MAX)].into_iter().sum::<Bytes>(),
+            Bytes(u64::MAX)
+        );
+    }
+}